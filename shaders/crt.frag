@@ -0,0 +1,25 @@
+#version 330 core
+// Barrel distortion with a soft vignette, like a curved CRT tube.
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_source;
+
+const vec2 CURVATURE = vec2(6.0, 5.0);
+
+vec2 curve(vec2 uv) {
+    uv = uv * 2.0 - 1.0;
+    vec2 offset = abs(uv.yx) / CURVATURE;
+    uv = uv + uv * offset * offset;
+    return uv * 0.5 + 0.5;
+}
+
+void main() {
+    vec2 uv = curve(v_uv);
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec3 color = texture(u_source, uv).rgb;
+    float vignette = 16.0 * uv.x * uv.y * (1.0 - uv.x) * (1.0 - uv.y);
+    frag_color = vec4(color * pow(vignette, 0.15), 1.0);
+}
@@ -0,0 +1,24 @@
+#version 330 core
+// Phosphor persistence: lit pixels fade out over a few frames instead of
+// vanishing, with a little glow bleeding into their neighbours.
+// Put it first in the chain so it works on emulated pixels.
+#pragma scale source
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_source;
+uniform sampler2D u_prev;
+uniform vec2 u_source_size;
+
+const float DECAY = 0.82;
+const float GLOW = 0.12;
+
+void main() {
+    vec2 texel = 1.0 / u_source_size;
+    vec3 current = texture(u_source, v_uv).rgb;
+    vec3 glow = texture(u_source, v_uv + vec2(texel.x, 0.0)).rgb
+        + texture(u_source, v_uv - vec2(texel.x, 0.0)).rgb
+        + texture(u_source, v_uv + vec2(0.0, texel.y)).rgb
+        + texture(u_source, v_uv - vec2(0.0, texel.y)).rgb;
+    vec3 previous = texture(u_prev, v_uv).rgb * DECAY;
+    frag_color = vec4(max(current + glow * GLOW * 0.25, previous), 1.0);
+}
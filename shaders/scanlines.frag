@@ -0,0 +1,15 @@
+#version 330 core
+// Darkens the gap between emulated rows. Use after the screen has been scaled up.
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_source;
+uniform vec2 u_native_size;
+
+const float STRENGTH = 0.45;
+
+void main() {
+    vec3 color = texture(u_source, v_uv).rgb;
+    float row = fract(v_uv.y * u_native_size.y);
+    float beam = sin(row * 3.14159265);
+    frag_color = vec4(color * mix(1.0 - STRENGTH, 1.0, beam), 1.0);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+// Integer prescale followed by bilinear filtering: crisp pixels without the
+// uneven widths nearest-neighbour gives at non-integer scales.
+#pragma filter linear
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_source;
+uniform vec2 u_source_size;
+uniform vec2 u_output_size;
+
+void main() {
+    vec2 texel = v_uv * u_source_size;
+    float scale = max(floor(min(u_output_size.x / u_source_size.x, u_output_size.y / u_source_size.y)), 1.0);
+    vec2 region_range = vec2(0.5 - 0.5 / scale);
+    vec2 center_dist = fract(texel) - 0.5;
+    vec2 f = (center_dist - clamp(center_dist, -region_range, region_range)) * scale + 0.5;
+    frag_color = vec4(texture(u_source, (floor(texel) + f) / u_source_size).rgb, 1.0);
+}
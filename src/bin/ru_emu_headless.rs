@@ -0,0 +1,22 @@
+use std::{ env, process };
+
+use ru_emu_lib::cli::{ self, Command };
+use ru_emu_lib::logging;
+
+fn main() {
+    let cli = match cli::parse_headless_args(env::args().skip(1).collect()) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err.message, err.usage);
+            process::exit(cli::EXIT_USAGE);
+        },
+    };
+    logging::set_level(cli.log_level);
+
+    match cli.command {
+        Command::Headless(options) => process::exit(cli::run_headless(&options)),
+        Command::Help(usage) => println!("{}", usage),
+        Command::Version => println!("ru_emu_headless {}", env!("CARGO_PKG_VERSION")),
+        _ => unreachable!("parse_headless_args only returns headless commands"),
+    }
+}
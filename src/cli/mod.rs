@@ -0,0 +1,748 @@
+//! Command-line parsing for the `ru_emu_lib` and `ru_emu_headless` binaries,
+//! and the commands that don't need a window.
+//!
+//! ```text
+//! ru_emu_lib [run] [rom] [options]
+//! ru_emu_lib disasm <rom> [--start <hex addr>] [--count <n>]
+//! ru_emu_lib info <rom>
+//! ru_emu_lib headless <rom> [options]
+//! ```
+//!
+//! Nothing here exits the process, errors carry the exit status to use.
+
+use std::{ fs, str::FromStr };
+
+use crate::config::{ Config, rom_settings::{ self, RomStore } };
+use crate::debugger::{ Breakpoint, Watchpoint };
+use crate::emulators::{ self, palette, Emulator, SystemInfo };
+use crate::filters::{ persistence::PersistenceMode, upscale::Upscaler };
+use crate::headless::{ self, HeadlessOptions, StopCondition };
+use crate::logging::LogLevel;
+
+pub use crate::headless::{ EXIT_OK, EXIT_USAGE, EXIT_IO };
+
+// Emulated frames per second, the same as `frontend::pacing::FRAME_RATE`
+const FRAMES_PER_SECOND: u32 = 60;
+// Instructions `disasm` prints without --count
+const DEFAULT_DISASM_COUNT: u32 = 32;
+
+pub const MAIN_USAGE: &str = "Usage: ru_emu_lib [command] [rom file] [options]
+
+Commands:
+    run        Play the ROM in a window, or pick one in the ROM browser (the default)
+    disasm     Print the disassembly of the ROM
+    info       Print the ROM's system, hash and settings
+    headless   Run without a window, for scripts and CI
+
+Options for every command:
+    --system <name>        Core to use, picked from the file extension by default
+    --config <file>        Config file (default ~/.config/ru_emu_lib/config.toml)
+    --log-level <level>    error, warn, info or debug (default info)
+    -h, --help             This help, or the options of a command after its name
+    --version              Print the version
+    --list-displays        Print the displays and their modes, for --display and --display-mode
+
+Exit status: 0 ok, 1 program failed, 2 usage error, 3 I/O error";
+
+pub const RUN_USAGE: &str = "Usage: ru_emu_lib run [rom file] [options]
+
+Without a ROM file the window opens in the ROM browser.
+
+Options:
+    --rom-dir <dir>            Directory listed by the ROM browser
+    --system <name>            Core to use, picked from the file extension by default
+    --scale <n>                Initial window scale
+    --scale-mode <mode>        nearest, integer or aspect
+    --fullscreen               Start fullscreen, Alt+Enter toggles it
+    --fullscreen-mode <mode>   borderless (the desktop resolution) or exclusive
+    --display <n>              Display to open the window on, see --list-displays
+    --display-mode <WxH[@hz]>  Resolution for exclusive fullscreen, the closest one is used
+    --keypad                   Show the clickable on-screen keypad
+    --clock <hz>               Instructions per second, rounded to whole ticks per frame
+    --ticks-per-frame <n>      Emulator ticks per frame
+    --quirks <preset>          Interpreter quirks preset, e.g. cosmac or schip
+    --palette <colours>        A preset (mono, amber, green, lcd, high-contrast, colorblind)
+                               or colours as #bg,#fg,.. with optional alpha
+    --seed <n>                 Seed for the core's random numbers
+    --paused                   Start paused, F10 steps one instruction (also --debug)
+    --break <0xaddr|pattern>   Breakpoint at an address or on an opcode pattern like DXYN,
+                               can be given more than once
+    --watch <r|w|c>:<range>    Pause after a read, write or change of memory, e.g. w:0x200-0x3ff
+                               or c:0x2a4=0x00 with a value, can be given more than once
+    --renderer <canvas|gl>     Renderer to use
+    --shaders <a,b,..>         GL post-processing passes, implies --renderer gl
+    --shader-dir <dir>         Where to look for shader names
+    --filter <name>            Upscaler: none, scale2x, scale3x, eagle or xbr
+    --persistence <mode>       Flicker reduction: off, max2 or fade:<frames>
+    --vsync                    Wait for vsync instead of sleeping between frames
+    --no-audio                 Don't open an audio device
+    --print-config             Print the settings in effect and exit
+    --config <file>            Config file to read
+    --log-level <level>        error, warn, info or debug
+
+--quirks, --palette, --clock and --ticks-per-frame are remembered for the ROM.";
+
+pub const DISASM_USAGE: &str = "Usage: ru_emu_lib disasm <rom file> [options]
+
+Options:
+    --system <name>        Core to use, picked from the file extension by default
+    --start <hex addr>     First address, the entry point by default
+    --count <n>            Instructions to print (default 32)
+    --log-level <level>    error, warn, info or debug";
+
+pub const INFO_USAGE: &str = "Usage: ru_emu_lib info <rom file> [options]
+
+Options:
+    --system <name>        Core to use, picked from the file extension by default
+    --config <file>        Config file to read
+    --log-level <level>    error, warn, info or debug";
+
+pub const HEADLESS_USAGE: &str = "Usage: ru_emu_headless <rom file> [options]
+
+Options:
+    --system <name>          Core to use, picked from the file extension by default
+    --config <file>          Config file for the system's clock, quirks and palette,
+                             the default config file is not read
+    --frames <n>             Frames to run, or the limit for --until-* (default 60)
+    --ticks-per-frame <n>    Emulator ticks per frame (default: the system's, 10 for chip8)
+    --clock <hz>             Instructions per second instead of --ticks-per-frame
+    --seed <n>               Seed for the core's random numbers
+    --input <file>           Input script, one '<frame> <press|release> <hex key>' per line
+    --until-pc <hex addr>    Stop once the program counter reaches the address
+    --until-exit             Stop once the program reports an exit code
+    --screenshot <file.png>  Write the final frame as a PNG
+    --scale <n>              Screenshot scale (default 1)
+    --filter <name>          Screenshot upscaler: none, scale2x, scale3x, eagle or xbr
+    --persistence <mode>     Flicker reduction for the screenshot: off, max2 or fade:<frames>
+    --palette <colours>      A palette preset or colours as #bg,#fg,..
+    --state <file.json|->    Write the registers and run result as JSON
+    --log-level <level>      error, warn, info or debug
+
+The ROM can also be given with --f <file>.
+
+Exit status: 0 ok, 1 program failed, 2 usage error, 3 I/O error, 4 --until-* not reached";
+
+pub struct Cli {
+    // None reads the default config file
+    pub config_path: Option<String>,
+    pub log_level: LogLevel,
+    pub system: Option<String>,
+    pub command: Command,
+}
+
+pub enum Command {
+    Run(RunOptions),
+    Disasm(DisasmOptions),
+    Info(InfoOptions),
+    Headless(HeadlessOptions),
+    // Usage text to print
+    Help(&'static str),
+    Version,
+    ListDisplays,
+}
+
+/**
+ * Options for the windowed frontend. Unset ones come from the config file.
+ */
+#[derive(Default)]
+pub struct RunOptions {
+    // Empty opens the ROM browser
+    pub rom_path: String,
+    pub rom_dir: Option<String>,
+    pub scale: Option<u32>,
+    pub scale_mode: Option<String>,
+    pub fullscreen: bool,
+    pub fullscreen_mode: Option<String>,
+    pub display: Option<u32>,
+    pub display_mode: Option<String>,
+    pub keypad: bool,
+    pub ticks_per_frame: Option<u32>,
+    pub quirks: Option<String>,
+    pub palette: Vec<String>,
+    pub seed: Option<u64>,
+    pub paused: bool,
+    pub breakpoints: Vec<String>,
+    pub watchpoints: Vec<String>,
+    pub renderer: Option<String>,
+    pub shaders: Option<Vec<String>>,
+    pub shader_dir: Option<String>,
+    pub filter: Option<String>,
+    pub persistence: Option<String>,
+    pub vsync: bool,
+    pub no_audio: bool,
+    pub print_config: bool,
+}
+
+pub struct DisasmOptions {
+    pub rom_path: String,
+    // None starts at the program counter after loading
+    pub start: Option<u64>,
+    pub count: u32,
+}
+
+pub struct InfoOptions {
+    pub rom_path: String,
+}
+
+/**
+ * A bad command line, printed as the message followed by `usage`.
+ */
+pub struct UsageError {
+    pub message: String,
+    pub usage: &'static str,
+}
+
+pub enum CliError {
+    Usage(String),
+    Io(String),
+}
+
+impl CliError {
+    pub fn status_code(self: &Self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Io(_) => EXIT_IO,
+        }
+    }
+
+    pub fn message(self: &Self) -> &str {
+        match self {
+            CliError::Usage(msg) | CliError::Io(msg) => msg,
+        }
+    }
+}
+
+/**
+ * Parses the `ru_emu_lib` arguments, without the program name. A ROM path
+ * with no command in front of it means `run`.
+ */
+pub fn parse_args(args: Vec<String>) -> Result<Cli, UsageError> {
+    let (command, usage, args) = match args.first().map(|a| a.as_str()) {
+        Some("run") => ("run", RUN_USAGE, &args[1..]),
+        Some("disasm") => ("disasm", DISASM_USAGE, &args[1..]),
+        Some("info") => ("info", INFO_USAGE, &args[1..]),
+        Some("headless") => ("headless", HEADLESS_USAGE, &args[1..]),
+        _ => ("run", MAIN_USAGE, &args[..]),
+    };
+    parse_command(command, usage, args.to_vec())
+}
+
+/**
+ * Parses the `ru_emu_headless` arguments, the same as `ru_emu_lib headless`.
+ */
+pub fn parse_headless_args(args: Vec<String>) -> Result<Cli, UsageError> {
+    parse_command("headless", HEADLESS_USAGE, args)
+}
+
+fn parse_command(command: &str, usage: &'static str, args: Vec<String>) -> Result<Cli, UsageError> {
+    let mut cli = Cli {
+        config_path: None,
+        log_level: LogLevel::Info,
+        system: None,
+        command: Command::Help(usage),
+    };
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        return Ok(cli);
+    }
+    if args.iter().any(|a| a == "--version") {
+        cli.command = Command::Version;
+        return Ok(cli);
+    }
+    if command == "run" && args.iter().any(|a| a == "--list-displays") {
+        cli.command = Command::ListDisplays;
+        return Ok(cli);
+    }
+
+    let mut args = Args { args: args.into_iter() };
+    let parsed = match command {
+        "disasm" => parse_disasm(&mut cli, &mut args).map(Command::Disasm),
+        "info" => parse_info(&mut cli, &mut args).map(Command::Info),
+        "headless" => parse_headless(&mut cli, &mut args).map(Command::Headless),
+        _ => parse_run(&mut cli, &mut args).map(Command::Run),
+    };
+    cli.command = parsed.map_err(|message| UsageError { message, usage })?;
+    if let Some(name) = &cli.system {
+        if emulators::find_system_by_name(name).is_none() {
+            return Err(UsageError { message: unknown_system(name), usage });
+        }
+    }
+    Ok(cli)
+}
+
+struct Args {
+    args: std::vec::IntoIter<String>,
+}
+
+impl Args {
+    fn next(self: &mut Self) -> Option<String> {
+        self.args.next()
+    }
+
+    fn value(self: &mut Self, arg: &str) -> Result<String, String> {
+        self.args.next().ok_or(format!("Missing value for {}", arg))
+    }
+}
+
+/**
+ * Handles the options every command takes, false if `arg` isn't one of them.
+ */
+fn parse_global(cli: &mut Cli, arg: &str, args: &mut Args) -> Result<bool, String> {
+    match arg {
+        "--config" => cli.config_path = Some(args.value(arg)?),
+        "--log-level" => cli.log_level = args.value(arg)?.parse()?,
+        "--system" => cli.system = Some(args.value(arg)?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/**
+ * Takes a positional argument as the ROM path, only one is allowed.
+ */
+fn set_rom_path(rom_path: &mut String, arg: String) -> Result<(), String> {
+    if arg.starts_with('-') {
+        return Err(format!("Unknown option: {}", arg));
+    }
+    if !rom_path.is_empty() {
+        return Err(format!("Unexpected argument: {}", arg));
+    }
+    *rom_path = arg;
+    Ok(())
+}
+
+fn require_rom_path(rom_path: &str) -> Result<(), String> {
+    if rom_path.is_empty() {
+        return Err(String::from("Missing the ROM file"));
+    }
+    Ok(())
+}
+
+fn parse_run(cli: &mut Cli, args: &mut Args) -> Result<RunOptions, String> {
+    let mut options = RunOptions::default();
+    while let Some(arg) = args.next() {
+        if parse_global(cli, &arg, args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--f" => options.rom_path = args.value(&arg)?,
+            "--rom-dir" => options.rom_dir = Some(args.value(&arg)?),
+            "--scale" => options.scale = Some(parse_positive(&arg, &args.value(&arg)?)?),
+            "--scale-mode" => options.scale_mode = Some(args.value(&arg)?),
+            "--fullscreen" => options.fullscreen = true,
+            "--fullscreen-mode" => options.fullscreen_mode = Some(args.value(&arg)?),
+            "--display" => options.display = Some(parse_number(&arg, &args.value(&arg)?)?),
+            "--display-mode" => options.display_mode = Some(args.value(&arg)?),
+            "--keypad" => options.keypad = true,
+            "--clock" => options.ticks_per_frame = Some(clock_to_ticks(&arg, &args.value(&arg)?)?),
+            "--ticks-per-frame" => options.ticks_per_frame = Some(parse_positive(&arg, &args.value(&arg)?)?),
+            "--quirks" => options.quirks = Some(args.value(&arg)?),
+            "--palette" => options.palette = parse_palette(&args.value(&arg)?)?,
+            "--seed" => options.seed = Some(parse_number(&arg, &args.value(&arg)?)?),
+            "--paused" | "--debug" => options.paused = true,
+            "--break" => {
+                let breakpoint = args.value(&arg)?;
+                breakpoint.parse::<Breakpoint>()?;
+                options.breakpoints.push(breakpoint);
+            },
+            "--watch" => {
+                let watchpoint = args.value(&arg)?;
+                watchpoint.parse::<Watchpoint>()?;
+                options.watchpoints.push(watchpoint);
+            },
+            "--renderer" => {
+                let renderer = args.value(&arg)?;
+                if renderer != "canvas" && renderer != "gl" {
+                    return Err(format!("Unknown renderer '{}', expected canvas or gl", renderer));
+                }
+                options.renderer = Some(renderer);
+            },
+            "--shaders" => {
+                options.shaders = Some(args.value(&arg)?.split(',').map(String::from).collect());
+            },
+            "--shader-dir" => options.shader_dir = Some(args.value(&arg)?),
+            "--filter" => {
+                let filter = args.value(&arg)?;
+                filter.parse::<Upscaler>()?;
+                options.filter = Some(filter);
+            },
+            "--persistence" => {
+                let persistence = args.value(&arg)?;
+                persistence.parse::<PersistenceMode>()?;
+                options.persistence = Some(persistence);
+            },
+            "--vsync" => options.vsync = true,
+            "--no-audio" => options.no_audio = true,
+            "--print-config" => options.print_config = true,
+            _ => set_rom_path(&mut options.rom_path, arg)?,
+        }
+    }
+    Ok(options)
+}
+
+fn parse_disasm(cli: &mut Cli, args: &mut Args) -> Result<DisasmOptions, String> {
+    let mut options = DisasmOptions {
+        rom_path: String::new(),
+        start: None,
+        count: DEFAULT_DISASM_COUNT,
+    };
+    while let Some(arg) = args.next() {
+        if parse_global(cli, &arg, args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--f" => options.rom_path = args.value(&arg)?,
+            "--start" => options.start = Some(parse_hex(&arg, &args.value(&arg)?)?),
+            "--count" => options.count = parse_number(&arg, &args.value(&arg)?)?,
+            _ => set_rom_path(&mut options.rom_path, arg)?,
+        }
+    }
+    require_rom_path(&options.rom_path)?;
+    Ok(options)
+}
+
+fn parse_info(cli: &mut Cli, args: &mut Args) -> Result<InfoOptions, String> {
+    let mut options = InfoOptions { rom_path: String::new() };
+    while let Some(arg) = args.next() {
+        if parse_global(cli, &arg, args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--f" => options.rom_path = args.value(&arg)?,
+            _ => set_rom_path(&mut options.rom_path, arg)?,
+        }
+    }
+    require_rom_path(&options.rom_path)?;
+    Ok(options)
+}
+
+fn parse_headless(cli: &mut Cli, args: &mut Args) -> Result<HeadlessOptions, String> {
+    let mut options = HeadlessOptions::default();
+    while let Some(arg) = args.next() {
+        if parse_global(cli, &arg, args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--f" => options.rom_path = args.value(&arg)?,
+            "--frames" => options.frames = parse_number(&arg, &args.value(&arg)?)?,
+            "--ticks-per-frame" => options.ticks_per_frame = Some(parse_positive(&arg, &args.value(&arg)?)?),
+            "--clock" => options.ticks_per_frame = Some(clock_to_ticks(&arg, &args.value(&arg)?)?),
+            "--seed" => options.seed = Some(parse_number(&arg, &args.value(&arg)?)?),
+            "--input" => options.input_script = Some(args.value(&arg)?),
+            "--until-pc" => options.until = Some(StopCondition::Pc(parse_hex(&arg, &args.value(&arg)?)?)),
+            "--until-exit" => options.until = Some(StopCondition::Exit),
+            "--screenshot" => options.screenshot_path = Some(args.value(&arg)?),
+            "--scale" => options.screenshot_scale = parse_positive(&arg, &args.value(&arg)?)?,
+            "--filter" => options.upscaler = args.value(&arg)?.parse()?,
+            "--persistence" => options.persistence = args.value(&arg)?.parse()?,
+            "--palette" => options.palette = palette::parse_palette(&parse_palette(&args.value(&arg)?)?)?,
+            "--state" => options.state_path = Some(args.value(&arg)?),
+            _ => set_rom_path(&mut options.rom_path, arg)?,
+        }
+    }
+    require_rom_path(&options.rom_path)?;
+    options.system = cli.system.clone();
+    options.config_path = cli.config_path.clone();
+    Ok(options)
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("Invalid number for {}: {}", arg, value))
+}
+
+fn parse_positive(arg: &str, value: &str) -> Result<u32, String> {
+    match parse_number::<u32>(arg, value)? {
+        0 => Err(format!("{} must be at least 1", arg)),
+        n => Ok(n),
+    }
+}
+
+fn parse_hex(arg: &str, value: &str) -> Result<u64, String> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u64::from_str_radix(digits, 16)
+        .map_err(|_| format!("Invalid address for {}: {}", arg, value))
+}
+
+/**
+ * Instructions per second to ticks per frame, at least one.
+ */
+fn clock_to_ticks(arg: &str, value: &str) -> Result<u32, String> {
+    let hz = parse_positive(arg, value)?;
+    Ok(((hz + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND).max(1))
+}
+
+/**
+ * Comma separated, kept as given so it can be saved for the ROM.
+ */
+fn parse_palette(value: &str) -> Result<Vec<String>, String> {
+    let entries: Vec<String> = value.split(',').map(|c| c.trim().to_string()).collect();
+    palette::parse_palette(&entries)?;
+    Ok(entries)
+}
+
+fn unknown_system(name: &str) -> String {
+    let names: Vec<&str> = emulators::SYSTEMS.iter().map(|s| s.name).collect();
+    format!("Unknown system '{}', expected one of: {}", name, names.join(", "))
+}
+
+/**
+ * The system asked for by name, or the one registered for the file's
+ * extension, or the first one.
+ */
+pub fn select_system(name: Option<&str>, rom_path: &str) -> Result<&'static SystemInfo, CliError> {
+    match name {
+        Some(name) => emulators::find_system_by_name(name).ok_or_else(|| CliError::Usage(unknown_system(name))),
+        None => match emulators::find_system_for_file(rom_path) {
+            Some(system) => Ok(system),
+            None => {
+                let system = &emulators::SYSTEMS[0];
+                crate::log_warn!("[System] No system registered for {}, defaulting to {}", rom_path, system.name);
+                Ok(system)
+            },
+        },
+    }
+}
+
+fn read_rom(rom_path: &str) -> Result<Vec<u8>, CliError> {
+    fs::read(rom_path).map_err(|e| CliError::Io(format!("Could not read {}: {}", rom_path, e)))
+}
+
+/**
+ * A started `system` core with the ROM bytes from `rom_path` loaded.
+ */
+fn start_rom(system: &SystemInfo, rom_path: &str, data: &[u8]) -> Result<Box<dyn Emulator>, CliError> {
+    let mut emu = (system.create)();
+    emu.load_data(data).map_err(|e| CliError::Io(format!("Could not load {}: {}", rom_path, e)))?;
+    emu.start();
+    Ok(emu)
+}
+
+/**
+ * `count` instructions from `start`, one `<addr>  <instruction>` per line.
+ */
+pub fn disassemble_rom(options: &DisasmOptions, system: &SystemInfo) -> Result<String, CliError> {
+    let data = read_rom(&options.rom_path)?;
+    let emu = start_rom(system, &options.rom_path, &data)?;
+
+    let mut addr = options.start.unwrap_or_else(|| emu.get_program_counter());
+    let mut out = String::new();
+    for _ in 0..options.count {
+        let (text, len) = match emu.disassemble(addr) {
+            Some(instr) => instr,
+            None if out.is_empty() => {
+                return Err(CliError::Usage(format!("Nothing to disassemble at 0x{:x} with the {} core", addr, system.name)));
+            },
+            None => break,
+        };
+        out.push_str(&format!("0x{:04x}  {}\n", addr, text));
+        addr += len.max(1);
+    }
+    Ok(out)
+}
+
+/**
+ * What `info` prints: the system, size and hash of the ROM and the settings
+ * it would run with.
+ */
+pub fn rom_info(options: &InfoOptions, system: &SystemInfo, config: &Config, rom_store: &RomStore) -> Result<String, CliError> {
+    let data = read_rom(&options.rom_path)?;
+    let hash = rom_settings::sha1_hex(&data);
+    let emu = start_rom(system, &options.rom_path, &data)?;
+    let res = emu.get_screen_resolution();
+
+    let mut settings = config.system(system);
+    let saved = rom_store.get(&hash);
+    if let Some(saved) = saved {
+        saved.apply_to(&mut settings);
+    }
+
+    let mut out = String::new();
+    out.push_str(&format!("File:            {}\n", options.rom_path));
+    out.push_str(&format!("System:          {} ({})\n", system.name, system.description));
+    out.push_str(&format!("Size:            {} bytes\n", data.len()));
+    out.push_str(&format!("SHA-1:           {}\n", hash));
+    out.push_str(&format!("Screen:          {}x{}\n", res.width, res.height));
+    out.push_str(&format!("Entry point:     0x{:x}\n", emu.get_program_counter()));
+    out.push_str(&format!("Ticks per frame: {}\n", settings.ticks_per_frame.unwrap_or(system.ticks_per_frame)));
+    if let Some(quirks) = &settings.quirks {
+        out.push_str(&format!("Quirks:          {}\n", quirks));
+    }
+    if !settings.palette.is_empty() {
+        out.push_str(&format!("Palette:         {}\n", settings.palette.join(", ")));
+    }
+    out.push_str(&format!("Saved settings:  {}\n", if saved.is_some() { "yes" } else { "no" }));
+    Ok(out)
+}
+
+/**
+ * Runs the `headless` command and returns the exit status.
+ */
+pub fn run_headless(options: &HeadlessOptions) -> i32 {
+    match headless::run(options) {
+        Ok(result) => {
+            crate::log_info!("[Headless] {} frames, exit code {:?}", result.frames_run, result.exit_code);
+            result.status_code()
+        },
+        Err(err) => {
+            crate::log_error!("[Headless] {}", err.message());
+            err.status_code()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn parse(line: &[&str]) -> Cli {
+        match parse_args(args(line)) {
+            Ok(cli) => cli,
+            Err(err) => panic!("{:?}: {}", line, err.message),
+        }
+    }
+
+    fn parse_err(line: &[&str]) -> UsageError {
+        match parse_args(args(line)) {
+            Ok(_) => panic!("{:?} parsed", line),
+            Err(err) => err,
+        }
+    }
+
+    fn headless(line: &[&str]) -> HeadlessOptions {
+        match parse_headless_args(args(line)).map(|cli| cli.command) {
+            Ok(Command::Headless(options)) => options,
+            Ok(_) => panic!("{:?} is not a headless command", line),
+            Err(err) => panic!("{:?}: {}", line, err.message),
+        }
+    }
+
+    #[test]
+    fn run_is_the_default_command() {
+        let cli = parse(&["game.ch8"]);
+        let Command::Run(options) = cli.command else { panic!("not run") };
+        assert_eq!(options.rom_path, "game.ch8");
+        assert_eq!(cli.log_level, LogLevel::Info);
+        assert!(cli.system.is_none() && cli.config_path.is_none());
+
+        // No ROM opens the browser
+        let Command::Run(options) = parse(&[]).command else { panic!("not run") };
+        assert!(options.rom_path.is_empty());
+    }
+
+    #[test]
+    fn run_options() {
+        let cli = parse(&[
+            "run", "--system", "chip8", "--scale", "3", "--log-level", "debug", "--config", "my.toml",
+            "--clock", "700", "--palette", "amber", "--break", "0x2a4", "--break", "DXYN", "--shaders", "crt,scanlines",
+            "--paused", "--f", "game.ch8",
+        ]);
+        assert_eq!(cli.system.as_deref(), Some("chip8"));
+        assert_eq!(cli.log_level, LogLevel::Debug);
+        assert_eq!(cli.config_path.as_deref(), Some("my.toml"));
+        let Command::Run(options) = cli.command else { panic!("not run") };
+        assert_eq!(options.rom_path, "game.ch8");
+        assert_eq!(options.scale, Some(3));
+        assert_eq!(options.ticks_per_frame, Some(12));
+        assert_eq!(options.palette, ["amber"]);
+        assert_eq!(options.breakpoints, ["0x2a4", "DXYN"]);
+        assert_eq!(options.shaders, Some(vec![String::from("crt"), String::from("scanlines")]));
+        assert!(options.paused);
+    }
+
+    #[test]
+    fn subcommands() {
+        let cli = parse(&["disasm", "game.ch8", "--start", "0X2A0", "--count", "4", "--system", "chip8"]);
+        assert_eq!(cli.system.as_deref(), Some("chip8"));
+        let Command::Disasm(options) = cli.command else { panic!("not disasm") };
+        assert_eq!((options.rom_path.as_str(), options.start, options.count), ("game.ch8", Some(0x2a0), 4));
+        let Command::Disasm(options) = parse(&["disasm", "game.ch8"]).command else { panic!("not disasm") };
+        assert_eq!((options.start, options.count), (None, DEFAULT_DISASM_COUNT));
+
+        let Command::Info(options) = parse(&["info", "game.ch8"]).command else { panic!("not info") };
+        assert_eq!(options.rom_path, "game.ch8");
+        let Command::Headless(options) = parse(&["headless", "game.ch8"]).command else { panic!("not headless") };
+        assert_eq!(options.rom_path, "game.ch8");
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert!(matches!(parse(&["--help"]).command, Command::Help(MAIN_USAGE)));
+        assert!(matches!(parse(&["disasm", "-h"]).command, Command::Help(DISASM_USAGE)));
+        assert!(matches!(parse(&["headless", "game.ch8", "--help"]).command, Command::Help(HEADLESS_USAGE)));
+        assert!(matches!(parse(&["info", "--version"]).command, Command::Version));
+        assert!(matches!(parse(&["--list-displays"]).command, Command::ListDisplays));
+    }
+
+    #[test]
+    fn headless_options() {
+        let options = headless(&[
+            "game.ch8", "--frames", "120", "--clock", "700", "--until-pc", "0x2A4", "--seed", "7",
+            "--config", "ci.toml", "--system", "chip8", "--filter", "eagle", "--persistence", "fade:3",
+            "--palette", "#000,#fff", "--scale", "4", "--state", "-",
+        ]);
+        assert_eq!(options.rom_path, "game.ch8");
+        assert_eq!(options.system.as_deref(), Some("chip8"));
+        assert_eq!(options.config_path.as_deref(), Some("ci.toml"));
+        assert_eq!((options.frames, options.ticks_per_frame, options.seed), (120, Some(12), Some(7)));
+        assert!(matches!(options.until, Some(StopCondition::Pc(0x2a4))));
+        assert_eq!(options.upscaler, Upscaler::Eagle);
+        assert_eq!(options.persistence, PersistenceMode::Fade(3));
+        assert_eq!(options.palette, [[0, 0, 0, 0xff], [0xff, 0xff, 0xff, 0xff]]);
+        assert_eq!(options.screenshot_scale, 4);
+        assert_eq!(options.state_path.as_deref(), Some("-"));
+
+        // The system picks the clock unless it's given
+        let options = headless(&["--f", "game.ch8", "--until-exit"]);
+        assert_eq!(options.rom_path, "game.ch8");
+        assert_eq!(options.ticks_per_frame, None);
+        assert!(matches!(options.until, Some(StopCondition::Exit)));
+        assert_eq!(headless(&["game.ch8", "--ticks-per-frame", "3"]).ticks_per_frame, Some(3));
+    }
+
+    #[test]
+    fn errors() {
+        let cases: [(&[&str], &str); 12] = [
+            (&["run", "--bogus"], "Unknown option: --bogus"),
+            (&["a.ch8", "b.ch8"], "Unexpected argument: b.ch8"),
+            (&["--scale"], "Missing value for --scale"),
+            (&["--scale", "0"], "--scale must be at least 1"),
+            (&["--scale", "x"], "Invalid number for --scale: x"),
+            (&["--system", "nes"], "Unknown system 'nes', expected one of: chip8, riscv"),
+            (&["--log-level", "loud"], "Unknown log level 'loud', expected error, warn, info or debug"),
+            (&["--renderer", "vulkan"], "Unknown renderer 'vulkan', expected canvas or gl"),
+            (&["disasm"], "Missing the ROM file"),
+            (&["disasm", "a.ch8", "--start", "0xZZ"], "Invalid address for --start: 0xZZ"),
+            (&["info"], "Missing the ROM file"),
+            (&["headless", "a.ch8", "--clock", "0"], "--clock must be at least 1"),
+        ];
+        for (line, message) in cases {
+            assert_eq!(parse_err(line).message, message, "{:?}", line);
+        }
+        assert_eq!(parse_err(&["disasm"]).usage, DISASM_USAGE);
+        assert_eq!(parse_err(&["a.ch8", "--bogus"]).usage, MAIN_USAGE);
+    }
+
+    #[test]
+    fn clock_rounding() {
+        assert_eq!(clock_to_ticks("--clock", "600"), Ok(10));
+        assert_eq!(clock_to_ticks("--clock", "629"), Ok(10));
+        assert_eq!(clock_to_ticks("--clock", "630"), Ok(11));
+        // At least one tick
+        assert_eq!(clock_to_ticks("--clock", "1"), Ok(1));
+        assert_eq!(clock_to_ticks("--clock", "0"), Err(String::from("--clock must be at least 1")));
+        assert!(clock_to_ticks("--clock", "-60").is_err());
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("--start", "0x2a4"), Ok(0x2a4));
+        assert_eq!(parse_hex("--start", "0X2A4"), Ok(0x2a4));
+        assert_eq!(parse_hex("--start", "2a4"), Ok(0x2a4));
+        assert_eq!(parse_hex("--start", "0x0x2a4"), Err(String::from("Invalid address for --start: 0x0x2a4")));
+        assert!(parse_hex("--start", "").is_err());
+        assert!(parse_hex("--start", "0x").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Keys changed on the controls screen, in `~/.config/ru_emu_lib/bindings.toml`.
+//! They are applied over the config file, keys saved for a ROM still win.
+
+use std::{ collections::BTreeMap, fs, path::PathBuf };
+
+use serde::{ Deserialize, Serialize };
+
+use super::{ config_dir, Config };
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemBindings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_preset: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, u8>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct BindingsFile {
+    hotkeys: BTreeMap<String, String>,
+    // Keyed by `SystemInfo::name`
+    systems: BTreeMap<String, SystemBindings>,
+}
+
+pub struct Bindings {
+    // None keeps the changes in memory only
+    path: Option<PathBuf>,
+    file: BindingsFile,
+}
+
+impl Bindings {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("bindings.toml"))
+    }
+
+    /**
+     * Reads the bindings at `path`, a missing file changes nothing.
+     */
+    pub fn load(path: Option<PathBuf>) -> Result<Bindings, String> {
+        let file = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(text)) => toml::from_str::<BindingsFile>(&text)
+                .map_err(|e| format!("{}: {}", path.as_ref().unwrap().display(), e))?,
+            _ => BindingsFile::default(),
+        };
+        Ok(Bindings { path, file })
+    }
+
+    /**
+     * Overrides the hotkeys and the system keys of `config` with the saved ones.
+     */
+    pub fn apply_to(self: &Self, config: &mut Config) {
+        config.hotkeys.extend(self.file.hotkeys.clone());
+        for (name, saved) in &self.file.systems {
+            let system = config.systems.entry(name.clone()).or_default();
+            if saved.key_preset.is_some() {
+                system.key_preset = saved.key_preset.clone();
+            }
+            if !saved.keys.is_empty() {
+                system.keys = saved.keys.clone();
+            }
+        }
+    }
+
+    /**
+     * Replaces the saved hotkeys and writes the file back.
+     */
+    pub fn set_hotkeys(self: &mut Self, hotkeys: &BTreeMap<String, String>) -> Result<(), String> {
+        self.file.hotkeys = hotkeys.clone();
+        self.save()
+    }
+
+    /**
+     * Replaces the saved keys of `system` and writes the file back.
+     */
+    pub fn set_system(self: &mut Self, system: &str, bindings: SystemBindings) -> Result<(), String> {
+        self.file.systems.insert(system.to_string(), bindings);
+        self.save()
+    }
+
+    pub fn save(self: &Self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let text = toml::to_string_pretty(&self.file).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,365 @@
+//! User settings, read from `~/.config/ru_emu_lib/config.toml`.
+//!
+//! Everything is optional, missing keys fall back to the defaults below and
+//! to the `SystemInfo` of the running core. Command-line flags are applied on
+//! top by the frontend.
+//!
+//! ```toml
+//! [video]
+//! renderer = "gl"
+//! shaders = ["phosphor", "crt"]
+//!
+//! [systems.chip8]
+//! ticks_per_frame = 12
+//! quirks = "cosmac"
+//! palette = ["#1a1c2c", "#f4f4f4"]
+//!
+//! [hotkeys]
+//! pause = "Pause"
+//!
+//! [systems.chip8.keys]
+//! X = 0x0
+//! Up = 0x5
+//!
+//! # Replace [video] and [audio] settings for one system
+//! [systems.riscv.video]
+//! renderer = "canvas"
+//!
+//! [systems.chip8.audio]
+//! tone_hz = 660
+//!
+//! # One table per controller, in the order they were plugged in
+//! [[systems.chip8.controllers]]
+//! dpup = 0x1
+//! dpdown = 0x4
+//!
+//! [[systems.chip8.controllers]]
+//! dpup = 0xc
+//! dpdown = 0xd
+//! ```
+
+use std::{ collections::BTreeMap, env, fs, path::PathBuf };
+
+use serde::{ Deserialize, Serialize };
+
+use crate::emulators::SystemInfo;
+use crate::log_warn;
+
+pub mod bindings;
+pub mod recent;
+pub mod rom_settings;
+pub mod save_states;
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    pub debugger: DebuggerConfig,
+    pub browser: BrowserConfig,
+    // Action name (see `frontend::actions::HOTKEY_ACTIONS`) to SDL key name,
+    // replacing the default keys of that action
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub hotkeys: BTreeMap<String, String>,
+    // Keyed by `SystemInfo::name`
+    pub systems: BTreeMap<String, SystemConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct VideoConfig {
+    // "canvas" or "gl"
+    pub renderer: String,
+    pub shaders: Vec<String>,
+    pub shader_dir: String,
+    pub scale_mode: String,
+    pub filter: String,
+    pub persistence: String,
+    pub vsync: bool,
+    // Start fullscreen, Alt+Enter toggles it either way
+    pub fullscreen: bool,
+    // "borderless" or "exclusive"
+    pub fullscreen_mode: String,
+    // Index into the displays SDL lists
+    pub display: u32,
+    // "<w>x<h>" or "<w>x<h>@<hz>" for exclusive fullscreen, empty for the desktop mode
+    pub display_mode: String,
+    // The clickable keypad under the screen, canvas renderer only
+    pub show_keypad: bool,
+    // Where the screenshot hotkey saves, relative to the working directory
+    pub screenshot_dir: String,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            renderer: String::from("canvas"),
+            shaders: Vec::new(),
+            shader_dir: String::from("shaders"),
+            scale_mode: String::from("integer"),
+            filter: String::from("none"),
+            persistence: String::from("off"),
+            vsync: false,
+            fullscreen: false,
+            fullscreen_mode: String::from("borderless"),
+            display: 0,
+            display_mode: String::new(),
+            show_keypad: false,
+            screenshot_dir: String::from("screenshots"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    // 0.0 - 1.0
+    pub volume: f32,
+    // Pitch of the beeper
+    pub tone_hz: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            enabled: true,
+            volume: 0.25,
+            tone_hz: 440,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebuggerConfig {
+    pub show_pane: bool,
+    // Set at start, "0x2a4" for an address or an opcode pattern like "DXYN"
+    pub breakpoints: Vec<String>,
+    // Set at start, "w:0x200-0x3ff" or "c:0x2a4=0x00", see `debugger::Watchpoint`
+    pub watchpoints: Vec<String>,
+    // Instructions the step hotkey runs
+    pub step_count: u32,
+    // Colours as "#rrggbb" or "#rrggbbaa"
+    pub window_background: String,
+    pub pane_background: String,
+    pub register_color: String,
+    pub current_instr_color: String,
+    pub next_instr_color: String,
+    // Memory viewer marks, see `frontend::memory_view::MemoryColors`
+    pub memory_pc_color: String,
+    pub memory_pointer_color: String,
+    pub memory_region_color: String,
+    pub memory_written_color: String,
+}
+
+impl Default for DebuggerConfig {
+    fn default() -> Self {
+        DebuggerConfig {
+            show_pane: true,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            step_count: 1,
+            window_background: String::from("#404040"),
+            pane_background: String::from("#020202"),
+            register_color: String::from("#ffff00"),
+            current_instr_color: String::from("#ffff00"),
+            next_instr_color: String::from("#00ff00"),
+            memory_pc_color: String::from("#00ff00"),
+            memory_pointer_color: String::from("#40c0ff"),
+            memory_region_color: String::from("#808080"),
+            memory_written_color: String::from("#ff4040"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrowserConfig {
+    // Listed by the ROM browser, relative to the working directory
+    pub rom_dir: String,
+    // Entries kept in the recent files list
+    pub max_recent: usize,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        BrowserConfig {
+            rom_dir: String::from("roms"),
+            max_recent: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemConfig {
+    // Unset means `SystemInfo::ticks_per_frame`
+    pub ticks_per_frame: Option<u32>,
+    // Core specific, see `EmuTrait::set_quirks`
+    pub quirks: Option<String>,
+    // Background first, or a single preset name, see `emulators::palette`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub palette: Vec<String>,
+    // One of `SystemInfo::key_presets`, used when `keys` is empty
+    pub key_preset: Option<String>,
+    // SDL key name to core key. Empty means the key preset
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, u8>,
+    // Per player, SDL controller button name ("a", "dpup", ...) to core key.
+    // Empty means `SystemInfo::default_buttons`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub controllers: Vec<BTreeMap<String, u8>>,
+    // Replace what they set in `[video]` and `[audio]`, see `Config::resolve_system`
+    pub video: Option<SystemVideoConfig>,
+    pub audio: Option<SystemAudioConfig>,
+}
+
+/**
+ * `[systems.<name>.video]`, anything unset keeps the `[video]` value.
+ */
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemVideoConfig {
+    pub renderer: Option<String>,
+    pub shaders: Option<Vec<String>>,
+    pub scale_mode: Option<String>,
+    pub filter: Option<String>,
+    pub persistence: Option<String>,
+}
+
+/**
+ * `[systems.<name>.audio]`, anything unset keeps the `[audio]` value.
+ */
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemAudioConfig {
+    pub enabled: Option<bool>,
+    pub volume: Option<f32>,
+    pub tone_hz: Option<u32>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("config.toml"))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /**
+     * Reads `path`, or the default path when `None`. A missing default file
+     * is not an error, a missing file that was asked for is.
+     */
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (PathBuf::from(path), true),
+            None => match Config::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(_) if !required => Ok(Config::default()),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn to_toml(self: &Self) -> String {
+        toml::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /**
+     * Settings for `system` with the registry defaults filled in.
+     */
+    pub fn system(self: &Self, system: &SystemInfo) -> SystemConfig {
+        let mut config = self.systems.get(system.name).cloned().unwrap_or_default();
+        config.ticks_per_frame.get_or_insert(system.ticks_per_frame);
+        if config.keys.is_empty() {
+            let preset = match &config.key_preset {
+                Some(name) => system.key_preset(name).or_else(|| {
+                    log_warn!("[Config] Unknown key preset '{}' for {}", name, system.name);
+                    None
+                }),
+                None => None,
+            };
+            let keys = preset.or_else(|| system.key_presets.first().map(|(_, keys)| *keys)).unwrap_or_default();
+            config.keys = keys.iter()
+                .map(|(name, key)| (name.to_string(), *key))
+                .collect();
+        }
+        if config.controllers.is_empty() {
+            config.controllers = system.default_buttons.iter()
+                .map(|buttons| buttons.iter().map(|(name, key)| (name.to_string(), *key)).collect())
+                .collect();
+        }
+        config
+    }
+
+    /**
+     * Applies the `video` and `audio` overrides of `system` to the global
+     * sections and stores its filled in settings, for printing the effective
+     * config. Done once the system is known, before the command-line flags.
+     */
+    pub fn resolve_system(self: &mut Self, system: &SystemInfo) {
+        let config = self.system(system);
+        if let Some(video) = &config.video {
+            if let Some(renderer) = &video.renderer {
+                self.video.renderer = renderer.clone();
+            }
+            if let Some(shaders) = &video.shaders {
+                self.video.shaders = shaders.clone();
+            }
+            if let Some(scale_mode) = &video.scale_mode {
+                self.video.scale_mode = scale_mode.clone();
+            }
+            if let Some(filter) = &video.filter {
+                self.video.filter = filter.clone();
+            }
+            if let Some(persistence) = &video.persistence {
+                self.video.persistence = persistence.clone();
+            }
+        }
+        if let Some(audio) = &config.audio {
+            self.audio.enabled = audio.enabled.unwrap_or(self.audio.enabled);
+            self.audio.volume = audio.volume.unwrap_or(self.audio.volume);
+            self.audio.tone_hz = audio.tone_hz.unwrap_or(self.audio.tone_hz);
+        }
+        self.systems.insert(system.name.to_string(), config);
+    }
+}
+
+/**
+ * `$XDG_CONFIG_HOME/ru_emu_lib`, or `~/.config/ru_emu_lib`.
+ */
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("ru_emu_lib"))
+}
+
+/**
+ * `#rgb`, `#rrggbb` or `#rrggbbaa`, the `#` is optional.
+ */
+pub fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let hex = s.trim().trim_start_matches('#');
+    let err = || format!("Invalid colour '{}', expected #rrggbb or #rrggbbaa", s);
+    let digits: Vec<u8> = hex.chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8).ok_or_else(err))
+        .collect::<Result<_, _>>()?;
+    match digits.len() {
+        3 => Ok([digits[0] * 17, digits[1] * 17, digits[2] * 17, 0xff]),
+        6 | 8 => {
+            let mut color = [0xff; 4];
+            for (i, pair) in digits.chunks(2).enumerate() {
+                color[i] = pair[0] << 4 | pair[1];
+            }
+            Ok(color)
+        },
+        _ => Err(err()),
+    }
+}
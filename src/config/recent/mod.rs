@@ -0,0 +1,70 @@
+//! Recently played ROM files, newest first, in `~/.config/ru_emu_lib/recent.toml`.
+
+use std::{ fs, path::{ Path, PathBuf } };
+
+use serde::{ Deserialize, Serialize };
+
+use super::config_dir;
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct RecentFile {
+    files: Vec<String>,
+}
+
+pub struct RecentFiles {
+    // None keeps the list in memory only
+    path: Option<PathBuf>,
+    max_files: usize,
+    files: Vec<String>,
+}
+
+impl RecentFiles {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("recent.toml"))
+    }
+
+    /**
+     * Reads the list at `path`, a missing file is an empty list.
+     */
+    pub fn load(path: Option<PathBuf>, max_files: usize) -> Result<RecentFiles, String> {
+        let files = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(text)) => toml::from_str::<RecentFile>(&text)
+                .map_err(|e| format!("{}: {}", path.as_ref().unwrap().display(), e))?
+                .files,
+            _ => Vec::new(),
+        };
+        let mut recent = RecentFiles { path, max_files, files };
+        recent.files.truncate(max_files);
+        Ok(recent)
+    }
+
+    pub fn files(self: &Self) -> &[String] {
+        &self.files
+    }
+
+    /**
+     * Moves `file` to the front, as an absolute path, and writes the list back.
+     */
+    pub fn add(self: &mut Self, file: &Path) -> Result<(), String> {
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let file = file.to_string_lossy().into_owned();
+        self.files.retain(|f| *f != file);
+        self.files.insert(0, file);
+        self.files.truncate(self.max_files);
+        self.save()
+    }
+
+    pub fn save(self: &Self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let file = RecentFile { files: self.files.clone() };
+        let text = toml::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,112 @@
+//! Settings remembered per ROM, keyed by the SHA-1 of the ROM file, in
+//! `~/.config/ru_emu_lib/roms.toml`. They sit between the config file and
+//! the command line: anything set here wins over `[systems.<name>]`.
+
+use std::{ collections::BTreeMap, fs, path::PathBuf };
+
+use serde::{ Deserialize, Serialize };
+
+use super::{ config_dir, SystemConfig };
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RomSettings {
+    // File name the ROM was last loaded from, only to make the file readable
+    pub name: String,
+    pub ticks_per_frame: Option<u32>,
+    pub quirks: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub palette: Vec<String>,
+    // Only to show which layout `keys` came from
+    pub key_preset: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, u8>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub controllers: Vec<BTreeMap<String, u8>>,
+    pub save_slot: Option<u8>,
+}
+
+impl RomSettings {
+    /**
+     * Overrides whatever is set here in the system settings.
+     */
+    pub fn apply_to(self: &Self, config: &mut SystemConfig) {
+        if self.ticks_per_frame.is_some() {
+            config.ticks_per_frame = self.ticks_per_frame;
+        }
+        if self.quirks.is_some() {
+            config.quirks = self.quirks.clone();
+        }
+        if !self.palette.is_empty() {
+            config.palette = self.palette.clone();
+        }
+        if !self.keys.is_empty() {
+            config.key_preset = self.key_preset.clone();
+            config.keys = self.keys.clone();
+        }
+        if !self.controllers.is_empty() {
+            config.controllers = self.controllers.clone();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct RomSettingsFile {
+    // SHA-1 in lower case hex
+    roms: BTreeMap<String, RomSettings>,
+}
+
+pub struct RomStore {
+    // None keeps everything in memory only
+    path: Option<PathBuf>,
+    roms: BTreeMap<String, RomSettings>,
+}
+
+impl RomStore {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("roms.toml"))
+    }
+
+    /**
+     * Reads the store at `path`, a missing file is an empty store.
+     */
+    pub fn load(path: Option<PathBuf>) -> Result<RomStore, String> {
+        let roms = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(text)) => toml::from_str::<RomSettingsFile>(&text)
+                .map_err(|e| format!("{}: {}", path.as_ref().unwrap().display(), e))?
+                .roms,
+            _ => BTreeMap::new(),
+        };
+        Ok(RomStore { path, roms })
+    }
+
+    pub fn get(self: &Self, hash: &str) -> Option<&RomSettings> {
+        self.roms.get(hash)
+    }
+
+    /**
+     * Changes the settings for `hash` and writes the store back.
+     */
+    pub fn update<F: FnOnce(&mut RomSettings)>(self: &mut Self, hash: &str, change: F) -> Result<(), String> {
+        change(self.roms.entry(hash.to_string()).or_default());
+        self.save()
+    }
+
+    pub fn save(self: &Self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let file = RomSettingsFile { roms: self.roms.clone() };
+        let text = toml::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
@@ -0,0 +1,26 @@
+//! Save state files, `~/.config/ru_emu_lib/states/<sha1>.<slot>.state`.
+//! What goes in them is up to the core, see `EmuTrait::save_state`.
+
+use std::{ fs, path::PathBuf };
+
+use super::config_dir;
+
+// Slots 0 - 9
+pub const SLOT_COUNT: u8 = 10;
+
+pub fn state_path(hash: &str, slot: u8) -> Option<PathBuf> {
+    Some(config_dir()?.join("states").join(format!("{}.{}.state", hash, slot)))
+}
+
+pub fn save(hash: &str, slot: u8, data: &[u8]) -> Result<(), String> {
+    let path = state_path(hash, slot).ok_or("No config directory for save states")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, data).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+pub fn load(hash: &str, slot: u8) -> Result<Vec<u8>, String> {
+    let path = state_path(hash, slot).ok_or("No config directory for save states")?;
+    fs::read(&path).map_err(|_| format!("No state in slot {}", slot))
+}
@@ -0,0 +1,676 @@
+//! Breakpoints and run control that work with any core through [`EmuTrait`]
+//! and [`CpuInfo`]. The frontend hands its ticks to [`Debugger::run`], which
+//! stops at breakpoints and counts down single steps while paused. Step over
+//! and step out follow `CpuInfo::get_call_depth`, watchpoints check the
+//! accesses from `CpuInfo::take_memory_accesses` after every instruction.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::emulators::{ CpuInfo, EmuTrait, MemoryAccess };
+
+/**
+ * An instruction pattern such as `DXYN` or `FX55`. Hex digits have to match,
+ * any other letter (or `?`) takes any value for its nibble. The pattern is
+ * matched against the low nibbles of `CpuInfo::get_opcode`.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct OpcodePattern {
+    text: String,
+    mask: u64,
+    value: u64,
+}
+
+impl OpcodePattern {
+    pub fn matches(self: &Self, opcode: u64) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl FromStr for OpcodePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim().to_ascii_uppercase();
+        if text.is_empty() || text.len() > 16 || !text.chars().all(|c| c.is_ascii_alphanumeric() || c == '?') {
+            return Err(format!("Invalid opcode pattern '{}', expected hex digits and wildcards like DXYN", s));
+        }
+        let (mut mask, mut value) = (0u64, 0u64);
+        for c in text.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xf;
+                value |= digit as u64;
+            }
+        }
+        if mask == 0 {
+            return Err(format!("Opcode pattern '{}' has no hex digits and would match everything", s));
+        }
+        Ok(OpcodePattern { text, mask, value })
+    }
+}
+
+impl fmt::Display for OpcodePattern {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Breakpoint {
+    // Stops before the instruction at this address runs
+    Address(u64),
+    // Stops before any instruction matching the pattern runs
+    Opcode(OpcodePattern),
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    /**
+     * `0x2a4` for an address, anything else is an opcode pattern.
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16)
+                .map(Breakpoint::Address)
+                .map_err(|_| format!("Invalid breakpoint address '{}'", s)),
+            None => s.parse().map(Breakpoint::Opcode),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(addr) => write!(f, "0x{:X}", addr),
+            Breakpoint::Opcode(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // A write that gives the byte a new value
+    Change,
+}
+
+/**
+ * Stops after an instruction that accessed `start..=end`, and only if the
+ * byte read or written is `value` when that is set.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub start: u64,
+    pub end: u64,
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    pub fn matches(self: &Self, access: &MemoryAccess) -> bool {
+        let (addr, value) = match (self.kind, *access) {
+            (WatchKind::Read, MemoryAccess::Read { addr, value }) => (addr, value),
+            (WatchKind::Write, MemoryAccess::Write { addr, value, .. }) => (addr, value),
+            (WatchKind::Change, MemoryAccess::Write { addr, old, value }) if old != value => (addr, value),
+            _ => return false,
+        };
+        (self.start..=self.end).contains(&addr) && (self.value.is_none() || self.value == Some(value))
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /**
+     * `<r|w|c>:<addr>[-<end>][=<value>]` with hex numbers, e.g. `w:0x200-0x3ff`
+     * or `c:0x2a4=0x00`.
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid watchpoint '{}', expected <r|w|c>:<addr>[-<end>][=<value>] like w:0x200-0x3ff", s);
+        let hex = |text: &str| {
+            let text = text.trim();
+            let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+            u64::from_str_radix(digits, 16).map_err(|_| err())
+        };
+        let (kind, rest) = s.split_once(':').ok_or_else(err)?;
+        let kind = match kind.trim().to_ascii_lowercase().as_str() {
+            "r" | "read" => WatchKind::Read,
+            "w" | "write" => WatchKind::Write,
+            "c" | "change" => WatchKind::Change,
+            _ => return Err(err()),
+        };
+        let (range, value) = match rest.split_once('=') {
+            Some((range, value)) => (range, Some(u8::try_from(hex(value)?).map_err(|_| err())?)),
+            None => (rest, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (hex(start)?, hex(end)?),
+            None => (hex(range)?, hex(range)?),
+        };
+        if end < start {
+            return Err(err());
+        }
+        Ok(Watchpoint { kind, start, end, value })
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => 'r',
+            WatchKind::Write => 'w',
+            WatchKind::Change => 'c',
+        };
+        write!(f, "{}:0x{:X}", kind, self.start)?;
+        if self.end != self.start {
+            write!(f, "-0x{:X}", self.end)?;
+        }
+        if let Some(value) = self.value {
+            write!(f, "=0x{:02X}", value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PauseReason {
+    // Paused from a hotkey or the start options
+    User,
+    // The last of the requested steps ran, or a step over / out finished
+    Step,
+    // Run to address got there
+    Reached(u64),
+    // PC reached an address breakpoint
+    Breakpoint(u64),
+    // The instruction at the address matched the pattern
+    Opcode(OpcodePattern, u64),
+    // The instruction at `pc` made an access the watchpoint was waiting for
+    Watch { watchpoint: Watchpoint, access: MemoryAccess, pc: u64, instr: String },
+}
+
+impl fmt::Display for PauseReason {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PauseReason::User => write!(f, "Paused"),
+            PauseReason::Step => write!(f, "Stepped"),
+            PauseReason::Reached(addr) => write!(f, "Reached 0x{:X}", addr),
+            PauseReason::Breakpoint(addr) => write!(f, "Breakpoint at 0x{:X}", addr),
+            PauseReason::Opcode(pattern, addr) => write!(f, "Breakpoint {} at 0x{:X}", pattern, addr),
+            PauseReason::Watch { watchpoint, access, pc, instr } => {
+                match (watchpoint.kind, access) {
+                    (WatchKind::Change, MemoryAccess::Write { addr, old, value }) => {
+                        write!(f, "Change 0x{:X} 0x{:02X} > 0x{:02X}", addr, old, value)?
+                    },
+                    (_, MemoryAccess::Write { addr, value, .. }) => write!(f, "Write 0x{:X} = 0x{:02X}", addr, value)?,
+                    (_, MemoryAccess::Read { addr, value }) => write!(f, "Read 0x{:X} = 0x{:02X}", addr, value)?,
+                }
+                write!(f, " by 0x{:X} {}", pc, instr)
+            },
+        }
+    }
+}
+
+// Where a step over, step out or run to address stops
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RunTarget {
+    // Once the call depth is back down to this, after at least one instruction
+    CallDepth(usize),
+    // Before the instruction at this address runs
+    Address(u64),
+}
+
+/**
+ * Runs a core until a breakpoint or a pause, and a given number of
+ * instructions at a time while paused.
+ */
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    // None while running
+    paused: Option<PauseReason>,
+    // Set while running towards it, breakpoints on the way still stop
+    target: Option<RunTarget>,
+    // Instructions `run` may still execute while paused
+    steps_left: u32,
+    // Where the last instruction ran from. Breakpoints only fire when the PC
+    // moves onto them, so continuing from one (or a key wait spinning on it)
+    // doesn't stop again straight away
+    last_pc: Option<u64>,
+}
+
+impl Debugger {
+    pub fn new(paused: bool) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            paused: if paused { Some(PauseReason::User) } else { None },
+            target: None,
+            steps_left: 0,
+            last_pc: None,
+        }
+    }
+
+    pub fn is_paused(self: &Self) -> bool {
+        self.paused.is_some()
+    }
+
+    pub fn pause_reason(self: &Self) -> Option<&PauseReason> {
+        self.paused.as_ref()
+    }
+
+    pub fn pause(self: &mut Self) {
+        self.paused = Some(PauseReason::User);
+        self.target = None;
+        self.steps_left = 0;
+    }
+
+    /**
+     * Runs again, starting with the instruction at the PC even if it has a
+     * breakpoint.
+     */
+    pub fn resume<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E) {
+        self.paused = None;
+        self.target = None;
+        self.steps_left = 0;
+        self.last_pc = Some(emu.get_program_counter());
+    }
+
+    fn run_until<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E, target: RunTarget) {
+        self.resume(emu);
+        self.target = Some(target);
+    }
+
+    /**
+     * Pauses if running and lets the next `count` instructions run.
+     */
+    pub fn step<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E, count: u32) {
+        if self.paused.is_none() {
+            self.paused = Some(PauseReason::User);
+        }
+        self.target = None;
+        self.steps_left = count;
+        self.last_pc = Some(emu.get_program_counter());
+    }
+
+    /**
+     * Runs the next instruction, and a whole subroutine if it is a call.
+     * A single step on cores that don't track calls.
+     */
+    pub fn step_over<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E) {
+        match emu.get_call_depth() {
+            Some(depth) => self.run_until(emu, RunTarget::CallDepth(depth)),
+            None => self.step(emu, 1),
+        }
+    }
+
+    /**
+     * Runs until the current subroutine returns.
+     */
+    pub fn step_out<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E) -> Result<(), String> {
+        match emu.get_call_depth() {
+            Some(0) => Err(String::from("Not in a subroutine")),
+            Some(depth) => {
+                self.run_until(emu, RunTarget::CallDepth(depth - 1));
+                Ok(())
+            },
+            None => Err(String::from("This core doesn't track subroutine calls")),
+        }
+    }
+
+    /**
+     * Runs until the PC gets to `addr`, which is passed if it is the current one.
+     */
+    pub fn run_to<E: CpuInfo + ?Sized>(self: &mut Self, emu: &E, addr: u64) {
+        self.run_until(emu, RunTarget::Address(addr));
+    }
+
+    pub fn breakpoints(self: &Self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(self: &mut Self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn has_breakpoint(self: &Self, addr: u64) -> bool {
+        self.breakpoints.contains(&Breakpoint::Address(addr))
+    }
+
+    /**
+     * Sets or clears the breakpoint at `addr`, returns whether it is now set.
+     */
+    pub fn toggle_breakpoint(self: &mut Self, addr: u64) -> bool {
+        let breakpoint = Breakpoint::Address(addr);
+        match self.breakpoints.iter().position(|b| *b == breakpoint) {
+            Some(i) => {
+                self.breakpoints.remove(i);
+                false
+            },
+            None => {
+                self.breakpoints.push(breakpoint);
+                true
+            },
+        }
+    }
+
+    pub fn watchpoints(self: &Self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(self: &mut Self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    fn check_accesses<E: CpuInfo + ?Sized>(self: &Self, emu: &mut E, pc: u64) -> Option<PauseReason> {
+        let accesses = emu.take_memory_accesses();
+        let (watchpoint, access) = accesses.iter()
+            .find_map(|access| Some((self.watchpoints.iter().find(|w| w.matches(access))?, *access)))?;
+        // The opcode that ran, memory at `pc` may have been overwritten by it
+        Some(PauseReason::Watch { watchpoint: watchpoint.clone(), access, pc, instr: emu.get_current_instr() })
+    }
+
+    fn check<E: CpuInfo + ?Sized>(self: &Self, emu: &E, pc: u64) -> Option<PauseReason> {
+        let opcode = emu.get_opcode(pc);
+        self.breakpoints.iter().find_map(|breakpoint| match breakpoint {
+            Breakpoint::Address(addr) if *addr == pc => Some(PauseReason::Breakpoint(pc)),
+            Breakpoint::Opcode(pattern) if opcode.is_some_and(|op| pattern.matches(op)) => {
+                Some(PauseReason::Opcode(pattern.clone(), pc))
+            },
+            _ => None,
+        })
+        .or(match self.target {
+            Some(RunTarget::Address(addr)) if addr == pc => Some(PauseReason::Reached(pc)),
+            _ => None,
+        })
+    }
+
+    fn stop(self: &mut Self, reason: PauseReason) -> Option<PauseReason> {
+        self.steps_left = 0;
+        self.target = None;
+        self.paused = Some(reason.clone());
+        Some(reason)
+    }
+
+    /**
+     * Ticks `emu` up to `ticks` times, fewer while paused. Returns why it
+     * paused if it did during this call.
+     */
+    pub fn run<E: EmuTrait + CpuInfo + ?Sized>(self: &mut Self, emu: &mut E, ticks: u32) -> Option<PauseReason> {
+        let ticks = if self.paused.is_some() { ticks.min(self.steps_left) } else { ticks };
+        let watching = !self.watchpoints.is_empty();
+        emu.set_memory_trace(watching);
+        for _ in 0..ticks {
+            let pc = emu.get_program_counter();
+            if self.last_pc != Some(pc) {
+                if let Some(reason) = self.check(emu, pc) {
+                    self.last_pc = Some(pc);
+                    return self.stop(reason);
+                }
+            }
+            emu.tick();
+            self.last_pc = Some(pc);
+            if watching {
+                if let Some(reason) = self.check_accesses(emu, pc) {
+                    return self.stop(reason);
+                }
+            }
+            if let Some(RunTarget::CallDepth(max)) = self.target {
+                if emu.get_call_depth().is_some_and(|depth| depth <= max) {
+                    return self.stop(PauseReason::Step);
+                }
+            }
+            if self.paused.is_some() {
+                self.steps_left -= 1;
+                if self.steps_left == 0 {
+                    return self.stop(PauseReason::Step);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8Emu;
+
+    // 0x200  SETI 0x300
+    // 0x202  SET V0 5
+    // 0x204  CALL 0x20C
+    // 0x206  ADD V0 1     <- loop
+    // 0x208  STRMEM V0    writes V0 to 0x300
+    // 0x20A  JMP 0x206
+    // 0x20C  DRAW V0 V1 1 reads 0x300
+    // 0x20E  RET
+    const ROM: [u8; 16] = [
+        0xa3, 0x00, 0x60, 0x05, 0x22, 0x0c, 0x70, 0x01,
+        0xf0, 0x55, 0x12, 0x06, 0xd0, 0x11, 0x00, 0xee,
+    ];
+
+    fn emu() -> Chip8Emu {
+        Chip8Emu::builder().rom_data(&ROM).start_running(true).build()
+    }
+
+    fn v0(emu: &Chip8Emu) -> u64 {
+        emu.get_data_registers()[0].reg_value
+    }
+
+    #[test]
+    fn opcode_pattern_wildcards() {
+        let draw: OpcodePattern = "DXYN".parse().unwrap();
+        assert!(draw.matches(0xd011));
+        assert!(draw.matches(0xdfff));
+        assert!(!draw.matches(0xc011));
+
+        // A - F are digits, not wildcards
+        let key: OpcodePattern = "exa1".parse().unwrap();
+        assert!(key.matches(0xe3a1));
+        assert!(!key.matches(0xe39e));
+        let dump: OpcodePattern = "F?55".parse().unwrap();
+        assert!(dump.matches(0xf355));
+        assert!(!dump.matches(0xf365));
+
+        assert!("XYZW".parse::<OpcodePattern>().is_err());
+        assert!("D-Y1".parse::<OpcodePattern>().is_err());
+        assert!("".parse::<OpcodePattern>().is_err());
+    }
+
+    #[test]
+    fn breakpoint_from_str() {
+        assert_eq!("0x2a4".parse(), Ok(Breakpoint::Address(0x2a4)));
+        assert_eq!(" 0X2A4 ".parse(), Ok(Breakpoint::Address(0x2a4)));
+        assert!("0xZZ".parse::<Breakpoint>().is_err());
+        assert_eq!("FX55".parse(), Ok(Breakpoint::Opcode("FX55".parse().unwrap())));
+        assert_eq!(Breakpoint::Address(0x2a4).to_string(), "0x2A4");
+    }
+
+    #[test]
+    fn address_breakpoint_and_continue() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(false);
+        debugger.add_breakpoint(Breakpoint::Address(0x206));
+
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Breakpoint(0x206)));
+        assert_eq!(emu.get_program_counter(), 0x206);
+        assert_eq!(v0(&emu), 5);
+        // Stays put while paused
+        assert_eq!(debugger.run(&mut emu, 100), None);
+        assert_eq!(emu.get_program_counter(), 0x206);
+
+        // Continuing runs the instruction under the breakpoint, then stops
+        // on the next pass of the loop
+        debugger.resume(&emu);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Breakpoint(0x206)));
+        assert_eq!(v0(&emu), 6);
+    }
+
+    #[test]
+    fn opcode_breakpoint() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(false);
+        debugger.add_breakpoint("DXYN".parse().unwrap());
+
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Opcode("DXYN".parse().unwrap(), 0x20c)));
+        assert_eq!(emu.get_program_counter(), 0x20c);
+    }
+
+    #[test]
+    fn step_n() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(true);
+        assert_eq!(debugger.run(&mut emu, 100), None);
+        assert_eq!(emu.get_program_counter(), 0x200);
+
+        debugger.step(&emu, 3);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Step));
+        assert_eq!(emu.get_program_counter(), 0x20c);
+        assert!(debugger.is_paused());
+
+        // Steps spread over several frames
+        debugger.step(&emu, 3);
+        assert_eq!(debugger.run(&mut emu, 2), None);
+        assert_eq!(emu.get_program_counter(), 0x206);
+        assert_eq!(debugger.run(&mut emu, 2), Some(PauseReason::Step));
+        assert_eq!(emu.get_program_counter(), 0x208);
+    }
+
+    #[test]
+    fn step_over_call() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(true);
+        debugger.step(&emu, 2);
+        debugger.run(&mut emu, 100);
+        assert_eq!(emu.get_program_counter(), 0x204);
+
+        // Runs the whole subroutine and stops after the call
+        debugger.step_over(&emu);
+        assert!(!debugger.is_paused());
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Step));
+        assert_eq!(emu.get_program_counter(), 0x206);
+        assert_eq!(emu.get_call_depth(), Some(0));
+
+        // A plain instruction is a single step
+        debugger.step_over(&emu);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Step));
+        assert_eq!(emu.get_program_counter(), 0x208);
+    }
+
+    #[test]
+    fn step_over_stops_at_breakpoint_inside_call() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(true);
+        debugger.add_breakpoint(Breakpoint::Address(0x20e));
+        debugger.step(&emu, 2);
+        debugger.run(&mut emu, 100);
+
+        debugger.step_over(&emu);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Breakpoint(0x20e)));
+        assert_eq!(emu.get_call_depth(), Some(1));
+    }
+
+    #[test]
+    fn step_out() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(true);
+        assert!(debugger.step_out(&emu).is_err());
+        assert!(debugger.is_paused());
+
+        debugger.step(&emu, 3);
+        debugger.run(&mut emu, 100);
+        assert_eq!(emu.get_program_counter(), 0x20c);
+        assert_eq!(emu.get_call_depth(), Some(1));
+
+        assert!(debugger.step_out(&emu).is_ok());
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Step));
+        assert_eq!(emu.get_program_counter(), 0x206);
+        assert_eq!(emu.get_call_depth(), Some(0));
+    }
+
+    #[test]
+    fn run_to_address() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(true);
+        debugger.run_to(&emu, 0x20a);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Reached(0x20a)));
+        assert_eq!(emu.get_program_counter(), 0x20a);
+
+        // Already there, so it goes round the loop once
+        debugger.run_to(&emu, 0x20a);
+        assert_eq!(debugger.run(&mut emu, 100), Some(PauseReason::Reached(0x20a)));
+        assert_eq!(v0(&emu), 7);
+    }
+
+    #[test]
+    fn watchpoint_from_str() {
+        let range: Watchpoint = "w:0x200-0x3ff".parse().unwrap();
+        assert_eq!(range, Watchpoint { kind: WatchKind::Write, start: 0x200, end: 0x3ff, value: None });
+        assert_eq!(range.to_string(), "w:0x200-0x3FF");
+
+        let counter: Watchpoint = "c:0x2a4=0x00".parse().unwrap();
+        assert_eq!(counter, Watchpoint { kind: WatchKind::Change, start: 0x2a4, end: 0x2a4, value: Some(0) });
+        assert_eq!(counter.to_string(), "c:0x2A4=0x00");
+
+        assert!("w:0x3ff-0x200".parse::<Watchpoint>().is_err());
+        assert!("x:0x200".parse::<Watchpoint>().is_err());
+        assert!("w:0x200=0x100".parse::<Watchpoint>().is_err());
+        assert!("0x200".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn watchpoint_write_and_change() {
+        let write: Watchpoint = "w:0x200-0x3ff".parse().unwrap();
+        let change: Watchpoint = "c:0x200-0x3ff".parse().unwrap();
+        let same = MemoryAccess::Write { addr: 0x300, old: 7, value: 7 };
+        let new = MemoryAccess::Write { addr: 0x300, old: 7, value: 8 };
+        assert!(write.matches(&same));
+        assert!(!change.matches(&same));
+        assert!(change.matches(&new));
+        assert!(!write.matches(&MemoryAccess::Write { addr: 0x400, old: 0, value: 1 }));
+        assert!(!write.matches(&MemoryAccess::Read { addr: 0x300, value: 7 }));
+
+        let zero: Watchpoint = "c:0x300=0x00".parse().unwrap();
+        assert!(!zero.matches(&new));
+        assert!(zero.matches(&MemoryAccess::Write { addr: 0x300, old: 1, value: 0 }));
+    }
+
+    #[test]
+    fn fx55_fires_write_watchpoint() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(false);
+        debugger.add_watchpoint("w:0x300".parse().unwrap());
+
+        let reason = debugger.run(&mut emu, 100);
+        match &reason {
+            Some(PauseReason::Watch { access, pc, .. }) => {
+                assert_eq!(*access, MemoryAccess::Write { addr: 0x300, old: 0, value: 6 });
+                assert_eq!(*pc, 0x208);
+            },
+            _ => panic!("expected a watchpoint, got {:?}", reason),
+        }
+        // Stops after the instruction that wrote
+        assert_eq!(emu.get_program_counter(), 0x20a);
+        assert_eq!(reason.unwrap().to_string(), "Write 0x300 = 0x06 by 0x208 STRMEM V0");
+    }
+
+    #[test]
+    fn dxyn_fires_read_watchpoint() {
+        let mut emu = emu();
+        let mut debugger = Debugger::new(false);
+        debugger.add_watchpoint("r:0x300".parse().unwrap());
+
+        match debugger.run(&mut emu, 100) {
+            Some(PauseReason::Watch { access: MemoryAccess::Read { addr: 0x300, .. }, pc: 0x20c, .. }) => {},
+            reason => panic!("expected a read at 0x300 by 0x20C, got {:?}", reason),
+        }
+    }
+}
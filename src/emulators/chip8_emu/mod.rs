@@ -1,26 +1,57 @@
-use std::fs;
-use std::fs::File;
-use std::io::Read;
-use super::{ EmuTrait, ScreenResolution, CpuInfo, RegisterInfo, RegisterSize };
-use rand::Rng;
+use std::{ fs, ops::Range };
+use super::{ palette, EmuTrait, ScreenResolution, CpuInfo, KeyboardDriver, MemoryAccess, MemoryMark, RegisterInfo, RegisterSize };
+use rand::{ Rng, SeedableRng, rngs::StdRng };
+use crate::filters::upscale;
+
+pub mod quirks;
+use quirks::Chip8Quirks;
 
 const PROGRAM_START: usize = 0x200;
+// Start of a save state, the last byte is the format version
+const STATE_MAGIC: &[u8; 4] = b"C8S2";
+// Return addresses 2NNN can push, as on the SUPER-CHIP
+const STACK_SIZE: usize = 16;
+// 0 - F, 4x5 pixels each, what FX29 points I at
+const FONT: [u8; 16 * 5] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, 0x20, 0x60, 0x20, 0x20, 0x70, 
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, 0xf0, 0x10, 0xf0, 0x10, 0xf0, 
+    0x90, 0x90, 0xf0, 0x10, 0x10, 0xf0, 0x80, 0xf0, 0x10, 0xf0, 
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, 0xf0, 0x10, 0x20, 0x40, 0x40, 
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, 0xf0, 0x90, 0xf0, 0x10, 0xf0, 
+    0xf0, 0x90, 0xf0, 0x90, 0x90, 0xe0, 0x90, 0xe0, 0x90, 0xe0, 
+    0xf0, 0x80, 0x80, 0x80, 0xf0, 0xe0, 0x90, 0x90, 0x90, 0xe0, 
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, 0xf0, 0x80, 0xf0, 0x80, 0x80, 
+];
+const SCREEN_WIDTH: u16 = 64;
+const SCREEN_HEIGHT: u16 = 32;
 
 pub struct Chip8Emu {
     memory: Vec<u8>, 
     reg: Vec<u8>, 
-    // stack: Vec<u16>, 
+    // Return addresses pushed by 2NNN, popped by 00EE
+    stack: Vec<u16>, 
     display_buffer: Vec<u8>, 
     program_counter: u16, 
     index_register: u16, 
-    // stack_pointer: u8, 
     delay_timer: u8, 
     sound_timer: u8, 
 
     is_running: bool, 
     keys: Vec<bool>, 
     wait_for_key: bool, 
+    // Keys EX9E / EXA1 tested since the last `take_polled_keys`, one bit each
+    polled_keys: u16, 
     font_sprite_offset: u16, 
+    quirks: Chip8Quirks, 
+    // Indexed by the plane bits of a display_buffer byte, see `emulators::palette`
+    palette: [[u8; 4]; palette::MAX_COLORS], 
+    // Planes DXYN draws to and 00E0 clears, one bit each, picked by XO-CHIP's FN01
+    draw_planes: u8, 
+    rng: StdRng, 
+    // For `take_message`
+    message: Option<String>, 
+    // Accesses through `read_memory` / `write_memory`, only while tracing
+    memory_trace: Option<Vec<MemoryAccess>>, 
 
     curr_opcode: u16, 
 }
@@ -54,6 +85,95 @@ impl CpuInfo for Chip8Emu {
         opcode = opcode | ((self.memory[(self.program_counter + 1) as usize] & 0xff) as u16);
         self.translate_opcode(opcode)
     }
+    fn get_program_counter(self: &Self) -> u64 {
+        self.program_counter as u64
+    }
+
+    fn set_memory_trace(self: &mut Self, on: bool) {
+        match (on, &self.memory_trace) {
+            (true, None) => self.memory_trace = Some(Vec::new()), 
+            (false, _) => self.memory_trace = None, 
+            _ => {}, 
+        }
+    }
+
+    fn take_memory_accesses(self: &mut Self) -> Vec<MemoryAccess> {
+        self.memory_trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn get_memory_range(self: &Self) -> Option<Range<u64>> {
+        Some(0..self.memory.len() as u64)
+    }
+
+    fn peek(self: &Self, addr: u64) -> Option<u8> {
+        self.memory.get(addr as usize).copied()
+    }
+
+    fn poke(self: &mut Self, addr: u64, value: u8) -> Result<(), String> {
+        match self.memory.get_mut(addr as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }, 
+            None => Err(format!("No memory at 0x{:X}", addr)), 
+        }
+    }
+
+    fn get_memory_marks(self: &Self) -> Vec<MemoryMark> {
+        vec![
+            MemoryMark { name: "I", start: self.index_register as u64, len: 1, is_pointer: true }, 
+            MemoryMark { name: "font", start: self.font_sprite_offset as u64, len: FONT.len() as u64, is_pointer: false }, 
+        ]
+    }
+
+    fn get_call_depth(self: &Self) -> Option<usize> {
+        Some(self.stack.len())
+    }
+
+    fn get_opcode(self: &Self, addr: u64) -> Option<u64> {
+        let addr = addr as usize;
+        if addr + 1 >= self.memory.len() {
+            return None;
+        }
+        Some((((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16) as u64)
+    }
+
+    fn get_branch_target(self: &Self, addr: u64) -> Option<u64> {
+        let opcode = self.get_opcode(addr)?;
+        match opcode & 0xf000 {
+            // JMP and CALL, BNNN depends on V0
+            0x1000 | 0x2000 => Some(opcode & 0x0fff), 
+            _ => None, 
+        }
+    }
+
+    fn disassemble(self: &Self, addr: u64) -> Option<(String, u64)> {
+        let opcode = self.get_opcode(addr)? as u16;
+        Some((self.translate_opcode(opcode), 2))
+    }
+}
+
+impl KeyboardDriver for Chip8Emu {
+    fn on_key_press(self: &mut Self, key: u8) {
+        if (key as usize) < self.keys.len() {
+            self.keys[key as usize] = true;
+        }
+        // FX0A is re-executed on the next tick and picks up the key
+        self.wait_for_key = false;
+    }
+
+    fn on_key_release(self: &mut Self, key: u8) {
+        if (key as usize) < self.keys.len() {
+            self.keys[key as usize] = false;
+        }
+    }
+
+    fn take_polled_keys(self: &mut Self) -> u16 {
+        // FX0A takes any key
+        let polled = if self.wait_for_key { 0xffff } else { self.polled_keys };
+        self.polled_keys = 0;
+        polled
+    }
 }
 
 impl EmuTrait for Chip8Emu {
@@ -74,20 +194,13 @@ impl EmuTrait for Chip8Emu {
         self.is_running = true;
     }
 
-    fn load_data_file(self: &mut Self, file_name: &str) {
-        println!("[Loading] {}...", file_name);
-        let mut f = File::open(file_name).expect("Could not open file!");
-        let metadata = fs::metadata(file_name).expect("Could not read metadata... ");
-        let mut buffer = vec![0; metadata.len() as usize];
-        f.read(&mut buffer).expect("Could not read file!");
-
-        // println!("[Got Data]:\n{:x?}", buffer);
-        for i in 0..buffer.len() {
-            self.memory[i + PROGRAM_START] = buffer[i];
+    fn load_data(self: &mut Self, data: &[u8]) -> Result<(), String> {
+        let space = self.memory.len() - PROGRAM_START;
+        if data.len() > space {
+            return Err(format!("ROM is {} bytes, only {} fit in memory", data.len(), space));
         }
-
-        self.is_running = false;
-        self.program_counter = 0x200;
+        self.copy_program(data);
+        Ok(())
     }
 
     fn get_screen_resolution(self: &Self) -> ScreenResolution {
@@ -99,53 +212,117 @@ impl EmuTrait for Chip8Emu {
 
     fn get_cpu_screen_resolution(self: &Self) -> ScreenResolution {
         ScreenResolution {
-            // 2 chars for register name
-            // + 4 chars for register value
-            // + 1 space char
+            // 18 chars for the longest instruction (DRAW V0 V1 0x   f)
             // * 8 pixels per char
-            // * 1 pixel space per char (rounded to 8 pixels total)
-            width: 64, 
+            // + 2 pixel space per char
+            width: 180, 
             // 1 row for each register
             // + 1 row for pc
             // + 1 row for index reg (IR)
-            // + 1 row for delay timer
-            // + 1 row for audio timer
-            height: 184, 
+            // + 2 rows for current and next instruction
+            // + 3 rows for the debugger status, wrapped
+            // * 10 pixels per row
+            height: 230, 
         }
     }
 
     fn draw_to_buffer_rgba(self: &Self, buf: &mut Vec<u8>, target_res: &ScreenResolution) 
         -> Result<bool, bool> {
 
-        let screen_res = self.get_screen_resolution();
-        let pixel_width = target_res.width / screen_res.width;
-        let pixel_height = target_res.height / screen_res.height;
-
-        let mut display_buffer_x;
-        let mut display_buffer_y;
-
-        for x in 0..target_res.width {
-            display_buffer_x = x / pixel_width;
-            for y in 0..target_res.height {
-                display_buffer_y = y / pixel_height;
-                let arr_offset = y * 4 * target_res.width + (x * 4);
-                let val = self.display_buffer[(display_buffer_y * screen_res.width + display_buffer_x) as usize];
-                buf[(arr_offset) as usize] = val;
-                buf[(arr_offset + 1) as usize] = val;
-                buf[(arr_offset + 2) as usize] = val;
-            }
+        if buf.len() < (target_res.width * target_res.height * 4) as usize {
+            return Err(false);
         }
+        let screen_res = self.get_screen_resolution();
+        let screen: Vec<u8> = self.display_buffer.iter()
+            .flat_map(|val| self.palette[*val as usize % palette::MAX_COLORS])
+            .collect();
+        // Any target size, smaller and non-integer multiples included
+        upscale::scale_nearest(&screen, &screen_res, buf, target_res);
 
         Ok(true)
     }
 
-    fn tick(self: &mut Self) {
+    fn set_quirks(self: &mut Self, preset: &str) -> Result<(), String> {
+        self.quirks = Chip8Quirks::from_preset(preset).ok_or(format!(
+            "Unknown CHIP-8 quirk preset '{}', expected one of {}", preset, Chip8Quirks::preset_names().join(", ")))?;
+        Ok(())
+    }
+
+    fn get_quirk_presets(self: &Self) -> Vec<&'static str> {
+        Chip8Quirks::preset_names()
+    }
+
+    fn set_palette(self: &mut Self, colors: &[[u8; 4]]) {
+        self.palette = palette::expand(colors);
+    }
+
+    fn set_seed(self: &mut Self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn save_state(self: &Self) -> Option<Vec<u8>> {
+        let mut data = STATE_MAGIC.to_vec();
+        data.extend_from_slice(&self.program_counter.to_be_bytes());
+        data.extend_from_slice(&self.index_register.to_be_bytes());
+        data.extend_from_slice(&self.curr_opcode.to_be_bytes());
+        data.extend_from_slice(&[self.delay_timer, self.sound_timer, self.wait_for_key as u8, self.draw_planes]);
+        // Stack depth, then all slots so the state has a fixed size
+        data.push(self.stack.len() as u8);
+        for i in 0..STACK_SIZE {
+            data.extend_from_slice(&self.stack.get(i).copied().unwrap_or(0).to_be_bytes());
+        }
+        data.extend_from_slice(&self.reg);
+        data.extend_from_slice(&self.memory);
+        data.extend_from_slice(&self.display_buffer);
+        Some(data)
+    }
+
+    fn load_state(self: &mut Self, data: &[u8]) -> Result<(), String> {
+        let header_len = STATE_MAGIC.len() + 11 + STACK_SIZE * 2;
+        let expected = header_len + self.reg.len() + self.memory.len() + self.display_buffer.len();
+        if !data.starts_with(STATE_MAGIC) || data.len() != expected || data[STATE_MAGIC.len() + 10] as usize > STACK_SIZE {
+            return Err(String::from("Not a CHIP-8 save state"));
+        }
+        let header = &data[STATE_MAGIC.len()..header_len];
+        self.program_counter = u16::from_be_bytes([header[0], header[1]]);
+        self.index_register = u16::from_be_bytes([header[2], header[3]]);
+        self.curr_opcode = u16::from_be_bytes([header[4], header[5]]);
+        self.delay_timer = header[6];
+        self.sound_timer = header[7];
+        self.wait_for_key = header[8] != 0;
+        self.draw_planes = header[9];
+        let depth = header[10] as usize;
+        self.stack = header[11..11 + depth * 2].chunks(2)
+            .map(|slot| u16::from_be_bytes([slot[0], slot[1]]))
+            .collect();
+
+        let (reg, rest) = data[header_len..].split_at(self.reg.len());
+        let (memory, display) = rest.split_at(self.memory.len());
+        self.reg.copy_from_slice(reg);
+        self.memory.copy_from_slice(memory);
+        self.display_buffer.copy_from_slice(display);
+        Ok(())
+    }
+
+    fn take_message(self: &mut Self) -> Option<String> {
+        self.message.take()
+    }
+
+    fn is_sound_playing(self: &Self) -> bool {
+        self.sound_timer > 0
+    }
+
+    fn on_frame(self: &mut Self) {
+        // DT and ST count down at 60 Hz however many instructions a frame runs
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+    }
+
+    fn tick(self: &mut Self) {
         if !self.wait_for_key {
             // let pc = self.program_counter;
             self.curr_opcode = self.fetch_opcode();
@@ -158,26 +335,136 @@ impl EmuTrait for Chip8Emu {
     }
 }
 
+/**
+ * Builds a `Chip8Emu` with a ROM already in memory.
+ *
+ * ```
+ * use ru_emu_lib::Chip8Emu;
+ *
+ * let emu = Chip8Emu::builder()
+ *     .rom_data(&[0x00, 0xe0, 0x12, 0x00])
+ *     .build();
+ * ```
+ */
+pub struct Chip8EmuBuilder {
+    rom_data: Vec<u8>, 
+    font_sprite_offset: u16, 
+    quirks: Chip8Quirks, 
+    start_running: bool, 
+}
+
+impl Default for Chip8EmuBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8EmuBuilder {
+    pub fn new() -> Chip8EmuBuilder {
+        Chip8EmuBuilder {
+            rom_data: Vec::new(), 
+            font_sprite_offset: 0, 
+            quirks: Chip8Quirks::default(), 
+            start_running: false, 
+        }
+    }
+
+    pub fn rom_data(mut self, data: &[u8]) -> Self {
+        self.rom_data = data.to_vec();
+        self
+    }
+
+    pub fn rom_file(mut self, file_name: &str) -> std::io::Result<Self> {
+        self.rom_data = fs::read(file_name)?;
+        Ok(self)
+    }
+
+    /**
+     * Where the built-in hex font sprites go and FX29 expects them, 0 by
+     * default. Moved back if the font wouldn't fit in memory.
+     */
+    pub fn font_sprite_offset(mut self, offset: u16) -> Self {
+        self.font_sprite_offset = offset;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Chip8Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn start_running(mut self, start_running: bool) -> Self {
+        self.start_running = start_running;
+        self
+    }
+
+    pub fn build(self) -> Chip8Emu {
+        let mut emu = Chip8Emu::new();
+        emu.move_font(self.font_sprite_offset);
+        emu.quirks = self.quirks;
+        emu.copy_program(&self.rom_data);
+        if self.start_running {
+            emu.start();
+        }
+        emu
+    }
+}
+
 impl Chip8Emu {
+    pub fn builder() -> Chip8EmuBuilder {
+        Chip8EmuBuilder::new()
+    }
+
+    /**
+     * Copies a ROM image to the program area and resets the program counter,
+     * dropping whatever doesn't fit.
+     */
+    fn copy_program(self: &mut Self, data: &[u8]) {
+        let len = data.len().min(self.memory.len() - PROGRAM_START);
+        self.memory[PROGRAM_START..PROGRAM_START + len].copy_from_slice(&data[..len]);
+
+        self.is_running = false;
+        self.program_counter = PROGRAM_START as u16;
+    }
+
     pub fn new() -> Chip8Emu {
-        Chip8Emu {
+        let mut emu = Chip8Emu {
             memory: vec![0; 0x1000],        // 4096 bytes
             reg: vec![0; 0x10],             // 16 bytes
-            // stack: vec![0; 0x30],           // 48 short
+            stack: Vec::with_capacity(STACK_SIZE), 
             display_buffer: vec![0; 0x800],  // 64 * 32 bytes 
             program_counter: 0, 
             index_register: 0, 
-            // stack_pointer: 0, 
             delay_timer: 0, 
             sound_timer: 0, 
 
             is_running: false, 
             keys: vec![false; 0xff], 
             wait_for_key: false, 
+            polled_keys: 0, 
             font_sprite_offset: 0, 
+            quirks: Chip8Quirks::default(), 
+            palette: palette::DEFAULT_COLORS, 
+            draw_planes: 1, 
+            rng: StdRng::from_entropy(), 
+            message: None, 
+            memory_trace: None, 
 
             curr_opcode: 0, 
-        }
+        };
+        emu.move_font(0);
+        emu
+    }
+
+    /**
+     * Clears the font sprites where they were and writes them at `offset`.
+     */
+    fn move_font(self: &mut Self, offset: u16) {
+        let old = self.font_sprite_offset as usize;
+        self.memory[old..old + FONT.len()].fill(0);
+        let offset = offset.min((self.memory.len() - FONT.len()) as u16);
+        self.memory[offset as usize..offset as usize + FONT.len()].copy_from_slice(&FONT);
+        self.font_sprite_offset = offset;
     }
 
     fn fetch_opcode(self: &mut Self) -> u16 {
@@ -188,6 +475,27 @@ impl Chip8Emu {
         opcode
     }
 
+    /**
+     * Data reads of the opcodes go through here so watchpoints see them.
+     * Addresses wrap around the 4 KiB like the 12 bit I register of the VIP.
+     */
+    fn read_memory(self: &mut Self, addr: u16) -> u8 {
+        let addr = addr as usize % self.memory.len();
+        let value = self.memory[addr];
+        if let Some(trace) = &mut self.memory_trace {
+            trace.push(MemoryAccess::Read { addr: addr as u64, value });
+        }
+        value
+    }
+
+    fn write_memory(self: &mut Self, addr: u16, value: u8) {
+        let addr = addr as usize % self.memory.len();
+        if let Some(trace) = &mut self.memory_trace {
+            trace.push(MemoryAccess::Write { addr: addr as u64, old: self.memory[addr], value });
+        }
+        self.memory[addr] = value;
+    }
+
     fn translate_opcode(self: &Self, opcode: u16) -> String {
         let op = ((opcode & 0xf000) >> 12, 
         (opcode & 0x0f00) >> 8, 
@@ -221,6 +529,7 @@ impl Chip8Emu {
             (0xd, x, y, n) => format!("DRAW V{} V{} 0x{:4x}", x, y, n), 
             (0xe, x, 0x9, 0xe) => format!("JKEY V{}", x), 
             (0xe, x, 0xa, 0x1) => format!("JNKEY V{}", x), 
+            (0xf, n, 0x0, 0x1) => format!("PLANE 0x{:x}", n), 
             (0xf, x, 0x0, 0x7) => format!("GETDELAY V{}", x), 
             (0xf, x, 0x0, 0xa) => format!("GETKEY V{}", x), 
             (0xf, x, 0x1, 0x5) => format!("SETDELAY V{}", x), 
@@ -237,8 +546,12 @@ impl Chip8Emu {
     fn exec_opcode(self: &mut Self, opcode: u16) {
         if opcode == 0x00e0 {
             self.op_00e0_cls();
+        } else if opcode == 0x00ee {
+            self.op_00ee_ret();
         } else if (opcode & 0xf000) == 0x1000 {
             self.op_1nnn_jmp(opcode);
+        } else if (opcode & 0xf000) == 0x2000 {
+            self.op_2nnn_call(opcode);
         } else if (opcode & 0xf000) == 0x3000 {
             self.op_3xnn_je(opcode);
         } else if (opcode & 0xf000) == 0x4000 {
@@ -281,6 +594,8 @@ impl Chip8Emu {
             self.op_ex9e_jmp_key_on(opcode);
         } else if (opcode & 0xf0ff) == 0xe0a1 {
             self.op_exa1_jmp_key_off(opcode);
+        } else if (opcode & 0xf0ff) == 0xf001 {
+            self.op_fn01_select_planes(opcode);
         } else if (opcode & 0xf0ff) == 0xf007 {
             self.op_fx07_get_delay_timer(opcode);
         } else if (opcode & 0xf0ff) == 0xf00a {
@@ -300,7 +615,8 @@ impl Chip8Emu {
         } else if (opcode & 0xf0ff) == 0xf065 {
             self.op_fx65_reg_load(opcode);
         } else { 
-            println!("[Not implemented]: {:4x}", opcode);
+            crate::log_warn!("[Not implemented]: {:4x}", opcode);
+            self.message = Some(format!("Invalid opcode {:04X} at 0x{:X}", opcode, self.program_counter.wrapping_sub(2)));
         }
     }
 
@@ -309,7 +625,16 @@ impl Chip8Emu {
      */ 
     fn op_00e0_cls(self: &mut Self) {
         for i in 0..0x800 {
-            self.display_buffer[i] = 0;
+            self.display_buffer[i] &= !self.draw_planes;
+        }
+    }
+
+    fn op_00ee_ret(self: &mut Self) {
+        match self.stack.pop() {
+            Some(addr) => self.program_counter = addr, 
+            None => {
+                self.message = Some(format!("Return with an empty stack at 0x{:X}", self.program_counter.wrapping_sub(2)));
+            }, 
         }
     }
 
@@ -318,6 +643,15 @@ impl Chip8Emu {
         self.program_counter = nnn;
     }
 
+    fn op_2nnn_call(self: &mut Self, opcode: u16) {
+        if self.stack.len() >= STACK_SIZE {
+            self.message = Some(format!("Stack overflow at 0x{:X}", self.program_counter.wrapping_sub(2)));
+            return;
+        }
+        self.stack.push(self.program_counter);
+        self.program_counter = opcode & 0x0fff;
+    }
+
     fn op_3xnn_je(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
         let nn = (opcode & 0xff) as u8;
@@ -364,18 +698,27 @@ impl Chip8Emu {
         let x = (opcode & 0x0f00) >> 8;
         let y = ((opcode & 0xf0) >> 4) as u8;
         self.reg[x as usize] = self.reg[x as usize] | self.reg[y as usize];
+        if self.quirks.vf_reset {
+            self.reg[15] = 0;
+        }
     }
 
     fn op_8xy2_and(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
         let y = ((opcode & 0xf0) >> 4) as u8;
         self.reg[x as usize] = self.reg[x as usize] & self.reg[y as usize];
+        if self.quirks.vf_reset {
+            self.reg[15] = 0;
+        }
     }
 
     fn op_8xy3_xor(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
         let y = ((opcode & 0xf0) >> 4) as u8;
         self.reg[x as usize] = self.reg[x as usize] ^ self.reg[y as usize];
+        if self.quirks.vf_reset {
+            self.reg[15] = 0;
+        }
     }
 
     fn op_8xy4_add(self: &mut Self, opcode: u16) {
@@ -406,8 +749,10 @@ impl Chip8Emu {
 
     fn op_8xy6_rshift(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
-        self.reg[15] = self.reg[x as usize] & 0x01;
-        self.reg[x as usize] = self.reg[x as usize] >> 1;
+        let y = ((opcode & 0xf0) >> 4) as u8;
+        let src = if self.quirks.shift_uses_vy { self.reg[y as usize] } else { self.reg[x as usize] };
+        self.reg[x as usize] = src >> 1;
+        self.reg[15] = src & 0x01;
     }
 
     fn op_8xy7_sub(self: &mut Self, opcode: u16) {
@@ -425,8 +770,10 @@ impl Chip8Emu {
 
     fn op_8xye_lshift(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
-        self.reg[15] = (self.reg[x as usize] & 0x80) >> 7;
-        self.reg[x as usize] = self.reg[x as usize] << 1;
+        let y = ((opcode & 0xf0) >> 4) as u8;
+        let src = if self.quirks.shift_uses_vy { self.reg[y as usize] } else { self.reg[x as usize] };
+        self.reg[x as usize] = src << 1;
+        self.reg[15] = (src & 0x80) >> 7;
     }
 
     fn op_9xy0_jne(self: &mut Self, opcode: u16) {
@@ -445,36 +792,67 @@ impl Chip8Emu {
         let vx: u16 = ((opcode & 0x0f00) >> 8).try_into().unwrap();
         let vy: u16 = ((opcode & 0xf0) >> 4) as u16;
         let n: u8 = (opcode & 0x0f) as u8;
-        let x_pos = self.reg[vx as usize] as u16;
-        let y_pos = self.reg[vy as usize] as u16;
-
-        for y in 0..n {
-            let row_val = self.memory[(self.index_register + y as u16) as usize];
-            let draw_pos: usize = (((y_pos + y as u16) * 64) + x_pos).into();
-            for x in (0..8).rev() {
-                let bit_val = (row_val >> x) & 0x01;
-                if bit_val == 1 {
-                    self.reg[15] = 1;
-                    self.display_buffer[(draw_pos + (7 - x)) as usize] = 
-                        !self.display_buffer[(draw_pos + (7 - x)) as usize];
+        // The start position always wraps, the rest depends on clip_sprites
+        let x_pos = self.reg[vx as usize] as u16 % SCREEN_WIDTH;
+        let y_pos = self.reg[vy as usize] as u16 % SCREEN_HEIGHT;
+
+        // Each selected plane takes the next N bytes of sprite data, lowest plane first
+        let mut sprite_addr = self.index_register;
+        let planes = self.draw_planes;
+        // VF is only set when a lit pixel gets turned off
+        let mut collision = 0;
+        for plane in (0..4).map(|bit| 1u8 << bit).filter(|plane| planes & plane != 0) {
+            for y in 0..n {
+                let row_val = self.read_memory(sprite_addr.wrapping_add(y as u16));
+                let mut py = y_pos + y as u16;
+                if py >= SCREEN_HEIGHT {
+                    if self.quirks.clip_sprites {
+                        break;
+                    }
+                    py %= SCREEN_HEIGHT;
+                }
+                for x in (0..8).rev() {
+                    let bit_val = (row_val >> x) & 0x01;
+                    let mut px = x_pos + (7 - x) as u16;
+                    if px >= SCREEN_WIDTH {
+                        if self.quirks.clip_sprites {
+                            continue;
+                        }
+                        px %= SCREEN_WIDTH;
+                    }
+                    if bit_val == 1 {
+                        let draw_pos = (py * SCREEN_WIDTH + px) as usize;
+                        if self.display_buffer[draw_pos] & plane != 0 {
+                            collision = 1;
+                        }
+                        self.display_buffer[draw_pos] ^= plane;
+                    }
                 }
             }
+            sprite_addr = sprite_addr.wrapping_add(n as u16);
         }
+        self.reg[15] = collision;
+    }
+
+    fn op_fn01_select_planes(self: &mut Self, opcode: u16) {
+        self.draw_planes = ((opcode & 0x0f00) >> 8) as u8;
     }
 
     fn op_bnnn_jmp(self: &mut Self, opcode: u16) {
         let nnn: u16 = opcode & 0x0fff;
-        self.program_counter = self.reg[0] as u16 + nnn;
+        let x = if self.quirks.jump_uses_vx { (opcode & 0x0f00) >> 8 } else { 0 };
+        self.program_counter = self.reg[x as usize] as u16 + nnn;
     }
 
     fn op_cxnn_rnd(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
         let nn = (opcode & 0xff) as u8;
-        self.reg[x as usize] = rand::thread_rng().gen_range(0..=nn);
+        self.reg[x as usize] = self.rng.gen_range(0..=nn);
     }
 
     fn op_ex9e_jmp_key_on(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
+        self.polled_keys |= 1 << (self.reg[x as usize] & 0x0f);
         if self.keys[self.reg[x as usize] as usize] {
             self.program_counter += 2;
         }
@@ -482,6 +860,7 @@ impl Chip8Emu {
 
     fn op_exa1_jmp_key_off(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
+        self.polled_keys |= 1 << (self.reg[x as usize] & 0x0f);
         if !self.keys[self.reg[x as usize] as usize] {
             self.program_counter += 2;
         }
@@ -525,29 +904,30 @@ impl Chip8Emu {
 
     fn op_fx29_set_sprite_to_index(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
+        // Only the low digit, the font has 16 characters
         self.index_register = self.font_sprite_offset 
-            + (self.reg[x as usize] * 5) as u16;
+            + (self.reg[x as usize] & 0x0f) as u16 * 5;
     }
 
     fn op_fx33_bcd(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
-        let mut x_val = self.reg[x as usize];
-        let mut i = self.index_register;
-        while x_val > 0 {
-            self.memory[i as usize] = x_val % 10;
-            x_val = x_val / 10;
-            i += 1;
+        let x_val = self.reg[x as usize];
+        // Hundreds at I, tens at I + 1, ones at I + 2, I is left alone
+        let digits = [x_val / 100, x_val / 10 % 10, x_val % 10];
+        for (i, digit) in digits.into_iter().enumerate() {
+            self.write_memory(self.index_register.wrapping_add(i as u16), digit);
         }
-        self.index_register = self.font_sprite_offset 
-            + (self.reg[x as usize] * 5) as u16;
     }
 
     fn op_fx55_reg_dump(self: &mut Self, opcode: u16) {
         let x = (opcode & 0x0f00) >> 8;
         let mut mem_pos = self.index_register;
         for i in 0..=x {
-            self.memory[mem_pos as usize] = self.reg[i as usize];
-            mem_pos += 1;
+            self.write_memory(mem_pos, self.reg[i as usize]);
+            mem_pos = mem_pos.wrapping_add(1);
+        }
+        if self.quirks.load_store_increments_i {
+            self.index_register = mem_pos;
         }
     }
 
@@ -555,8 +935,100 @@ impl Chip8Emu {
         let x = (opcode & 0x0f00) >> 8;
         let mut mem_pos = self.index_register;
         for i in 0..=x {
-            self.reg[i as usize] = self.memory[mem_pos as usize];
-            mem_pos += 1;
+            self.reg[i as usize] = self.read_memory(mem_pos);
+            mem_pos = mem_pos.wrapping_add(1);
+        }
+        if self.quirks.load_store_increments_i {
+            self.index_register = mem_pos;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_to_any_size() {
+        let mut emu = Chip8Emu::builder().build();
+        emu.display_buffer[0] = 1;
+        let (off, on) = (emu.palette[0], emu.palette[1]);
+        // Smaller than the screen, integer and non-integer multiples
+        for (width, height) in [(64, 32), (32, 16), (1, 1), (100, 50), (640, 320)] {
+            let res = ScreenResolution { width, height };
+            let mut buf = vec![0; (width * height * 4) as usize];
+            assert_eq!(emu.draw_to_buffer_rgba(&mut buf, &res), Ok(true));
+            assert_eq!(buf[..4], on, "{}x{}", width, height);
         }
+        let res = ScreenResolution { width: 100, height: 50 };
+        let mut buf = vec![0; 100 * 50 * 4];
+        emu.draw_to_buffer_rgba(&mut buf, &res).unwrap();
+        assert_eq!(buf[4..8], on);
+        assert_eq!(buf[8..12], off);
+
+        let res = ScreenResolution { width: 64, height: 32 };
+        assert_eq!(emu.draw_to_buffer_rgba(&mut vec![0; 4], &res), Err(false));
+    }
+
+    #[test]
+    fn draw_collision_flag() {
+        let rom = [
+            0xa2, 0x0c,     // SETI 0x20C
+            0x6f, 0x01,     // SET VF 1
+            0xd0, 0x01,     // DRAW V0 V0 1
+            0x61, 0x04,     // SET V1 4
+            0xd1, 0x01,     // DRAW V1 V0 1, next to the first one
+            0xd0, 0x01,     // DRAW V0 V0 1, erases the first one
+            0xf0,           // Sprite, the left half of a row
+        ];
+        let mut emu = Chip8Emu::builder().rom_data(&rom).start_running(true).build();
+        for _ in 0..3 {
+            emu.tick();
+        }
+        // Cleared even though bits were drawn
+        assert_eq!(emu.reg[15], 0);
+        assert_eq!(emu.display_buffer[..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+        emu.tick();
+        emu.tick();
+        assert_eq!(emu.reg[15], 0);
+        assert_eq!(emu.display_buffer[..8], [1; 8]);
+        emu.tick();
+        assert_eq!(emu.reg[15], 1);
+        assert_eq!(emu.display_buffer[..8], [0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn bcd_digits() {
+        for (value, digits) in [(0, [0, 0, 0]), (7, [0, 0, 7]), (42, [0, 4, 2]), (255, [2, 5, 5])] {
+            let rom = [0xf0, 0x33];    // BCD V0
+            let mut emu = Chip8Emu::builder().rom_data(&rom).start_running(true).build();
+            emu.memory[0x300..0x303].fill(0xff);
+            emu.reg[0] = value;
+            emu.index_register = 0x300;
+            emu.tick();
+            assert_eq!(emu.memory[0x300..0x303], digits, "V0 = {}", value);
+            assert_eq!(emu.index_register, 0x300);
+        }
+    }
+
+    #[test]
+    fn load_store_wraps_around_memory() {
+        let rom = [
+            0xf2, 0x55,     // DUMP V0..V2
+            0xf2, 0x65,     // LOAD V0..V2
+        ];
+        let quirks = Chip8Quirks { load_store_increments_i: true, ..Chip8Quirks::default() };
+        let mut emu = Chip8Emu::builder().rom_data(&rom).quirks(quirks).start_running(true).build();
+        emu.reg[..3].copy_from_slice(&[1, 2, 3]);
+        emu.index_register = 0xffff;
+        emu.tick();
+        assert_eq!(emu.memory[0xfff], 1);
+        assert_eq!(emu.memory[..2], [2, 3]);
+        assert_eq!(emu.index_register, 0x0002);
+        emu.reg[..3].fill(0);
+        emu.index_register = 0xffff;
+        emu.tick();
+        assert_eq!(emu.reg[..3], [1, 2, 3]);
+        assert_eq!(emu.index_register, 0x0002);
     }
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+/**
+ * Behaviours that differ between CHIP-8 interpreters. ROMs written for one
+ * often break on another, so these are picked per ROM or per preset.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Chip8Quirks {
+    // 8XY6 / 8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55 / FX65 leave I pointing past the last register
+    pub load_store_increments_i: bool,
+    // BNNN jumps to NNN + VX (X being the top nibble of NNN) instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1 / 8XY2 / 8XY3 set VF to 0
+    pub vf_reset: bool,
+    // Sprites are cut at the screen edge instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+pub const QUIRK_PRESETS: &[(&str, Chip8Quirks)] = &[
+    // What this core has always done
+    ("default", Chip8Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+    }),
+    // The original COSMAC VIP interpreter
+    ("cosmac", Chip8Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+    }),
+    ("chip48", Chip8Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+    }),
+    ("schip", Chip8Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+    }),
+    ("xochip", Chip8Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+    }),
+];
+
+impl Default for Chip8Quirks {
+    fn default() -> Self {
+        QUIRK_PRESETS[0].1
+    }
+}
+
+impl Chip8Quirks {
+    pub fn from_preset(name: &str) -> Option<Chip8Quirks> {
+        QUIRK_PRESETS.iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+
+    pub fn preset_names() -> Vec<&'static str> {
+        QUIRK_PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+}
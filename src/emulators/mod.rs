@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 pub struct ScreenResolution {
     pub width: u32,
     pub height: u32,
@@ -12,7 +14,21 @@ pub enum RegisterSize
 }
 
 pub trait EmuTrait {
-    fn load_data_file(self: &mut Self, file_name: &str);
+    // Loads a ROM image already read into memory. Err for data the core can't
+    // run, e.g. a malformed ELF file
+    fn load_data(self: &mut Self, data: &[u8]) -> Result<(), String>;
+
+    // Reads and loads a ROM file, logging instead of failing when it can't
+    fn load_data_file(self: &mut Self, file_name: &str) {
+        crate::log_info!("[Loading] {}...", file_name);
+        let loaded = std::fs::read(file_name)
+            .map_err(|e| e.to_string())
+            .and_then(|data| self.load_data(&data));
+        if let Err(err) = loaded {
+            crate::log_error!("[Loading] Could not load {}: {}", file_name, err);
+        }
+    }
+
     fn start(self: &mut Self);
     fn stop(self: &mut Self);
     fn pause(self: &mut Self);
@@ -23,11 +39,69 @@ pub trait EmuTrait {
     fn draw_to_buffer_rgba(self: &Self, buf: &mut Vec<u8>, target_res: &ScreenResolution) 
         -> Result<bool, bool>;
     fn tick(self: &mut Self);
+
+    // Called once per 60 Hz frame after its ticks, for anything that runs at the
+    // frame rate rather than the instruction rate, like the CHIP-8 timers
+    fn on_frame(self: &mut Self) {
+    }
+
+    // Set once the emulated program has asked to exit, for cores that support it
+    fn get_exit_code(self: &Self) -> Option<u32> {
+        None
+    }
+
+    // Picks a named set of interpreter quirks, for cores that have them
+    fn set_quirks(self: &mut Self, preset: &str) -> Result<(), String> {
+        Err(format!("This core has no quirk presets, ignoring '{}'", preset))
+    }
+
+    // Names accepted by `set_quirks`
+    fn get_quirk_presets(self: &Self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    // Screen colours as RGBA, background first then one per combination of bit planes
+    // (see `palette`). Missing entries get the defaults, cores with a colour framebuffer ignore it
+    fn set_palette(self: &mut Self, _colors: &[[u8; 4]]) {
+    }
+
+    // Seeds the core's random number generator, for runs that repeat exactly
+    fn set_seed(self: &mut Self, _seed: u64) {
+    }
+
+    // Snapshot of the whole machine, for cores that support save states
+    fn save_state(self: &Self) -> Option<Vec<u8>> {
+        None
+    }
+
+    // Restores a snapshot taken by `save_state`
+    fn load_state(self: &mut Self, _data: &[u8]) -> Result<(), String> {
+        Err(String::from("This core has no save states"))
+    }
+
+    // Something the user should see that happened since the last call, e.g. an invalid opcode
+    fn take_message(self: &mut Self) -> Option<String> {
+        None
+    }
+
+    // True while the program wants the beeper on
+    fn is_sound_playing(self: &Self) -> bool {
+        false
+    }
 }
 
+/**
+ * `key` is in the core's own key space, e.g. 0x0 - 0xf for the CHIP-8 hex keypad.
+ */
 pub trait KeyboardDriver {
-    fn on_key_press(self: &mut Self);
-    fn on_key_release(self: &mut Self);
+    fn on_key_press(self: &mut Self, key: u8);
+    fn on_key_release(self: &mut Self, key: u8);
+
+    // Keys the program tested since the last call, one bit per key, for cores
+    // with up to 16 keys. Lets the frontend show which keys a game reads
+    fn take_polled_keys(self: &mut Self) -> u16 {
+        0
+    }
 }
 
 pub struct RegisterInfo {
@@ -36,11 +110,203 @@ pub struct RegisterInfo {
     pub reg_value: u64, 
 }
 
+/**
+ * A data read or write by the program, instruction fetches are left out.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MemoryAccess {
+    Read { addr: u64, value: u8 },
+    // `old` is what the byte held before
+    Write { addr: u64, old: u8, value: u8 },
+}
+
+/**
+ * Something the memory viewer marks besides the PC.
+ */
+pub struct MemoryMark {
+    pub name: &'static str, 
+    pub start: u64, 
+    pub len: u64, 
+    // Where a register points, like the CHIP-8 I, rather than a fixed area
+    pub is_pointer: bool, 
+}
+
 pub trait CpuInfo {
     fn get_data_registers(self: &Self) -> Vec<RegisterInfo>;
     fn get_current_instr(self: &Self) -> String;
     fn get_next_instr(self: &Self) -> String;
+    fn get_program_counter(self: &Self) -> u64;
+
+    // Subroutine calls that haven't returned yet, for cores that track them.
+    // The debugger's step over and step out run until it drops back
+    fn get_call_depth(self: &Self) -> Option<usize> {
+        None
+    }
+
+    // Raw instruction word at `addr` as the core decodes it, for opcode breakpoints
+    fn get_opcode(self: &Self, _addr: u64) -> Option<u64> {
+        None
+    }
+
+    // Starts or stops recording memory accesses for `take_memory_accesses`,
+    // for the debugger's watchpoints
+    fn set_memory_trace(self: &mut Self, _on: bool) {
+    }
+
+    // Accesses recorded since the last call, in the order they happened
+    fn take_memory_accesses(self: &mut Self) -> Vec<MemoryAccess> {
+        Vec::new()
+    }
+
+    // Addresses the memory viewer shows, None for cores without one
+    fn get_memory_range(self: &Self) -> Option<Range<u64>> {
+        None
+    }
+
+    // Byte at `addr` without the side effects of a program read
+    fn peek(self: &Self, _addr: u64) -> Option<u8> {
+        None
+    }
+
+    // Changes the byte at `addr` from the memory editor, watchpoints don't see it
+    fn poke(self: &mut Self, _addr: u64, _value: u8) -> Result<(), String> {
+        Err(String::from("This core's memory can't be edited"))
+    }
+
+    // Registers and areas the memory viewer marks, e.g. the CHIP-8 I and font
+    fn get_memory_marks(self: &Self) -> Vec<MemoryMark> {
+        Vec::new()
+    }
+
+    // Where the jump, call or branch at `addr` goes if that is a fixed
+    // address, for the labels of the disassembly view
+    fn get_branch_target(self: &Self, _addr: u64) -> Option<u64> {
+        None
+    }
+
+    // Instruction at `addr` without running anything, and its length in bytes
+    fn disassemble(self: &Self, _addr: u64) -> Option<(String, u64)> {
+        None
+    }
+}
+
+
+/**
+ * Anything the frontend can drive: a core, its debug view and its input.
+ */
+pub trait Emulator: EmuTrait + CpuInfo + KeyboardDriver {}
+
+impl<T: EmuTrait + CpuInfo + KeyboardDriver> Emulator for T {}
+
+pub struct SystemInfo {
+    pub name: &'static str, 
+    pub description: &'static str, 
+    // lower case, without the leading dot
+    pub extensions: &'static [&'static str], 
+    // Ticks run per 60 Hz frame at 1x speed
+    pub ticks_per_frame: u32, 
+    // Named keyboard layouts, key names (as SDL names them) and the core key they press
+    pub key_presets: &'static [(&'static str, &'static [(&'static str, u8)])], 
+    // Rows of the on-screen keypad, empty for cores without a keypad
+    pub keypad: &'static [&'static [u8]], 
+    // Per player, game controller button names (as SDL names them) and the core key they press
+    pub default_buttons: &'static [&'static [(&'static str, u8)]], 
+    // Typed text goes to the core a byte at a time through `KeyboardDriver::on_key_press`,
+    // and keys that type something aren't hotkeys
+    pub text_input: bool, 
+    pub create: fn() -> Box<dyn Emulator>, 
 }
 
+pub const SYSTEMS: &[SystemInfo] = &[
+    SystemInfo {
+        name: "chip8", 
+        description: "CHIP-8", 
+        extensions: &["ch8", "c8", "sc8", "xo8", "chip8"], 
+        // ~600 instructions per second
+        ticks_per_frame: 10, 
+        // The first preset is the default
+        key_presets: &[
+            // The COSMAC VIP 1-2-3-C keypad on the left of a QWERTY keyboard
+            ("qwerty", &[
+                ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xc), 
+                ("Q", 0x4), ("W", 0x5), ("E", 0x6), ("R", 0xd), 
+                ("A", 0x7), ("S", 0x8), ("D", 0x9), ("F", 0xe), 
+                ("Z", 0xa), ("X", 0x0), ("C", 0xb), ("V", 0xf), 
+            ]), 
+            // The same keypad positions on AZERTY
+            ("azerty", &[
+                ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xc), 
+                ("A", 0x4), ("Z", 0x5), ("E", 0x6), ("R", 0xd), 
+                ("Q", 0x7), ("S", 0x8), ("D", 0x9), ("F", 0xe), 
+                ("W", 0xa), ("X", 0x0), ("C", 0xb), ("V", 0xf), 
+            ]), 
+            // Each key pressed by the key with its label on the VIP keypad
+            ("vip", &[
+                ("0", 0x0), ("1", 0x1), ("2", 0x2), ("3", 0x3), 
+                ("4", 0x4), ("5", 0x5), ("6", 0x6), ("7", 0x7), 
+                ("8", 0x8), ("9", 0x9), ("A", 0xa), ("B", 0xb), 
+                ("C", 0xc), ("D", 0xd), ("E", 0xe), ("F", 0xf), 
+            ]), 
+        ], 
+        // The COSMAC VIP hex keypad
+        keypad: &[
+            &[0x1, 0x2, 0x3, 0xc], 
+            &[0x4, 0x5, 0x6, 0xd], 
+            &[0x7, 0x8, 0x9, 0xe], 
+            &[0xa, 0x0, 0xb, 0xf], 
+        ], 
+        // Player 1 on the 2/4/6/8 arrows with 5 to fire, player 2 on C/D
+        // like the right paddle in Pong
+        default_buttons: &[
+            &[("dpup", 0x2), ("dpleft", 0x4), ("dpright", 0x6), ("dpdown", 0x8), ("a", 0x5)], 
+            &[("dpup", 0xc), ("dpdown", 0xd), ("a", 0xf)], 
+        ], 
+        text_input: false, 
+        create: create_chip8, 
+    }, 
+    SystemInfo {
+        name: "riscv", 
+        description: "RISC-V RV32IM", 
+        extensions: &["elf", "bin", "rv32"], 
+        // ~3 MHz
+        ticks_per_frame: 50_000, 
+        // Typed text goes to the UART instead
+        key_presets: &[], 
+        keypad: &[], 
+        default_buttons: &[], 
+        text_input: true, 
+        create: create_riscv, 
+    }, 
+];
+
+fn create_chip8() -> Box<dyn Emulator> {
+    Box::new(chip8_emu::Chip8Emu::new())
+}
+
+fn create_riscv() -> Box<dyn Emulator> {
+    Box::new(riscv::RiscvEmu::new())
+}
+
+impl SystemInfo {
+    pub fn key_preset(self: &Self, name: &str) -> Option<&'static [(&'static str, u8)]> {
+        self.key_presets.iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, keys)| *keys)
+    }
+}
+
+pub fn find_system_by_name(name: &str) -> Option<&'static SystemInfo> {
+    SYSTEMS.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+}
+
+pub fn find_system_for_file(file_name: &str) -> Option<&'static SystemInfo> {
+    let ext = std::path::Path::new(file_name)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    SYSTEMS.iter().find(|s| s.extensions.contains(&ext.as_str()))
+}
 
-pub mod chip8_emu;
\ No newline at end of file
+pub mod chip8_emu;
+pub mod palette;
+pub mod riscv;
\ No newline at end of file
@@ -0,0 +1,137 @@
+//! Screen colours for cores that keep an indexed framebuffer.
+//!
+//! Entry 0 is the background, the rest are indexed by which bit planes are
+//! lit: 1 is plane 1 (plain CHIP-8 only has this one), 2 is plane 2 and 3
+//! both for 4-colour XO-CHIP, and up to 15 for 4 planes.
+
+use crate::config;
+
+// Palette entries a core can index, one per combination of 4 bit planes
+pub const MAX_COLORS: usize = 16;
+
+const fn rgb(color: u32) -> [u8; 4] {
+    [(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xff]
+}
+
+/**
+ * Fills the entries a preset or a short custom palette leaves out.
+ */
+pub const DEFAULT_COLORS: [[u8; 4]; MAX_COLORS] = [
+    rgb(0x000000), rgb(0xffffff), rgb(0xaaaaaa), rgb(0x555555),
+    rgb(0xff004d), rgb(0xffa300), rgb(0xffec27), rgb(0x00e436),
+    rgb(0x29adff), rgb(0x83769c), rgb(0xff77a8), rgb(0xffccaa),
+    rgb(0x1d2b53), rgb(0x7e2553), rgb(0x008751), rgb(0xab5236),
+];
+
+// Background, plane 1, plane 2, both planes
+pub const PALETTE_PRESETS: &[(&str, &[[u8; 4]])] = &[
+    ("mono", &[rgb(0x000000), rgb(0xffffff), rgb(0xaaaaaa), rgb(0x555555)]),
+    ("amber", &[rgb(0x1a0f00), rgb(0xffb000), rgb(0xa66f00), rgb(0xffd88a)]),
+    ("green", &[rgb(0x061a0b), rgb(0x33ff66), rgb(0x1f9940), rgb(0xb3ffc6)]),
+    // Original Game Boy screen, dark pixels on a light background
+    ("lcd", &[rgb(0x9bbc0f), rgb(0x0f380f), rgb(0x306230), rgb(0x8bac0f)]),
+    ("high-contrast", &[rgb(0x000000), rgb(0xffff00), rgb(0x00ffff), rgb(0xffffff)]),
+    // Okabe-Ito colours, told apart with any kind of colour blindness
+    ("colorblind", &[rgb(0x000000), rgb(0xe69f00), rgb(0x56b4e9), rgb(0xf0e442)]),
+];
+
+pub fn from_preset(name: &str) -> Option<&'static [[u8; 4]]> {
+    PALETTE_PRESETS.iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+        .map(|(_, colors)| *colors)
+}
+
+pub fn preset_names() -> Vec<&'static str> {
+    PALETTE_PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+/**
+ * A palette from the config or command line: either a single preset name or
+ * colours as accepted by `config::parse_color`, background first.
+ */
+pub fn parse_palette(entries: &[String]) -> Result<Vec<[u8; 4]>, String> {
+    if let [name] = entries {
+        if let Some(colors) = from_preset(name) {
+            return Ok(colors.to_vec());
+        }
+    }
+    if entries.len() > MAX_COLORS {
+        return Err(format!("A palette has at most {} colours, got {}", MAX_COLORS, entries.len()));
+    }
+    entries.iter()
+        .map(|entry| config::parse_color(entry).map_err(|_| format!(
+            "Invalid palette entry '{}', expected a colour or one of {}", entry, preset_names().join(", "))))
+        .collect()
+}
+
+/**
+ * `colors` padded to `MAX_COLORS` from `DEFAULT_COLORS`.
+ */
+pub fn expand(colors: &[[u8; 4]]) -> [[u8; 4]; MAX_COLORS] {
+    let mut palette = DEFAULT_COLORS;
+    for (entry, color) in palette.iter_mut().zip(colors) {
+        *entry = *color;
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_color;
+
+    fn entries(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#fff"), Ok([0xff, 0xff, 0xff, 0xff]));
+        assert_eq!(parse_color("#123"), Ok([0x11, 0x22, 0x33, 0xff]));
+        assert_eq!(parse_color("102030"), Ok([0x10, 0x20, 0x30, 0xff]));
+        assert_eq!(parse_color(" #AbCdEf "), Ok([0xab, 0xcd, 0xef, 0xff]));
+        assert_eq!(parse_color("#10203040"), Ok([0x10, 0x20, 0x30, 0x40]));
+
+        assert_eq!(parse_color("#12"), Err(String::from("Invalid colour '#12', expected #rrggbb or #rrggbbaa")));
+        for bad in ["", "#", "#1234", "#12345", "#1234567", "#123456789", "#12345g", "rgb(1,2,3)"] {
+            assert!(parse_color(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn presets_and_custom_palettes() {
+        assert_eq!(parse_palette(&entries(&["amber"])), Ok(from_preset("amber").unwrap().to_vec()));
+        assert_eq!(parse_palette(&entries(&["High-Contrast"])).unwrap()[1], rgb(0xffff00));
+        assert_eq!(parse_palette(&entries(&["#000", "#fff"])), Ok(vec![rgb(0x000000), rgb(0xffffff)]));
+        // A single colour is not a preset
+        assert_eq!(parse_palette(&entries(&["#102030"])), Ok(vec![rgb(0x102030)]));
+        assert_eq!(parse_palette(&[]), Ok(vec![]));
+
+        assert!(PALETTE_PRESETS.iter().all(|(_, colors)| colors.len() == 4));
+        assert_eq!(preset_names().len(), PALETTE_PRESETS.len());
+    }
+
+    #[test]
+    fn palette_errors() {
+        let err = parse_palette(&entries(&["#000", "bogus"])).unwrap_err();
+        assert!(err.starts_with("Invalid palette entry 'bogus', expected a colour or one of mono, amber"), "{}", err);
+        // Presets can't be mixed with colours
+        assert!(parse_palette(&entries(&["amber", "#fff"])).is_err());
+        assert_eq!(parse_palette(&vec![String::from("#000"); 17]),
+            Err(String::from("A palette has at most 16 colours, got 17")));
+        assert_eq!(parse_palette(&vec![String::from("#000"); 16]).map(|p| p.len()), Ok(16));
+    }
+
+    #[test]
+    fn expand_pads_from_the_defaults() {
+        let mono = from_preset("mono").unwrap();
+        let amber = from_preset("amber").unwrap();
+        let palette = expand(amber);
+        assert_eq!(palette[..4], *amber);
+        assert_eq!(palette[4..], DEFAULT_COLORS[4..]);
+        assert_eq!(mono[..], DEFAULT_COLORS[..4]);
+
+        assert_eq!(expand(&[]), DEFAULT_COLORS);
+        assert_eq!(expand(&[rgb(0x123456); 20]), [rgb(0x123456); MAX_COLORS]);
+    }
+}
@@ -0,0 +1,1247 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use super::{ EmuTrait, ScreenResolution, CpuInfo, KeyboardDriver, RegisterInfo, RegisterSize };
+
+/**
+ * Memory map
+ *
+ * 0x1000_0000 - 0x1000_0007   16550 UART (THR/RBR, IER, IIR/FCR, LCR, MCR, LSR, MSR, SCR)
+ * 0x2000_0000 - 0x2004_afff   RGBA framebuffer, FB_WIDTH x FB_HEIGHT, 4 bytes per pixel
+ * 0x8000_0000 - 0x80ff_ffff   RAM, flat binaries are loaded at the start of RAM
+ */
+pub const RAM_BASE: u32 = 0x8000_0000;
+const RAM_SIZE: u32 = 0x0100_0000;         // 16 MiB
+pub const UART_BASE: u32 = 0x1000_0000;
+const UART_SIZE: u32 = 0x8;
+pub const FB_BASE: u32 = 0x2000_0000;
+pub const FB_WIDTH: u32 = 320;
+pub const FB_HEIGHT: u32 = 240;
+const FB_SIZE: u32 = FB_WIDTH * FB_HEIGHT * 4;
+
+const CAUSE_MISALIGNED_FETCH: u32 = 0;
+const CAUSE_FETCH_ACCESS: u32 = 1;
+const CAUSE_ILLEGAL_INSTR: u32 = 2;
+const CAUSE_BREAKPOINT: u32 = 3;
+const CAUSE_LOAD_ACCESS: u32 = 5;
+const CAUSE_STORE_ACCESS: u32 = 7;
+const CAUSE_MACHINE_ECALL: u32 = 11;
+
+const CSR_MSTATUS: u16 = 0x300;
+const CSR_MISA: u16 = 0x301;
+const CSR_MTVEC: u16 = 0x305;
+const CSR_MEPC: u16 = 0x341;
+const CSR_MCAUSE: u16 = 0x342;
+const CSR_MTVAL: u16 = 0x343;
+const CSR_MCYCLE: u16 = 0xb00;
+const CSR_MINSTRET: u16 = 0xb02;
+const CSR_MCYCLEH: u16 = 0xb80;
+const CSR_MINSTRETH: u16 = 0xb82;
+const CSR_CYCLE: u16 = 0xc00;
+const CSR_TIME: u16 = 0xc01;
+const CSR_INSTRET: u16 = 0xc02;
+const CSR_CYCLEH: u16 = 0xc80;
+const CSR_TIMEH: u16 = 0xc81;
+const CSR_INSTRETH: u16 = 0xc82;
+const CSR_MHARTID: u16 = 0xf14;
+
+const MSTATUS_MIE: u32 = 1 << 3;
+const MSTATUS_MPIE: u32 = 1 << 7;
+const MSTATUS_MPP: u32 = 3 << 11;
+// RV32 + I + M
+const MISA_RV32IM: u32 = (1 << 30) | (1 << 8) | (1 << 12);
+
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+struct Trap {
+    cause: u32,
+    tval: u32,
+}
+
+/**
+ * Minimal 16550 UART. Transmitted bytes are captured instead of being
+ * sent anywhere, received bytes are queued with `push_uart_input`.
+ */
+struct Uart16550 {
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    rx_fifo: VecDeque<u8>,
+    tx_output: Vec<u8>,
+}
+
+impl Uart16550 {
+    fn new() -> Uart16550 {
+        Uart16550 {
+            ier: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            rx_fifo: VecDeque::new(),
+            tx_output: Vec::new(),
+        }
+    }
+
+    fn is_dlab(self: &Self) -> bool {
+        (self.lcr & 0x80) != 0
+    }
+
+    fn read(self: &mut Self, offset: u32) -> u8 {
+        match offset {
+            0 if self.is_dlab() => self.dll,
+            0 => self.rx_fifo.pop_front().unwrap_or(0),
+            1 if self.is_dlab() => self.dlm,
+            1 => self.ier,
+            // IIR: no interrupt pending, FIFOs enabled
+            2 => 0xc1,
+            3 => self.lcr,
+            4 => self.mcr,
+            // LSR: THR empty + transmitter idle, data ready if anything is queued
+            5 => 0x60 | (!self.rx_fifo.is_empty() as u8),
+            // MSR: CTS, DSR and DCD asserted
+            6 => 0xb0,
+            7 => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write(self: &mut Self, offset: u32, val: u8) {
+        match offset {
+            0 if self.is_dlab() => self.dll = val,
+            0 => self.tx_output.push(val),
+            1 if self.is_dlab() => self.dlm = val,
+            1 => self.ier = val & 0x0f,
+            // FCR: bit 1 clears the receive FIFO
+            2 if (val & 0x02) != 0 => self.rx_fifo.clear(),
+            3 => self.lcr = val,
+            4 => self.mcr = val,
+            7 => self.scr = val,
+            _ => {},
+        }
+    }
+}
+
+pub struct RiscvEmu {
+    ram: Vec<u8>,
+    framebuffer: Vec<u8>,
+    uart: Uart16550,
+    x: [u32; 32],
+    program_counter: u32,
+    csr: Vec<u32>,
+    cycle: u64,
+    instret: u64,
+
+    is_running: bool,
+    // HTIF style exit: the value written to the `tohost` symbol, if the ELF has one
+    tohost_addr: Option<u32>,
+    tohost_value: Option<u32>,
+
+    curr_instr: u32,
+}
+
+impl CpuInfo for RiscvEmu {
+    fn get_data_registers(self: &Self) -> Vec<RegisterInfo> {
+        let mut c_info = Vec::<RegisterInfo>::new();
+        for i in 0..32 {
+            c_info.push(RegisterInfo {
+                reg_size_bits: RegisterSize::RegSize32,
+                reg_value: self.x[i] as u64,
+            });
+        }
+        c_info.push(RegisterInfo {
+            reg_size_bits: RegisterSize::RegSize32,
+            reg_value: self.program_counter as u64,
+        });
+        c_info
+    }
+
+    fn get_current_instr(self: &Self) -> String {
+        self.translate_instr(self.curr_instr)
+    }
+
+    fn get_next_instr(self: &Self) -> String {
+        match self.peek_u32(self.program_counter) {
+            Some(instr) => self.translate_instr(instr),
+            None => String::from("INVALID FETCH"),
+        }
+    }
+
+    fn get_program_counter(self: &Self) -> u64 {
+        self.program_counter as u64
+    }
+
+    fn get_memory_range(self: &Self) -> Option<Range<u64>> {
+        Some(RAM_BASE as u64..RAM_BASE as u64 + RAM_SIZE as u64)
+    }
+
+    fn peek(self: &Self, addr: u64) -> Option<u8> {
+        self.ram.get(addr.checked_sub(RAM_BASE as u64)? as usize).copied()
+    }
+
+    fn poke(self: &mut Self, addr: u64, value: u8) -> Result<(), String> {
+        let byte = addr.checked_sub(RAM_BASE as u64).and_then(|offset| self.ram.get_mut(offset as usize));
+        match byte {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            },
+            None => Err(format!("No RAM at 0x{:X}", addr)),
+        }
+    }
+
+    fn get_opcode(self: &Self, addr: u64) -> Option<u64> {
+        self.peek_u32(u32::try_from(addr).ok()?).map(|instr| instr as u64)
+    }
+
+    fn get_branch_target(self: &Self, addr: u64) -> Option<u64> {
+        let pc = u32::try_from(addr).ok()?;
+        let instr = self.peek_u32(pc)?;
+        let offset = match instr & 0x7f {
+            // JAL, JALR depends on a register
+            0x6f => imm_j(instr),
+            // BRANCH
+            0x63 => imm_b(instr),
+            _ => return None,
+        };
+        Some(pc.wrapping_add(offset) as u64)
+    }
+
+    fn disassemble(self: &Self, addr: u64) -> Option<(String, u64)> {
+        let instr = self.peek_u32(u32::try_from(addr).ok()?)?;
+        Some((self.translate_instr(instr), 4))
+    }
+}
+
+/**
+ * Key presses arrive on the UART as the ASCII byte of the key.
+ */
+impl KeyboardDriver for RiscvEmu {
+    fn on_key_press(self: &mut Self, key: u8) {
+        self.uart.rx_fifo.push_back(key);
+    }
+
+    fn on_key_release(self: &mut Self, _key: u8) {
+    }
+}
+
+impl EmuTrait for RiscvEmu {
+    fn start(self: &mut Self) {
+        self.is_running = true;
+    }
+
+    fn stop(self: &mut Self) {
+        self.is_running = false;
+    }
+
+    fn pause(self: &mut Self) {
+        self.is_running = false;
+    }
+
+    fn resume(self: &mut Self) {
+        self.is_running = true;
+    }
+
+    /**
+     * ELF32 files are loaded by their program headers, anything else is
+     * treated as a flat binary and copied to the start of RAM.
+     */
+    fn load_data(self: &mut Self, data: &[u8]) -> Result<(), String> {
+        self.reset();
+        self.is_running = false;
+        if data.starts_with(b"\x7fELF") {
+            self.load_elf(data)
+        } else {
+            self.load_flat_binary(data)
+        }
+    }
+
+    fn get_screen_resolution(self: &Self) -> ScreenResolution {
+        ScreenResolution {
+            width: FB_WIDTH,
+            height: FB_HEIGHT,
+        }
+    }
+
+    fn get_cpu_screen_resolution(self: &Self) -> ScreenResolution {
+        ScreenResolution {
+            // 24 chars for the longest instruction (8 hex chars per register)
+            // * 8 pixels per char
+            // + 2 pixel space per char
+            width: 240,
+            // 1 row for each of x0 - x31
+            // + 1 row for pc
+            // + 2 rows for current and next instruction
+            // + 2 rows for the debugger status, wrapped
+            // * 10 pixels per row
+            height: 370,
+        }
+    }
+
+    fn draw_to_buffer_rgba(self: &Self, buf: &mut Vec<u8>, target_res: &ScreenResolution)
+        -> Result<bool, bool> {
+
+        if target_res.width < FB_WIDTH || target_res.height < FB_HEIGHT
+            || buf.len() < (target_res.width * target_res.height * 4) as usize {
+            return Err(false);
+        }
+        let pixel_width = target_res.width / FB_WIDTH;
+        let pixel_height = target_res.height / FB_HEIGHT;
+
+        for y in 0..target_res.height {
+            let fb_y = (y / pixel_height).min(FB_HEIGHT - 1);
+            for x in 0..target_res.width {
+                let fb_x = (x / pixel_width).min(FB_WIDTH - 1);
+                let src = ((fb_y * FB_WIDTH + fb_x) * 4) as usize;
+                let dst = ((y * target_res.width + x) * 4) as usize;
+                buf[dst..dst + 4].copy_from_slice(&self.framebuffer[src..src + 4]);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn tick(self: &mut Self) {
+        if self.tohost_value.is_some() {
+            return;
+        }
+        let pc = self.program_counter;
+        let result = self.fetch_instr(pc).and_then(|instr| {
+            self.curr_instr = instr;
+            self.exec_instr(instr)
+        });
+        match result {
+            Ok(next_pc) => {
+                self.program_counter = next_pc;
+                self.instret += 1;
+            },
+            Err(trap) => self.take_trap(trap, pc),
+        }
+        self.x[0] = 0;
+        self.cycle += 1;
+    }
+
+    /**
+     * Set once the program writes a non-zero value to `tohost`, by the HTIF
+     * convention `tohost = (code << 1) | 1`. With riscv-tests 0 means pass,
+     * otherwise it is the number of the failing test.
+     */
+    fn get_exit_code(self: &Self) -> Option<u32> {
+        self.tohost_value.map(|val| val >> 1)
+    }
+}
+
+impl Default for RiscvEmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RiscvEmu {
+    pub fn new() -> RiscvEmu {
+        let mut emu = RiscvEmu {
+            ram: vec![0; RAM_SIZE as usize],
+            framebuffer: vec![0; FB_SIZE as usize],
+            uart: Uart16550::new(),
+            x: [0; 32],
+            program_counter: RAM_BASE,
+            csr: vec![0; 0x1000],
+            cycle: 0,
+            instret: 0,
+
+            is_running: false,
+            tohost_addr: None,
+            tohost_value: None,
+
+            curr_instr: 0,
+        };
+        emu.reset();
+        emu
+    }
+
+    pub fn reset(self: &mut Self) {
+        self.ram.iter_mut().for_each(|b| *b = 0);
+        self.framebuffer.iter_mut().for_each(|b| *b = 0);
+        self.uart = Uart16550::new();
+        self.x = [0; 32];
+        self.program_counter = RAM_BASE;
+        self.csr.iter_mut().for_each(|c| *c = 0);
+        self.csr[CSR_MISA as usize] = MISA_RV32IM;
+        self.csr[CSR_MSTATUS as usize] = MSTATUS_MPP;
+        self.cycle = 0;
+        self.instret = 0;
+        self.tohost_addr = None;
+        self.tohost_value = None;
+        self.curr_instr = 0;
+    }
+
+    /**
+     * Everything the program has written to the UART so far.
+     */
+    pub fn uart_output(self: &Self) -> String {
+        String::from_utf8_lossy(&self.uart.tx_output).into_owned()
+    }
+
+    pub fn push_uart_input(self: &mut Self, data: &[u8]) {
+        self.uart.rx_fifo.extend(data);
+    }
+
+    pub fn load_flat_binary(self: &mut Self, data: &[u8]) -> Result<(), String> {
+        self.write_ram(RAM_BASE, data)?;
+        self.program_counter = RAM_BASE;
+        Ok(())
+    }
+
+    pub fn load_elf(self: &mut Self, data: &[u8]) -> Result<(), String> {
+        if data.len() < 52 || !data.starts_with(b"\x7fELF") {
+            return Err(String::from("Not an ELF file"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(String::from("Only little endian ELF32 files are supported"));
+        }
+        if read_u16_le(data, 18)? != 0xf3 {
+            return Err(String::from("ELF file is not a RISC-V executable"));
+        }
+
+        let entry = read_u32_le(data, 24)?;
+        let ph_off = read_u32_le(data, 28)? as usize;
+        let sh_off = read_u32_le(data, 32)? as usize;
+        let ph_entsize = read_u16_le(data, 42)? as usize;
+        let ph_num = read_u16_le(data, 44)? as usize;
+        let sh_entsize = read_u16_le(data, 46)? as usize;
+        let sh_num = read_u16_le(data, 48)? as usize;
+
+        for i in 0..ph_num {
+            let ph = ph_off + i * ph_entsize;
+            // PT_LOAD
+            if read_u32_le(data, ph)? != 1 {
+                continue;
+            }
+            let offset = read_u32_le(data, ph + 4)? as usize;
+            let paddr = read_u32_le(data, ph + 12)?;
+            let file_size = read_u32_le(data, ph + 16)? as usize;
+            let mem_size = read_u32_le(data, ph + 20)? as usize;
+            let segment = data.get(offset..offset + file_size)
+                .ok_or(String::from("ELF segment is out of bounds"))?;
+            // Checked before anything is written, the sizes come from the file
+            let range = ram_range(paddr, mem_size.max(file_size))?;
+            self.ram[range.start..range.start + file_size].copy_from_slice(segment);
+            self.ram[range.start + file_size..range.end].fill(0);
+        }
+
+        for i in 0..sh_num {
+            let sh = sh_off + i * sh_entsize;
+            // SHT_SYMTAB
+            if read_u32_le(data, sh + 4)? != 2 {
+                continue;
+            }
+            let sym_off = read_u32_le(data, sh + 16)? as usize;
+            let sym_size = read_u32_le(data, sh + 20)? as usize;
+            let str_sh = sh_off + read_u32_le(data, sh + 24)? as usize * sh_entsize;
+            let str_off = read_u32_le(data, str_sh + 16)? as usize;
+            for sym in (sym_off..sym_off + sym_size).step_by(16) {
+                let name_off = str_off + read_u32_le(data, sym)? as usize;
+                if data.get(name_off..name_off + 7) == Some(b"tohost\0".as_slice()) {
+                    self.tohost_addr = Some(read_u32_le(data, sym + 4)?);
+                }
+            }
+        }
+
+        self.program_counter = entry;
+        Ok(())
+    }
+
+    fn write_ram(self: &mut Self, addr: u32, data: &[u8]) -> Result<(), String> {
+        let range = ram_range(addr, data.len())?;
+        self.ram[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    /**
+     * Side effect free RAM read, used for disassembly.
+     */
+    fn peek_u32(self: &Self, addr: u32) -> Option<u32> {
+        let start = addr.wrapping_sub(RAM_BASE) as usize;
+        if addr < RAM_BASE || start + 4 > RAM_SIZE as usize {
+            return None;
+        }
+        read_u32_le(&self.ram, start).ok()
+    }
+
+    /**
+     * Bus
+     */
+    fn load(self: &mut Self, addr: u32, size: u32) -> Result<u32, Trap> {
+        let mut val: u32 = 0;
+        if addr >= RAM_BASE && addr - RAM_BASE + size <= RAM_SIZE {
+            let start = (addr - RAM_BASE) as usize;
+            for i in (0..size as usize).rev() {
+                val = (val << 8) | self.ram[start + i] as u32;
+            }
+        } else if addr >= UART_BASE && addr - UART_BASE < UART_SIZE {
+            val = self.uart.read(addr - UART_BASE) as u32;
+        } else if addr >= FB_BASE && addr - FB_BASE + size <= FB_SIZE {
+            let start = (addr - FB_BASE) as usize;
+            for i in (0..size as usize).rev() {
+                val = (val << 8) | self.framebuffer[start + i] as u32;
+            }
+        } else {
+            return Err(Trap { cause: CAUSE_LOAD_ACCESS, tval: addr });
+        }
+        Ok(val)
+    }
+
+    fn store(self: &mut Self, addr: u32, size: u32, val: u32) -> Result<(), Trap> {
+        if addr >= RAM_BASE && addr - RAM_BASE + size <= RAM_SIZE {
+            let start = (addr - RAM_BASE) as usize;
+            for i in 0..size as usize {
+                self.ram[start + i] = (val >> (i * 8)) as u8;
+            }
+            if Some(addr) == self.tohost_addr && val != 0 {
+                self.tohost_value = Some(val);
+            }
+        } else if addr >= UART_BASE && addr - UART_BASE < UART_SIZE {
+            self.uart.write(addr - UART_BASE, val as u8);
+        } else if addr >= FB_BASE && addr - FB_BASE + size <= FB_SIZE {
+            let start = (addr - FB_BASE) as usize;
+            for i in 0..size as usize {
+                self.framebuffer[start + i] = (val >> (i * 8)) as u8;
+            }
+        } else {
+            return Err(Trap { cause: CAUSE_STORE_ACCESS, tval: addr });
+        }
+        Ok(())
+    }
+
+    /**
+     * Instructions only come from RAM, fetching from a device would have its
+     * side effects, e.g. popping the UART receive FIFO.
+     */
+    fn fetch_instr(self: &Self, pc: u32) -> Result<u32, Trap> {
+        if (pc & 0x3) != 0 {
+            return Err(Trap { cause: CAUSE_MISALIGNED_FETCH, tval: pc });
+        }
+        self.peek_u32(pc).ok_or(Trap { cause: CAUSE_FETCH_ACCESS, tval: pc })
+    }
+
+    fn take_trap(self: &mut Self, trap: Trap, pc: u32) {
+        let mstatus = self.csr[CSR_MSTATUS as usize];
+        let mpie = if (mstatus & MSTATUS_MIE) != 0 { MSTATUS_MPIE } else { 0 };
+        self.csr[CSR_MSTATUS as usize] = (mstatus & !(MSTATUS_MIE | MSTATUS_MPIE)) | mpie | MSTATUS_MPP;
+        self.csr[CSR_MEPC as usize] = pc;
+        self.csr[CSR_MCAUSE as usize] = trap.cause;
+        self.csr[CSR_MTVAL as usize] = trap.tval;
+        self.program_counter = self.csr[CSR_MTVEC as usize] & !0x3;
+    }
+
+    /**
+     * CSRs
+     */
+    fn read_csr(self: &Self, csr: u16) -> u32 {
+        match csr {
+            CSR_CYCLE | CSR_MCYCLE | CSR_TIME => self.cycle as u32,
+            CSR_CYCLEH | CSR_MCYCLEH | CSR_TIMEH => (self.cycle >> 32) as u32,
+            CSR_INSTRET | CSR_MINSTRET => self.instret as u32,
+            CSR_INSTRETH | CSR_MINSTRETH => (self.instret >> 32) as u32,
+            CSR_MHARTID => 0,
+            _ => self.csr[csr as usize],
+        }
+    }
+
+    fn write_csr(self: &mut Self, csr: u16, val: u32) -> Result<(), Trap> {
+        // csr[11:10] == 0b11 marks read-only CSRs
+        if (csr >> 10) == 0x3 {
+            return Err(Trap { cause: CAUSE_ILLEGAL_INSTR, tval: self.curr_instr });
+        }
+        match csr {
+            CSR_MCYCLE => self.cycle = (self.cycle & !0xffff_ffff) | val as u64,
+            CSR_MCYCLEH => self.cycle = (self.cycle & 0xffff_ffff) | ((val as u64) << 32),
+            CSR_MINSTRET => self.instret = (self.instret & !0xffff_ffff) | val as u64,
+            CSR_MINSTRETH => self.instret = (self.instret & 0xffff_ffff) | ((val as u64) << 32),
+            // misa is WARL, we only ever implement RV32IM
+            CSR_MISA => {},
+            CSR_MEPC => self.csr[csr as usize] = val & !0x3,
+            _ => self.csr[csr as usize] = val,
+        }
+        Ok(())
+    }
+
+    /**
+     * Executes one instruction and returns the next pc
+     */
+    fn exec_instr(self: &mut Self, instr: u32) -> Result<u32, Trap> {
+        let pc = self.program_counter;
+        let opcode = instr & 0x7f;
+        let rd = ((instr >> 7) & 0x1f) as usize;
+        let funct3 = (instr >> 12) & 0x7;
+        let rs1 = ((instr >> 15) & 0x1f) as usize;
+        let rs2 = ((instr >> 20) & 0x1f) as usize;
+        let funct7 = instr >> 25;
+        let illegal = Trap { cause: CAUSE_ILLEGAL_INSTR, tval: instr };
+
+        match opcode {
+            // LUI
+            0x37 => self.x[rd] = imm_u(instr),
+            // AUIPC
+            0x17 => self.x[rd] = pc.wrapping_add(imm_u(instr)),
+            // JAL
+            0x6f => {
+                let target = pc.wrapping_add(imm_j(instr));
+                return self.jump(rd, target);
+            },
+            // JALR
+            0x67 if funct3 == 0 => {
+                let target = self.x[rs1].wrapping_add(imm_i(instr)) & !0x1;
+                return self.jump(rd, target);
+            },
+            // BRANCH
+            0x63 => {
+                let a = self.x[rs1];
+                let b = self.x[rs2];
+                let taken = match funct3 {
+                    0x0 => a == b,
+                    0x1 => a != b,
+                    0x4 => (a as i32) < (b as i32),
+                    0x5 => (a as i32) >= (b as i32),
+                    0x6 => a < b,
+                    0x7 => a >= b,
+                    _ => return Err(illegal),
+                };
+                if taken {
+                    let target = pc.wrapping_add(imm_b(instr));
+                    if (target & 0x3) != 0 {
+                        return Err(Trap { cause: CAUSE_MISALIGNED_FETCH, tval: target });
+                    }
+                    return Ok(target);
+                }
+            },
+            // LOAD
+            0x03 => {
+                let addr = self.x[rs1].wrapping_add(imm_i(instr));
+                let val = match funct3 {
+                    0x0 => self.load(addr, 1)? as i8 as i32 as u32,
+                    0x1 => self.load(addr, 2)? as i16 as i32 as u32,
+                    0x2 => self.load(addr, 4)?,
+                    0x4 => self.load(addr, 1)?,
+                    0x5 => self.load(addr, 2)?,
+                    _ => return Err(illegal),
+                };
+                self.x[rd] = val;
+            },
+            // STORE
+            0x23 => {
+                let addr = self.x[rs1].wrapping_add(imm_s(instr));
+                match funct3 {
+                    0x0 => self.store(addr, 1, self.x[rs2])?,
+                    0x1 => self.store(addr, 2, self.x[rs2])?,
+                    0x2 => self.store(addr, 4, self.x[rs2])?,
+                    _ => return Err(illegal),
+                }
+            },
+            // OP-IMM
+            0x13 => {
+                let a = self.x[rs1];
+                let imm = imm_i(instr);
+                let shamt = rs2 as u32;
+                self.x[rd] = match (funct3, funct7) {
+                    (0x0, _) => a.wrapping_add(imm),
+                    (0x2, _) => ((a as i32) < (imm as i32)) as u32,
+                    (0x3, _) => (a < imm) as u32,
+                    (0x4, _) => a ^ imm,
+                    (0x6, _) => a | imm,
+                    (0x7, _) => a & imm,
+                    (0x1, 0x00) => a << shamt,
+                    (0x5, 0x00) => a >> shamt,
+                    (0x5, 0x20) => ((a as i32) >> shamt) as u32,
+                    _ => return Err(illegal),
+                };
+            },
+            // OP
+            0x33 => {
+                let a = self.x[rs1];
+                let b = self.x[rs2];
+                self.x[rd] = match (funct7, funct3) {
+                    (0x00, 0x0) => a.wrapping_add(b),
+                    (0x20, 0x0) => a.wrapping_sub(b),
+                    (0x00, 0x1) => a << (b & 0x1f),
+                    (0x00, 0x2) => ((a as i32) < (b as i32)) as u32,
+                    (0x00, 0x3) => (a < b) as u32,
+                    (0x00, 0x4) => a ^ b,
+                    (0x00, 0x5) => a >> (b & 0x1f),
+                    (0x20, 0x5) => ((a as i32) >> (b & 0x1f)) as u32,
+                    (0x00, 0x6) => a | b,
+                    (0x00, 0x7) => a & b,
+                    // M extension
+                    (0x01, 0x0) => a.wrapping_mul(b),
+                    (0x01, 0x1) => ((a as i32 as i64 * b as i32 as i64) >> 32) as u32,
+                    (0x01, 0x2) => ((a as i32 as i64 * b as i64) >> 32) as u32,
+                    (0x01, 0x3) => ((a as u64 * b as u64) >> 32) as u32,
+                    (0x01, 0x4) => {
+                        if b == 0 { u32::MAX } else { (a as i32).wrapping_div(b as i32) as u32 }
+                    },
+                    (0x01, 0x5) => a.checked_div(b).unwrap_or(u32::MAX),
+                    (0x01, 0x6) => {
+                        if b == 0 { a } else { (a as i32).wrapping_rem(b as i32) as u32 }
+                    },
+                    (0x01, 0x7) => a.checked_rem(b).unwrap_or(a),
+                    _ => return Err(illegal),
+                };
+            },
+            // MISC-MEM: FENCE and FENCE.I, there is no cache or reordering to deal with
+            0x0f if funct3 <= 0x1 => {},
+            // SYSTEM
+            0x73 => {
+                match (funct3, instr) {
+                    (0x0, 0x0000_0073) => {
+                        return Err(Trap { cause: CAUSE_MACHINE_ECALL, tval: 0 });
+                    },
+                    (0x0, 0x0010_0073) => {
+                        return Err(Trap { cause: CAUSE_BREAKPOINT, tval: pc });
+                    },
+                    // MRET
+                    (0x0, 0x3020_0073) => {
+                        let mstatus = self.csr[CSR_MSTATUS as usize];
+                        let mie = if (mstatus & MSTATUS_MPIE) != 0 { MSTATUS_MIE } else { 0 };
+                        self.csr[CSR_MSTATUS as usize] = (mstatus & !MSTATUS_MIE) | mie | MSTATUS_MPIE;
+                        return Ok(self.csr[CSR_MEPC as usize]);
+                    },
+                    // WFI, nothing can interrupt us so just carry on
+                    (0x0, 0x1050_0073) => {},
+                    (0x1..=0x3, _) | (0x5..=0x7, _) => {
+                        let csr = (instr >> 20) as u16;
+                        let src = if funct3 >= 0x5 { rs1 as u32 } else { self.x[rs1] };
+                        let old = self.read_csr(csr);
+                        let new_val = match funct3 & 0x3 {
+                            0x1 => Some(src),
+                            0x2 if rs1 != 0 => Some(old | src),
+                            0x3 if rs1 != 0 => Some(old & !src),
+                            _ => None,
+                        };
+                        if let Some(val) = new_val {
+                            self.write_csr(csr, val)?;
+                        }
+                        self.x[rd] = old;
+                    },
+                    _ => return Err(illegal),
+                }
+            },
+            _ => return Err(illegal),
+        }
+        Ok(pc.wrapping_add(4))
+    }
+
+    fn jump(self: &mut Self, rd: usize, target: u32) -> Result<u32, Trap> {
+        if (target & 0x3) != 0 {
+            return Err(Trap { cause: CAUSE_MISALIGNED_FETCH, tval: target });
+        }
+        self.x[rd] = self.program_counter.wrapping_add(4);
+        Ok(target)
+    }
+
+    fn translate_instr(self: &Self, instr: u32) -> String {
+        let opcode = instr & 0x7f;
+        let rd = ABI_NAMES[((instr >> 7) & 0x1f) as usize];
+        let funct3 = (instr >> 12) & 0x7;
+        let rs1 = ABI_NAMES[((instr >> 15) & 0x1f) as usize];
+        let rs2 = ABI_NAMES[((instr >> 20) & 0x1f) as usize];
+        let funct7 = instr >> 25;
+
+        let name = match (opcode, funct3, funct7) {
+            (0x37, _, _) => return format!("lui {}, 0x{:x}", rd, instr >> 12),
+            (0x17, _, _) => return format!("auipc {}, 0x{:x}", rd, instr >> 12),
+            (0x6f, _, _) => return format!("jal {}, {}", rd, imm_j(instr) as i32),
+            (0x67, 0x0, _) => return format!("jalr {}, {}({})", rd, imm_i(instr) as i32, rs1),
+            (0x63, _, _) => {
+                let name = match funct3 {
+                    0x0 => "beq", 0x1 => "bne", 0x4 => "blt",
+                    0x5 => "bge", 0x6 => "bltu", 0x7 => "bgeu",
+                    _ => return format!("INVALID: 0x{:08x}", instr),
+                };
+                return format!("{} {}, {}, {}", name, rs1, rs2, imm_b(instr) as i32);
+            },
+            (0x03, _, _) => {
+                let name = match funct3 {
+                    0x0 => "lb", 0x1 => "lh", 0x2 => "lw", 0x4 => "lbu", 0x5 => "lhu",
+                    _ => return format!("INVALID: 0x{:08x}", instr),
+                };
+                return format!("{} {}, {}({})", name, rd, imm_i(instr) as i32, rs1);
+            },
+            (0x23, _, _) => {
+                let name = match funct3 {
+                    0x0 => "sb", 0x1 => "sh", 0x2 => "sw",
+                    _ => return format!("INVALID: 0x{:08x}", instr),
+                };
+                return format!("{} {}, {}({})", name, rs2, imm_s(instr) as i32, rs1);
+            },
+            (0x13, 0x1, _) => return format!("slli {}, {}, {}", rd, rs1, (instr >> 20) & 0x1f),
+            (0x13, 0x5, 0x00) => return format!("srli {}, {}, {}", rd, rs1, (instr >> 20) & 0x1f),
+            (0x13, 0x5, 0x20) => return format!("srai {}, {}, {}", rd, rs1, (instr >> 20) & 0x1f),
+            (0x13, _, _) => {
+                let name = match funct3 {
+                    0x0 => "addi", 0x2 => "slti", 0x3 => "sltiu",
+                    0x4 => "xori", 0x6 => "ori", 0x7 => "andi",
+                    _ => return format!("INVALID: 0x{:08x}", instr),
+                };
+                return format!("{} {}, {}, {}", name, rd, rs1, imm_i(instr) as i32);
+            },
+            (0x33, 0x0, 0x00) => "add",
+            (0x33, 0x0, 0x20) => "sub",
+            (0x33, 0x1, 0x00) => "sll",
+            (0x33, 0x2, 0x00) => "slt",
+            (0x33, 0x3, 0x00) => "sltu",
+            (0x33, 0x4, 0x00) => "xor",
+            (0x33, 0x5, 0x00) => "srl",
+            (0x33, 0x5, 0x20) => "sra",
+            (0x33, 0x6, 0x00) => "or",
+            (0x33, 0x7, 0x00) => "and",
+            (0x33, 0x0, 0x01) => "mul",
+            (0x33, 0x1, 0x01) => "mulh",
+            (0x33, 0x2, 0x01) => "mulhsu",
+            (0x33, 0x3, 0x01) => "mulhu",
+            (0x33, 0x4, 0x01) => "div",
+            (0x33, 0x5, 0x01) => "divu",
+            (0x33, 0x6, 0x01) => "rem",
+            (0x33, 0x7, 0x01) => "remu",
+            (0x0f, 0x0, _) => return String::from("fence"),
+            (0x0f, 0x1, _) => return String::from("fence.i"),
+            (0x73, 0x0, _) => {
+                return match instr {
+                    0x0000_0073 => String::from("ecall"),
+                    0x0010_0073 => String::from("ebreak"),
+                    0x3020_0073 => String::from("mret"),
+                    0x1050_0073 => String::from("wfi"),
+                    _ => format!("INVALID: 0x{:08x}", instr),
+                };
+            },
+            (0x73, _, _) => {
+                let name = match funct3 {
+                    0x1 => "csrrw", 0x2 => "csrrs", 0x3 => "csrrc",
+                    0x5 => "csrrwi", 0x6 => "csrrsi", 0x7 => "csrrci",
+                    _ => return format!("INVALID: 0x{:08x}", instr),
+                };
+                let csr = instr >> 20;
+                if funct3 >= 0x5 {
+                    return format!("{} {}, 0x{:03x}, {}", name, rd, csr, (instr >> 15) & 0x1f);
+                }
+                return format!("{} {}, 0x{:03x}, {}", name, rd, csr, rs1);
+            },
+            _ => return format!("INVALID: 0x{:08x}", instr),
+        };
+        format!("{} {}, {}, {}", name, rd, rs1, rs2)
+    }
+}
+
+/**
+ * Immediate decoding
+ */
+fn imm_i(instr: u32) -> u32 {
+    ((instr as i32) >> 20) as u32
+}
+
+fn imm_s(instr: u32) -> u32 {
+    ((((instr as i32) >> 25) << 5) as u32) | ((instr >> 7) & 0x1f)
+}
+
+fn imm_b(instr: u32) -> u32 {
+    ((((instr as i32) >> 31) << 12) as u32)
+        | ((instr << 4) & 0x800)
+        | ((instr >> 20) & 0x7e0)
+        | ((instr >> 7) & 0x1e)
+}
+
+fn imm_u(instr: u32) -> u32 {
+    instr & 0xffff_f000
+}
+
+fn imm_j(instr: u32) -> u32 {
+    ((((instr as i32) >> 31) << 20) as u32)
+        | (instr & 0xf_f000)
+        | ((instr >> 9) & 0x800)
+        | ((instr >> 20) & 0x7fe)
+}
+
+/**
+ * Offsets into `ram` of `len` bytes at `addr`, if they all fit.
+ */
+fn ram_range(addr: u32, len: usize) -> Result<Range<usize>, String> {
+    let start = addr.wrapping_sub(RAM_BASE) as usize;
+    if addr < RAM_BASE || len > RAM_SIZE as usize - start {
+        return Err(format!("0x{:08x} + 0x{:x} bytes does not fit in RAM", addr, len));
+    }
+    Ok(start..start + len)
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(format!("Read past the end of data at 0x{:x}", offset))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(format!("Read past the end of data at 0x{:x}", offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: u32 = RAM_BASE + 0x100;
+    const HANDLER: u32 = RAM_BASE + 0x200;
+
+    // Instruction encoders, registers are plain numbers
+    fn op(funct7: u32, funct3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+        (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | 0x33
+    }
+
+    fn imm(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+        ((imm as u32 & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+    }
+
+    fn store(funct3: u32, rs2: u32, rs1: u32, imm: i32) -> u32 {
+        let imm = imm as u32 & 0xfff;
+        ((imm >> 5) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | ((imm & 0x1f) << 7) | 0x23
+    }
+
+    fn csr(funct3: u32, rd: u32, csr: u16, rs1: u32) -> u32 {
+        ((csr as u32) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | 0x73
+    }
+
+    fn emu() -> RiscvEmu {
+        let mut emu = RiscvEmu::new();
+        emu.csr[CSR_MTVEC as usize] = HANDLER;
+        emu.start();
+        emu
+    }
+
+    /**
+     * Puts `instr` at the PC and runs it.
+     */
+    fn exec(emu: &mut RiscvEmu, instr: u32) {
+        emu.write_ram(emu.program_counter, &instr.to_le_bytes()).unwrap();
+        emu.tick();
+    }
+
+    fn assert_trap(emu: &RiscvEmu, cause: u32, tval: u32, pc: u32) {
+        assert_eq!(emu.program_counter, HANDLER);
+        assert_eq!(emu.csr[CSR_MCAUSE as usize], cause);
+        assert_eq!(emu.csr[CSR_MTVAL as usize], tval);
+        assert_eq!(emu.csr[CSR_MEPC as usize], pc);
+    }
+
+    #[test]
+    fn alu() {
+        let mut emu = emu();
+        emu.x[1] = 5;
+        emu.x[2] = -3i32 as u32;
+        let cases = [
+            (op(0x00, 0x0, 3, 1, 2), 2),                    // add
+            (op(0x20, 0x0, 3, 1, 2), 8),                    // sub
+            (op(0x00, 0x2, 3, 2, 1), 1),                    // slt
+            (op(0x00, 0x3, 3, 2, 1), 0),                    // sltu
+            (op(0x20, 0x5, 3, 2, 1), 0xffff_ffff),          // sra
+            (op(0x00, 0x5, 3, 2, 1), 0x07ff_ffff),          // srl
+            (op(0x00, 0x1, 3, 1, 1), 0xa0),                 // sll
+            (imm(0x13, 0x0, 3, 1, -6), 0xffff_ffff),        // addi
+            (imm(0x13, 0x3, 3, 1, -1), 1),                  // sltiu, the immediate is sign extended
+            (imm(0x13, 0x4, 3, 1, 0x0f), 0x0a),             // xori
+            (0x1234_51b7, 0x1234_5000),                     // lui x3, 0x12345
+        ];
+        for (instr, expected) in cases {
+            exec(&mut emu, instr);
+            assert_eq!(emu.x[3], expected, "{}", emu.translate_instr(instr));
+        }
+
+        // auipc x3, 1
+        let pc = emu.program_counter;
+        exec(&mut emu, 0x0000_1197);
+        assert_eq!(emu.x[3], pc + 0x1000);
+        // addi x0, x1, 1
+        exec(&mut emu, imm(0x13, 0x0, 0, 1, 1));
+        assert_eq!(emu.x[0], 0);
+    }
+
+    #[test]
+    fn m_extension() {
+        let mut emu = emu();
+        let cases = [
+            // By zero
+            (0x4, 7, 0, u32::MAX),                          // div
+            (0x5, 7, 0, u32::MAX),                          // divu
+            (0x6, 7, 0, 7),                                 // rem
+            (0x7, 7, 0, 7),                                 // remu
+            // Overflow
+            (0x4, 0x8000_0000, u32::MAX, 0x8000_0000),
+            (0x6, 0x8000_0000, u32::MAX, 0),
+            // Rounds towards zero, the remainder takes the dividend's sign
+            (0x4, -7i32 as u32, 2, -3i32 as u32),
+            (0x6, -7i32 as u32, 2, -1i32 as u32),
+            (0x0, 0x1_0001, 0x1_0001, 0x2_0001),            // mul
+            (0x1, -2i32 as u32, 3, u32::MAX),               // mulh
+            (0x2, -2i32 as u32, 3, u32::MAX),               // mulhsu
+            (0x3, -2i32 as u32, 3, 2),                      // mulhu
+        ];
+        for (funct3, a, b, expected) in cases {
+            emu.x[1] = a;
+            emu.x[2] = b;
+            let instr = op(0x01, funct3, 3, 1, 2);
+            exec(&mut emu, instr);
+            assert_eq!(emu.x[3], expected, "{} with 0x{:x}, 0x{:x}", emu.translate_instr(instr), a, b);
+        }
+    }
+
+    #[test]
+    fn sign_extending_loads() {
+        let mut emu = emu();
+        emu.x[1] = DATA;
+        emu.x[2] = 0x8001_8080;
+        exec(&mut emu, store(0x2, 2, 1, 0));
+        let cases = [
+            (0x0, 0, 0xffff_ff80),                          // lb
+            (0x4, 0, 0x80),                                 // lbu
+            (0x1, 2, 0xffff_8001),                          // lh
+            (0x5, 2, 0x8001),                               // lhu
+            (0x2, 0, 0x8001_8080),                          // lw
+            // Misaligned accesses in RAM just work
+            (0x2, 1, 0x0080_0180),
+        ];
+        for (funct3, offset, expected) in cases {
+            let instr = imm(0x03, funct3, 3, 1, offset);
+            exec(&mut emu, instr);
+            assert_eq!(emu.x[3], expected, "{}", emu.translate_instr(instr));
+        }
+
+        // sb and sh only write their bytes
+        emu.x[2] = 0x1234_5678;
+        exec(&mut emu, store(0x0, 2, 1, 0));
+        exec(&mut emu, store(0x1, 2, 1, 2));
+        assert_eq!(emu.peek_u32(DATA), Some(0x5678_8078));
+    }
+
+    #[test]
+    fn access_faults() {
+        let mut emu = emu();
+        emu.x[3] = 0x55;
+
+        // Nothing mapped
+        emu.x[1] = 0x10;
+        exec(&mut emu, imm(0x03, 0x2, 3, 1, 0));
+        assert_trap(&emu, CAUSE_LOAD_ACCESS, 0x10, RAM_BASE);
+        assert_eq!(emu.x[3], 0x55);
+
+        let pc = emu.program_counter;
+        emu.x[1] = RAM_BASE - 4;
+        exec(&mut emu, store(0x2, 3, 1, 0));
+        assert_trap(&emu, CAUSE_STORE_ACCESS, RAM_BASE - 4, pc);
+
+        // Half in RAM
+        let pc = emu.program_counter;
+        emu.x[1] = RAM_BASE + RAM_SIZE - 2;
+        exec(&mut emu, imm(0x03, 0x2, 3, 1, 0));
+        assert_trap(&emu, CAUSE_LOAD_ACCESS, RAM_BASE + RAM_SIZE - 2, pc);
+
+        // jal x1, +2 doesn't link
+        let pc = emu.program_counter;
+        emu.x[1] = 0;
+        exec(&mut emu, (1 << 21) | (1 << 7) | 0x6f);
+        assert_trap(&emu, CAUSE_MISALIGNED_FETCH, pc + 2, pc);
+        assert_eq!(emu.x[1], 0);
+
+        emu.program_counter = RAM_BASE + 2;
+        emu.tick();
+        assert_trap(&emu, CAUSE_MISALIGNED_FETCH, RAM_BASE + 2, RAM_BASE + 2);
+
+        // Fetching from the UART doesn't pop its receive FIFO
+        emu.push_uart_input(b"a");
+        emu.program_counter = UART_BASE;
+        emu.tick();
+        assert_trap(&emu, CAUSE_FETCH_ACCESS, UART_BASE, UART_BASE);
+        assert_eq!(emu.uart.rx_fifo.len(), 1);
+    }
+
+    #[test]
+    fn csrs() {
+        let mut emu = emu();
+        emu.x[1] = 0x1234;
+        emu.x[3] = 0x1;
+        let cases = [
+            (csr(0x1, 2, CSR_MTVEC, 1), HANDLER, 0x1234),   // csrrw
+            (csr(0x2, 2, CSR_MTVEC, 3), 0x1234, 0x1235),    // csrrs
+            (csr(0x3, 2, CSR_MTVEC, 1), 0x1235, 0x1),       // csrrc
+            (csr(0x5, 2, CSR_MTVEC, 0x1c), 0x1, 0x1c),      // csrrwi
+            (csr(0x6, 2, CSR_MTVEC, 0x3), 0x1c, 0x1f),      // csrrsi
+            (csr(0x7, 2, CSR_MTVEC, 0x1f), 0x1f, 0),        // csrrci
+        ];
+        for (instr, old, new) in cases {
+            exec(&mut emu, instr);
+            assert_eq!(emu.x[2], old, "{}", emu.translate_instr(instr));
+            assert_eq!(emu.csr[CSR_MTVEC as usize], new, "{}", emu.translate_instr(instr));
+        }
+        emu.csr[CSR_MTVEC as usize] = HANDLER;
+
+        // misa ignores writes, mepc drops the low bits
+        exec(&mut emu, csr(0x1, 2, CSR_MISA, 1));
+        assert_eq!(emu.x[2], MISA_RV32IM);
+        assert_eq!(emu.read_csr(CSR_MISA), MISA_RV32IM);
+        emu.x[1] = 0x1237;
+        exec(&mut emu, csr(0x1, 0, CSR_MEPC, 1));
+        assert_eq!(emu.csr[CSR_MEPC as usize], 0x1234);
+
+        // The counters, reading counts the instructions before it
+        let ticks = emu.cycle as u32;
+        exec(&mut emu, csr(0x2, 2, CSR_CYCLE, 0));
+        assert_eq!(emu.x[2], ticks);
+        exec(&mut emu, csr(0x2, 2, CSR_INSTRET, 0));
+        assert_eq!(emu.x[2], ticks + 1);
+        exec(&mut emu, csr(0x2, 2, CSR_MHARTID, 0));
+        assert_eq!(emu.x[2], 0);
+
+        // Writing a read-only CSR
+        let pc = emu.program_counter;
+        let instr = csr(0x1, 0, CSR_CYCLE, 1);
+        exec(&mut emu, instr);
+        assert_trap(&emu, CAUSE_ILLEGAL_INSTR, instr, pc);
+    }
+
+    #[test]
+    fn ecall_and_mret() {
+        let mut emu = emu();
+        emu.csr[CSR_MSTATUS as usize] |= MSTATUS_MIE;
+        exec(&mut emu, 0x0000_0073);
+        assert_trap(&emu, CAUSE_MACHINE_ECALL, 0, RAM_BASE);
+        let mstatus = emu.csr[CSR_MSTATUS as usize];
+        assert_eq!(mstatus & (MSTATUS_MIE | MSTATUS_MPIE), MSTATUS_MPIE);
+
+        // The handler skips the ecall and returns
+        emu.csr[CSR_MEPC as usize] += 4;
+        exec(&mut emu, 0x3020_0073);
+        assert_eq!(emu.program_counter, RAM_BASE + 4);
+        assert_ne!(emu.csr[CSR_MSTATUS as usize] & MSTATUS_MIE, 0);
+
+        exec(&mut emu, 0x0010_0073);
+        assert_trap(&emu, CAUSE_BREAKPOINT, RAM_BASE + 4, RAM_BASE + 4);
+        exec(&mut emu, 0xffff_ffff);
+        assert_trap(&emu, CAUSE_ILLEGAL_INSTR, 0xffff_ffff, HANDLER);
+    }
+
+    #[test]
+    fn uart() {
+        let mut emu = emu();
+        emu.x[1] = UART_BASE;
+        for c in b"Hi" {
+            emu.x[2] = *c as u32;
+            exec(&mut emu, store(0x0, 2, 1, 0));
+        }
+        assert_eq!(emu.uart_output(), "Hi");
+
+        // LSR, data ready once something is queued
+        exec(&mut emu, imm(0x03, 0x4, 3, 1, 5));
+        assert_eq!(emu.x[3], 0x60);
+        emu.on_key_press(b'a');
+        exec(&mut emu, imm(0x03, 0x4, 3, 1, 5));
+        assert_eq!(emu.x[3], 0x61);
+        exec(&mut emu, imm(0x03, 0x4, 3, 1, 0));
+        assert_eq!(emu.x[3], b'a' as u32);
+        exec(&mut emu, imm(0x03, 0x4, 3, 1, 5));
+        assert_eq!(emu.x[3], 0x60);
+
+        // With DLAB set offset 0 is the divisor latch, not the transmitter
+        emu.x[2] = 0x80;
+        exec(&mut emu, store(0x0, 2, 1, 3));
+        emu.x[2] = 0x0c;
+        exec(&mut emu, store(0x0, 2, 1, 0));
+        assert_eq!(emu.uart.dll, 0x0c);
+        assert_eq!(emu.uart_output(), "Hi");
+    }
+
+    #[test]
+    fn framebuffer() {
+        let mut emu = emu();
+        // The pixel at 3, 2
+        emu.x[1] = FB_BASE + (2 * FB_WIDTH + 3) * 4;
+        emu.x[2] = 0xff33_2211;
+        exec(&mut emu, store(0x2, 2, 1, 0));
+        exec(&mut emu, imm(0x03, 0x2, 3, 1, 0));
+        assert_eq!(emu.x[3], 0xff33_2211);
+
+        let res = ScreenResolution { width: FB_WIDTH * 2, height: FB_HEIGHT * 2 };
+        let mut buf = vec![0; (res.width * res.height * 4) as usize];
+        assert_eq!(emu.draw_to_buffer_rgba(&mut buf, &res), Ok(true));
+        let pixel = |x: u32, y: u32| buf[((y * res.width + x) * 4) as usize..][..4].to_vec();
+        for (x, y) in [(6, 4), (7, 4), (6, 5), (7, 5)] {
+            assert_eq!(pixel(x, y), [0x11, 0x22, 0x33, 0xff]);
+        }
+        assert_eq!(pixel(8, 4), [0, 0, 0, 0]);
+
+        let small = ScreenResolution { width: FB_WIDTH / 2, height: FB_HEIGHT / 2 };
+        assert_eq!(emu.draw_to_buffer_rgba(&mut buf, &small), Err(false));
+
+        // The last word of the framebuffer, then one past it
+        emu.x[1] = FB_BASE + FB_SIZE - 4;
+        exec(&mut emu, store(0x2, 2, 1, 0));
+        let pc = emu.program_counter;
+        exec(&mut emu, store(0x2, 2, 1, 2));
+        assert_trap(&emu, CAUSE_STORE_ACCESS, FB_BASE + FB_SIZE - 2, pc);
+    }
+
+    #[test]
+    fn tohost_exit_code() {
+        let mut emu = emu();
+        emu.tohost_addr = Some(DATA);
+        emu.x[1] = DATA;
+        emu.x[2] = (3 << 1) | 1;
+        exec(&mut emu, store(0x2, 2, 1, 0));
+        assert_eq!(emu.get_exit_code(), Some(3));
+        // Stopped
+        let pc = emu.program_counter;
+        emu.tick();
+        assert_eq!(emu.program_counter, pc);
+    }
+
+    /**
+     * ELF32 header with one PT_LOAD segment holding `file`.
+     */
+    fn elf(paddr: u32, file: &[u8], mem_size: u32) -> Vec<u8> {
+        let mut data = vec![0; 84];
+        data[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        data[18..20].copy_from_slice(&0xf3u16.to_le_bytes());
+        data[24..28].copy_from_slice(&paddr.to_le_bytes());
+        data[28..32].copy_from_slice(&52u32.to_le_bytes());
+        data[42..44].copy_from_slice(&32u16.to_le_bytes());
+        data[44..46].copy_from_slice(&1u16.to_le_bytes());
+        data[52..56].copy_from_slice(&1u32.to_le_bytes());
+        data[56..60].copy_from_slice(&84u32.to_le_bytes());
+        data[64..68].copy_from_slice(&paddr.to_le_bytes());
+        data[68..72].copy_from_slice(&(file.len() as u32).to_le_bytes());
+        data[72..76].copy_from_slice(&mem_size.to_le_bytes());
+        data.extend_from_slice(file);
+        data
+    }
+
+    #[test]
+    fn load_elf_segments() {
+        let mut emu = emu();
+        emu.write_ram(DATA, &[0xaa; 12]).unwrap();
+        emu.load_elf(&elf(DATA, &[1, 2, 3, 4], 8)).unwrap();
+        assert_eq!(emu.program_counter, DATA);
+        // The rest of mem_size is zeroed, what follows is left alone
+        assert_eq!(&emu.ram[0x100..0x10c], &[1, 2, 3, 4, 0, 0, 0, 0, 0xaa, 0xaa, 0xaa, 0xaa]);
+
+        assert!(emu.load_elf(&elf(RAM_BASE, &[], u32::MAX)).is_err());
+        assert!(emu.load_elf(&elf(RAM_BASE + RAM_SIZE - 4, &[0; 4], 8)).is_err());
+        assert!(emu.load_elf(&elf(RAM_BASE - 4, &[0; 4], 4)).is_err());
+        let mut truncated = elf(DATA, &[1, 2, 3, 4], 4);
+        truncated.truncate(86);
+        assert!(emu.load_elf(&truncated).is_err());
+        assert!(emu.load_data(b"\x7fELF\x02\x01").is_err());
+    }
+}
@@ -0,0 +1,5 @@
+//! CPU-side filters for the RGBA output of `EmuTrait::draw_to_buffer_rgba`,
+//! for the renderers that have no shaders (headless, SDL canvas).
+
+pub mod persistence;
+pub mod upscale;
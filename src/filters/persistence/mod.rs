@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+/**
+ * Flicker reduction for 1bpp displays. A pixel is "off" when it has the
+ * background colour, anything else counts as lit.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PersistenceMode {
+    Off, 
+    // Pixels that turn off fade to the background over this many frames
+    Fade(u8), 
+    // A pixel is lit if it was lit in this frame or the one before
+    MaxOfTwo, 
+}
+
+impl FromStr for PersistenceMode {
+    type Err = String;
+
+    /**
+     * `off`, `max2` or `fade:<frames>`
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(PersistenceMode::Off), 
+            "max2" => Ok(PersistenceMode::MaxOfTwo), 
+            _ => {
+                let frames = s.strip_prefix("fade:")
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or(format!("Unknown persistence mode '{}', expected off, max2 or fade:<frames>", s))?;
+                Ok(PersistenceMode::Fade(frames))
+            }, 
+        }
+    }
+}
+
+pub struct PersistenceFilter {
+    pub mode: PersistenceMode, 
+    pub background: [u8; 3], 
+    // Colour each pixel had when it was last lit, and frames since then
+    last_lit: Vec<u8>, 
+    age: Vec<u8>, 
+}
+
+impl PersistenceFilter {
+    pub fn new(mode: PersistenceMode) -> PersistenceFilter {
+        PersistenceFilter {
+            mode, 
+            background: [0, 0, 0], 
+            last_lit: Vec::new(), 
+            age: Vec::new(), 
+        }
+    }
+
+    /**
+     * Forget the history, e.g. after a reset or resolution change.
+     */
+    pub fn clear(self: &mut Self) {
+        self.last_lit.clear();
+        self.age.clear();
+    }
+
+    /**
+     * Call once per displayed frame with a freshly drawn RGBA buffer.
+     */
+    pub fn apply(self: &mut Self, buf: &mut [u8]) {
+        let max_age = match self.mode {
+            PersistenceMode::Off => return, 
+            PersistenceMode::Fade(frames) => frames, 
+            PersistenceMode::MaxOfTwo => 1, 
+        };
+        if self.last_lit.len() != buf.len() {
+            self.last_lit = buf.to_vec();
+            self.age = vec![u8::MAX; buf.len() / 4];
+        }
+
+        let bg = self.background;
+        for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+            let lit = &mut self.last_lit[i * 4..i * 4 + 3];
+            if px[..3] != bg {
+                lit.copy_from_slice(&px[..3]);
+                self.age[i] = 0;
+                continue;
+            }
+            self.age[i] = self.age[i].saturating_add(1);
+            if self.age[i] > max_age {
+                continue;
+            }
+            match self.mode {
+                PersistenceMode::MaxOfTwo => px[..3].copy_from_slice(lit), 
+                _ => {
+                    // Linear fade from the last lit colour to the background
+                    let weight = (max_age - self.age[i] + 1) as u32;
+                    for c in 0..3 {
+                        px[c] = ((bg[c] as u32 * (max_age as u32 + 1 - weight) 
+                            + lit[c] as u32 * weight) / (max_age as u32 + 1)) as u8;
+                    }
+                }, 
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+    const BLACK: [u8; 3] = [0, 0, 0];
+
+    /**
+     * Runs one frame of RGB pixels through `filter`, alpha is left at 0xff.
+     */
+    fn frame(filter: &mut PersistenceFilter, pixels: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        let mut buf: Vec<u8> = pixels.iter().flat_map(|[r, g, b]| [*r, *g, *b, 0xff]).collect();
+        filter.apply(&mut buf);
+        assert!(buf.chunks(4).all(|px| px[3] == 0xff));
+        buf.chunks(4).map(|px| [px[0], px[1], px[2]]).collect()
+    }
+
+    #[test]
+    fn parse_modes() {
+        assert_eq!("off".parse(), Ok(PersistenceMode::Off));
+        assert_eq!("max2".parse(), Ok(PersistenceMode::MaxOfTwo));
+        assert_eq!("fade:3".parse(), Ok(PersistenceMode::Fade(3)));
+        assert!("fade:0".parse::<PersistenceMode>().is_err());
+        assert!("fade:300".parse::<PersistenceMode>().is_err());
+        assert!("fade".parse::<PersistenceMode>().is_err());
+    }
+
+    #[test]
+    fn off_changes_nothing() {
+        let mut filter = PersistenceFilter::new(PersistenceMode::Off);
+        frame(&mut filter, &[WHITE]);
+        assert_eq!(frame(&mut filter, &[BLACK]), [BLACK]);
+    }
+
+    #[test]
+    fn fade_decays_linearly() {
+        let mut filter = PersistenceFilter::new(PersistenceMode::Fade(3));
+        // The second pixel was never lit and stays off
+        assert_eq!(frame(&mut filter, &[WHITE, BLACK]), [WHITE, BLACK]);
+        let faded: Vec<u8> = (0..4).map(|_| frame(&mut filter, &[BLACK, BLACK])[0][0]).collect();
+        assert_eq!(faded, [191, 127, 63, 0]);
+        assert_eq!(frame(&mut filter, &[BLACK, BLACK]), [BLACK, BLACK]);
+
+        // Lit again restarts the fade
+        frame(&mut filter, &[WHITE, BLACK]);
+        assert_eq!(frame(&mut filter, &[BLACK, BLACK])[0], [191; 3]);
+    }
+
+    #[test]
+    fn max_of_two_keeps_one_frame() {
+        let mut filter = PersistenceFilter::new(PersistenceMode::MaxOfTwo);
+        let red = [0xff, 0, 0];
+        frame(&mut filter, &[red]);
+        assert_eq!(frame(&mut filter, &[BLACK]), [red]);
+        assert_eq!(frame(&mut filter, &[BLACK]), [BLACK]);
+
+        // Flickering every other frame looks steady
+        for i in 0..6 {
+            let pixel = if i % 2 == 0 { WHITE } else { BLACK };
+            assert_eq!(frame(&mut filter, &[pixel]), [WHITE]);
+        }
+    }
+
+    #[test]
+    fn background_colour() {
+        let mut filter = PersistenceFilter::new(PersistenceMode::Fade(1));
+        let bg = [0x10, 0x20, 0x30];
+        filter.background = bg;
+        // Black isn't the background here, so it counts as lit
+        frame(&mut filter, &[WHITE, BLACK]);
+        assert_eq!(frame(&mut filter, &[bg, bg]), [[135, 143, 151], [8, 16, 24]]);
+        assert_eq!(frame(&mut filter, &[bg, bg]), [bg, bg]);
+    }
+
+    #[test]
+    fn history_resets() {
+        let mut filter = PersistenceFilter::new(PersistenceMode::MaxOfTwo);
+        frame(&mut filter, &[WHITE]);
+        filter.clear();
+        assert_eq!(frame(&mut filter, &[BLACK]), [BLACK]);
+
+        // A new size starts over too
+        frame(&mut filter, &[WHITE]);
+        assert_eq!(frame(&mut filter, &[BLACK, BLACK]), [BLACK, BLACK]);
+    }
+}
@@ -0,0 +1,398 @@
+use std::str::FromStr;
+
+use crate::emulators::ScreenResolution;
+
+/**
+ * Pixel-art upscalers. Each one multiplies the size by a fixed factor, use
+ * `UpscaleChain` to get from there to any target size.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Upscaler {
+    // Plain block replication
+    None,
+    // EPX / AdvMAME2x
+    Scale2x,
+    // AdvMAME3x
+    Scale3x,
+    Eagle,
+    // 2x, blends corners along diagonal edges instead of copying a neighbour
+    XbrLite,
+}
+
+impl Upscaler {
+    pub const ALL: [Upscaler; 5] = [
+        Upscaler::None, Upscaler::Scale2x, Upscaler::Scale3x, Upscaler::Eagle, Upscaler::XbrLite,
+    ];
+
+    pub fn name(self: &Self) -> &'static str {
+        match self {
+            Upscaler::None => "none",
+            Upscaler::Scale2x => "scale2x",
+            Upscaler::Scale3x => "scale3x",
+            Upscaler::Eagle => "eagle",
+            Upscaler::XbrLite => "xbr",
+        }
+    }
+
+    pub fn factor(self: &Self) -> u32 {
+        match self {
+            Upscaler::None => 1,
+            Upscaler::Scale3x => 3,
+            _ => 2,
+        }
+    }
+
+    /**
+     * The next filter in `ALL`, for cycling through them with a hotkey.
+     */
+    pub fn next(self: &Self) -> Upscaler {
+        let i = Upscaler::ALL.iter().position(|u| u == self).unwrap_or(0);
+        Upscaler::ALL[(i + 1) % Upscaler::ALL.len()]
+    }
+
+    /**
+     * Scales `src` by `factor()` into `dst`, resizing it as needed.
+     * Returns the resolution of the result.
+     */
+    pub fn upscale(self: &Self, src: &[u8], res: &ScreenResolution, dst: &mut Vec<u8>) -> ScreenResolution {
+        let factor = self.factor();
+        let out_res = ScreenResolution { width: res.width * factor, height: res.height * factor };
+        dst.resize((out_res.width * out_res.height * 4) as usize, 0);
+
+        let src = Pixels { buf: src, width: res.width as i32, height: res.height as i32 };
+        let out_width = out_res.width as usize;
+        for y in 0..src.height {
+            for x in 0..src.width {
+                let block = match self {
+                    Upscaler::None => [src.get(x, y); 9],
+                    Upscaler::Scale2x => scale2x(&src, x, y),
+                    Upscaler::Scale3x => scale3x(&src, x, y),
+                    Upscaler::Eagle => eagle(&src, x, y),
+                    Upscaler::XbrLite => xbr_lite(&src, x, y),
+                };
+                for by in 0..factor as usize {
+                    for bx in 0..factor as usize {
+                        let ox = x as usize * factor as usize + bx;
+                        let oy = y as usize * factor as usize + by;
+                        let i = (oy * out_width + ox) * 4;
+                        dst[i..i + 4].copy_from_slice(&block[by * factor as usize + bx]);
+                    }
+                }
+            }
+        }
+        out_res
+    }
+}
+
+impl FromStr for Upscaler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "epx" => Ok(Upscaler::Scale2x),
+            "xbr-lite" => Ok(Upscaler::XbrLite),
+            _ => Upscaler::ALL.iter()
+                .find(|u| u.name() == s)
+                .copied()
+                .ok_or(format!("Unknown filter '{}', expected none, scale2x, scale3x, eagle or xbr", s)),
+        }
+    }
+}
+
+/**
+ * Nearest-neighbour scaling to any size, non-integer ratios included.
+ */
+pub fn scale_nearest(src: &[u8], src_res: &ScreenResolution, dst: &mut [u8], dst_res: &ScreenResolution) {
+    if src_res.width == 0 || src_res.height == 0 {
+        return;
+    }
+    for y in 0..dst_res.height {
+        let sy = (y as u64 * src_res.height as u64 / dst_res.height as u64) as usize;
+        for x in 0..dst_res.width {
+            let sx = (x as u64 * src_res.width as u64 / dst_res.width as u64) as usize;
+            let si = (sy * src_res.width as usize + sx) * 4;
+            let di = ((y * dst_res.width + x) * 4) as usize;
+            if di + 4 > dst.len() || si + 4 > src.len() {
+                return;
+            }
+            dst[di..di + 4].copy_from_slice(&src[si..si + 4]);
+        }
+    }
+}
+
+/**
+ * An upscaler followed by nearest-neighbour scaling to the final size.
+ */
+pub struct UpscaleChain {
+    pub upscaler: Upscaler,
+    buf: Vec<u8>,
+}
+
+impl UpscaleChain {
+    pub fn new(upscaler: Upscaler) -> UpscaleChain {
+        UpscaleChain { upscaler, buf: Vec::new() }
+    }
+
+    /**
+     * Resolution of the upscaler output for a screen of `res`.
+     */
+    pub fn output_res(self: &Self, res: &ScreenResolution) -> ScreenResolution {
+        let factor = self.upscaler.factor();
+        ScreenResolution { width: res.width * factor, height: res.height * factor }
+    }
+
+    pub fn apply(self: &mut Self, src: &[u8], src_res: &ScreenResolution,
+        dst: &mut [u8], dst_res: &ScreenResolution) {
+
+        if self.upscaler == Upscaler::None {
+            scale_nearest(src, src_res, dst, dst_res);
+            return;
+        }
+        let up_res = self.upscaler.upscale(src, src_res, &mut self.buf);
+        scale_nearest(&self.buf, &up_res, dst, dst_res);
+    }
+}
+
+type Rgba = [u8; 4];
+
+struct Pixels<'a> {
+    buf: &'a [u8],
+    width: i32,
+    height: i32,
+}
+
+impl<'a> Pixels<'a> {
+    // Coordinates outside the screen are clamped to the edge
+    fn get(self: &Self, x: i32, y: i32) -> Rgba {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.buf[i], self.buf[i + 1], self.buf[i + 2], self.buf[i + 3]]
+    }
+
+    // 3x3 neighbourhood, row by row: A B C / D E F / G H I
+    fn around(self: &Self, x: i32, y: i32) -> [Rgba; 9] {
+        let mut n = [[0; 4]; 9];
+        for dy in 0..3 {
+            for dx in 0..3 {
+                n[(dy * 3 + dx) as usize] = self.get(x + dx - 1, y + dy - 1);
+            }
+        }
+        n
+    }
+}
+
+// Output blocks are row by row, 2x2 blocks use the first 4 entries
+fn scale2x(src: &Pixels, x: i32, y: i32) -> [Rgba; 9] {
+    let [_, b, _, d, e, f, _, h, _] = src.around(x, y);
+    let mut out = [e; 9];
+    if b != h && d != f {
+        if d == b { out[0] = d; }
+        if b == f { out[1] = f; }
+        if d == h { out[2] = d; }
+        if h == f { out[3] = f; }
+    }
+    out
+}
+
+fn scale3x(src: &Pixels, x: i32, y: i32) -> [Rgba; 9] {
+    let [a, b, c, d, e, f, g, h, i] = src.around(x, y);
+    let mut out = [e; 9];
+    if b != h && d != f {
+        if d == b { out[0] = d; }
+        if (d == b && e != c) || (b == f && e != a) { out[1] = b; }
+        if b == f { out[2] = f; }
+        if (d == b && e != g) || (d == h && e != a) { out[3] = d; }
+        if (b == f && e != i) || (h == f && e != c) { out[5] = f; }
+        if d == h { out[6] = d; }
+        if (d == h && e != i) || (h == f && e != g) { out[7] = h; }
+        if h == f { out[8] = f; }
+    }
+    out
+}
+
+fn eagle(src: &Pixels, x: i32, y: i32) -> [Rgba; 9] {
+    let [a, b, c, d, e, f, g, h, i] = src.around(x, y);
+    let mut out = [e; 9];
+    if a == b && a == d { out[0] = a; }
+    if c == b && c == f { out[1] = c; }
+    if g == d && g == h { out[2] = g; }
+    if i == f && i == h { out[3] = i; }
+    out
+}
+
+fn xbr_lite(src: &Pixels, x: i32, y: i32) -> [Rgba; 9] {
+    let n = src.around(x, y);
+    let e = n[4];
+    let mut out = [e; 9];
+    // (vertical neighbour, horizontal neighbour, diagonal, the two far corners) per output corner
+    let corners = [
+        (n[1], n[3], n[0], n[2], n[6]),
+        (n[1], n[5], n[2], n[0], n[8]),
+        (n[7], n[3], n[6], n[0], n[8]),
+        (n[7], n[5], n[8], n[2], n[6]),
+    ];
+    for (k, (v, hz, diag, far1, far2)) in corners.into_iter().enumerate() {
+        // The edge runs between v and hz, and e continues along it
+        let is_edge = distance(&v, &hz) < distance(&e, &diag)
+            && distance(&e, &v) > EDGE_THRESHOLD
+            && distance(&e, &hz) > EDGE_THRESHOLD
+            && (distance(&e, &far1) <= EDGE_THRESHOLD || distance(&e, &far2) <= EDGE_THRESHOLD);
+        if is_edge {
+            out[k] = blend(&e, &blend(&v, &hz));
+        }
+    }
+    out
+}
+
+const EDGE_THRESHOLD: u32 = 48;
+
+// Rough luma/chroma distance, luma counts double
+fn distance(p: &Rgba, q: &Rgba) -> u32 {
+    let dr = p[0] as i32 - q[0] as i32;
+    let dg = p[1] as i32 - q[1] as i32;
+    let db = p[2] as i32 - q[2] as i32;
+    let y = (dr * 299 + dg * 587 + db * 114) / 1000;
+    let u = db - y;
+    let v = dr - y;
+    (2 * y.abs() + u.abs() / 2 + v.abs() / 2) as u32
+}
+
+fn blend(p: &Rgba, q: &Rgba) -> Rgba {
+    [
+        ((p[0] as u16 + q[0] as u16) / 2) as u8,
+        ((p[1] as u16 + q[1] as u16) / 2) as u8,
+        ((p[2] as u16 + q[2] as u16) / 2) as u8,
+        ((p[3] as u16 + q[3] as u16) / 2) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba = [0, 0, 0, 0xff];
+    const WHITE: Rgba = [0xff, 0xff, 0xff, 0xff];
+
+    /**
+     * `#` is black, `.` white, one string per row.
+     */
+    fn image(rows: &[&str]) -> (Vec<u8>, ScreenResolution) {
+        let buf = rows.iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|c| if c == '#' { BLACK } else { WHITE })
+            .collect();
+        (buf, ScreenResolution { width: rows[0].len() as u32, height: rows.len() as u32 })
+    }
+
+    /**
+     * Back to rows, anything other than black or white is `?`.
+     */
+    fn rows(buf: &[u8], res: &ScreenResolution) -> Vec<String> {
+        buf.chunks(4)
+            .map(|p| if p == BLACK { '#' } else if p == WHITE { '.' } else { '?' })
+            .collect::<Vec<char>>()
+            .chunks(res.width as usize)
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    fn upscale(upscaler: Upscaler, src: &[&str]) -> Vec<String> {
+        let (buf, res) = image(src);
+        let mut out = Vec::new();
+        let out_res = upscaler.upscale(&buf, &res, &mut out);
+        rows(&out, &out_res)
+    }
+
+    #[test]
+    fn nearest() {
+        let (src, res) = image(&["#."]);
+        let mut dst = vec![0; 4 * 2 * 4];
+        let dst_res = ScreenResolution { width: 4, height: 2 };
+        scale_nearest(&src, &res, &mut dst, &dst_res);
+        assert_eq!(rows(&dst, &dst_res), ["##..", "##.."]);
+
+        // Non-integer ratios and shrinking
+        let mut dst = vec![0; 3 * 4];
+        let dst_res = ScreenResolution { width: 3, height: 1 };
+        scale_nearest(&src, &res, &mut dst, &dst_res);
+        assert_eq!(rows(&dst, &dst_res), ["##."]);
+        let (src, res) = image(&["#.#.", "...."]);
+        let mut dst = vec![0; 2 * 4];
+        let dst_res = ScreenResolution { width: 2, height: 1 };
+        scale_nearest(&src, &res, &mut dst, &dst_res);
+        assert_eq!(rows(&dst, &dst_res), ["##"]);
+
+        // A short destination is filled as far as it goes
+        let mut dst = vec![0; 4];
+        scale_nearest(&src, &res, &mut dst, &ScreenResolution { width: 8, height: 8 });
+        assert_eq!(dst, BLACK);
+    }
+
+    #[test]
+    fn block_copy() {
+        assert_eq!(upscale(Upscaler::None, &["#.", ".."]), ["#.", ".."]);
+    }
+
+    #[test]
+    fn scale2x_rounds_corners() {
+        assert_eq!(upscale(Upscaler::Scale2x, &["#.", ".."]), ["##..", "#...", "....", "...."]);
+        // A lone pixel stays a block
+        assert_eq!(upscale(Upscaler::Scale2x, &["...", ".#.", "..."])[2..4], ["..##..", "..##.."]);
+    }
+
+    #[test]
+    fn scale3x_rounds_corners() {
+        assert_eq!(upscale(Upscaler::Scale3x, &["#.", ".."]), [
+            "###...",
+            "##....",
+            "#.....",
+            "......",
+            "......",
+            "......",
+        ]);
+    }
+
+    #[test]
+    fn eagle() {
+        assert_eq!(upscale(Upscaler::Eagle, &["#.", ".."]), ["##..", "#...", "....", "...."]);
+        // Unlike Scale2x, a lone pixel is taken over by its neighbours
+        assert_eq!(upscale(Upscaler::Eagle, &["...", ".#.", "..."])[2..4], ["......", "......"]);
+    }
+
+    #[test]
+    fn xbr_blends_diagonals() {
+        let src = ["..#", ".##", "###"];
+        // Both sides of the edge get blended corners, Scale2x copies the neighbour
+        assert_eq!(upscale(Upscaler::XbrLite, &src)[2..4], ["..?###", ".?####"]);
+        assert_eq!(upscale(Upscaler::Scale2x, &src)[2..4], ["...###", ".#####"]);
+
+        let (buf, res) = image(&src);
+        let mut out = Vec::new();
+        let out_res = Upscaler::XbrLite.upscale(&buf, &res, &mut out);
+        let i = ((2 * out_res.width + 2) * 4) as usize;
+        assert_eq!(out[i..i + 4], [0x7f, 0x7f, 0x7f, 0xff]);
+    }
+
+    #[test]
+    fn chain_to_any_size() {
+        let (src, res) = image(&["#.", ".."]);
+        let mut dst = vec![0; 3 * 3 * 4];
+        let dst_res = ScreenResolution { width: 3, height: 3 };
+        UpscaleChain::new(Upscaler::Scale2x).apply(&src, &res, &mut dst, &dst_res);
+        assert_eq!(rows(&dst, &dst_res), ["##.", "#..", "..."]);
+        assert_eq!(UpscaleChain::new(Upscaler::Scale3x).output_res(&res).width, 6);
+    }
+
+    #[test]
+    fn names() {
+        for upscaler in Upscaler::ALL {
+            assert_eq!(upscaler.name().parse(), Ok(upscaler));
+        }
+        assert_eq!("EPX".parse(), Ok(Upscaler::Scale2x));
+        assert_eq!("xbr-lite".parse(), Ok(Upscaler::XbrLite));
+        assert!("hq2x".parse::<Upscaler>().is_err());
+        assert_eq!(Upscaler::XbrLite.next(), Upscaler::None);
+    }
+}
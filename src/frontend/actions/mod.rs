@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+
+use sdl2::keyboard::Keycode;
+
+use crate::log_warn;
+
+/**
+ * Things the frontend can be asked to do, from a hotkey or a menu entry.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    // Pauses into single stepping, or runs again
+    TogglePause,
+    // Runs again after a pause or a breakpoint
+    Continue,
+    // `[debugger] step_count` instructions, pausing first if running
+    Step,
+    // Over subroutine calls, see `Debugger::step_over`
+    StepOver,
+    StepOut,
+    // Until the PC gets to the disassembly cursor, or to the instruction
+    // after the current one, e.g. out of a loop
+    RunToCursor,
+    // At the disassembly cursor or the current PC
+    ToggleBreakpoint,
+    // Only while the key is held, handled with the key events
+    FastForward,
+    SpeedUp,
+    SlowDown,
+    ClockUp,
+    ClockDown,
+    NextQuirks,
+    NextPalette,
+    NextFilter,
+    ToggleDebugPane,
+    ToggleAudio,
+    ToggleKeypad,
+    ToggleMemoryView,
+    ToggleDisassembly,
+    // Also Alt+Enter, which can't be rebound
+    ToggleFullscreen,
+    Screenshot,
+    OpenBrowser,
+    SaveState,
+    LoadState,
+    NextSlot,
+    PrevSlot,
+    Reset,
+    OpenMenu,
+    OpenSettings,
+    OpenControls,
+    ShowHelp,
+    // Closes the menu or help overlay
+    Resume,
+    Quit,
+}
+
+/**
+ * Actions that can have a key, in the order the help overlay and the
+ * controls screen list them, with their config name and description.
+ */
+pub const HOTKEY_ACTIONS: &[(Action, &str, &str)] = &[
+    (Action::OpenMenu, "menu", "Pause menu"),
+    (Action::ShowHelp, "help", "Hotkeys"),
+    (Action::OpenBrowser, "load_rom", "Load ROM"),
+    (Action::TogglePause, "pause", "Pause / run"),
+    (Action::Continue, "continue", "Continue"),
+    (Action::Step, "step", "Step"),
+    (Action::StepOver, "step_over", "Step over"),
+    (Action::StepOut, "step_out", "Step out"),
+    (Action::RunToCursor, "run_to", "Run to cursor"),
+    (Action::ToggleBreakpoint, "breakpoint", "Breakpoint at cursor"),
+    (Action::Reset, "reset", "Reset"),
+    (Action::SaveState, "save_state", "Save state"),
+    (Action::LoadState, "load_state", "Load state"),
+    (Action::NextSlot, "next_slot", "Next state slot"),
+    (Action::PrevSlot, "prev_slot", "Previous state slot"),
+    (Action::FastForward, "fast_forward", "Fast forward while held"),
+    (Action::SpeedUp, "faster", "Faster"),
+    (Action::SlowDown, "slower", "Slower"),
+    (Action::ClockUp, "clock_up", "More ticks per frame"),
+    (Action::ClockDown, "clock_down", "Fewer ticks per frame"),
+    (Action::NextFilter, "next_filter", "Next filter"),
+    (Action::NextQuirks, "next_quirks", "Next quirks preset"),
+    (Action::NextPalette, "next_palette", "Next palette"),
+    (Action::Screenshot, "screenshot", "Screenshot"),
+    (Action::ToggleDebugPane, "debug_pane", "Debugger pane"),
+    (Action::ToggleMemoryView, "memory", "Memory viewer"),
+    (Action::ToggleDisassembly, "disassembly", "Disassembly"),
+    (Action::ToggleAudio, "audio", "Audio on / off"),
+    (Action::ToggleKeypad, "keypad", "On-screen keypad"),
+    (Action::ToggleFullscreen, "fullscreen", "Fullscreen"),
+    (Action::Quit, "quit", "Quit"),
+];
+
+pub const DEFAULT_HOTKEYS: &[(Keycode, Action)] = &[
+    (Keycode::Escape, Action::OpenMenu),
+    (Keycode::F1, Action::ShowHelp),
+    (Keycode::F2, Action::OpenBrowser),
+    (Keycode::P, Action::TogglePause),
+    (Keycode::F5, Action::Continue),
+    (Keycode::F9, Action::ToggleBreakpoint),
+    (Keycode::F10, Action::Step),
+    (Keycode::PageDown, Action::StepOver),
+    (Keycode::PageUp, Action::StepOut),
+    (Keycode::End, Action::RunToCursor),
+    (Keycode::Backspace, Action::Reset),
+    (Keycode::F3, Action::SaveState),
+    (Keycode::F4, Action::LoadState),
+    (Keycode::Tab, Action::FastForward),
+    (Keycode::Equals, Action::SpeedUp),
+    (Keycode::KpPlus, Action::SpeedUp),
+    (Keycode::Minus, Action::SlowDown),
+    (Keycode::KpMinus, Action::SlowDown),
+    (Keycode::RightBracket, Action::ClockUp),
+    (Keycode::LeftBracket, Action::ClockDown),
+    (Keycode::F6, Action::NextFilter),
+    (Keycode::F7, Action::NextQuirks),
+    (Keycode::F8, Action::NextPalette),
+    (Keycode::F11, Action::Screenshot),
+    (Keycode::F12, Action::ToggleDebugPane),
+    (Keycode::M, Action::ToggleMemoryView),
+    (Keycode::L, Action::ToggleDisassembly),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        HOTKEY_ACTIONS.iter().find(|(_, n, _)| *n == name).map(|(action, _, _)| *action)
+    }
+
+    /**
+     * Config name, empty for actions that can't have a key.
+     */
+    pub fn name(self: &Self) -> &'static str {
+        HOTKEY_ACTIONS.iter().find(|(a, _, _)| a == self).map_or("", |(_, name, _)| *name)
+    }
+
+    pub fn description(self: &Self) -> &'static str {
+        HOTKEY_ACTIONS.iter().find(|(a, _, _)| a == self).map_or("", |(_, _, description)| *description)
+    }
+}
+
+/**
+ * The keys bound to actions: `DEFAULT_HOTKEYS` with the overrides from the
+ * config file and the controls screen on top. An override replaces all
+ * default keys of its action and takes its key away from other actions.
+ */
+#[derive(Clone)]
+pub struct Hotkeys {
+    // Action name to SDL key name, an empty key name unbinds the action
+    overrides: BTreeMap<String, String>,
+    bindings: Vec<(Keycode, Action)>,
+}
+
+impl Hotkeys {
+    pub fn new(overrides: &BTreeMap<String, String>) -> Hotkeys {
+        let mut hotkeys = Hotkeys { overrides: BTreeMap::new(), bindings: Vec::new() };
+        for (name, key_name) in overrides {
+            if Action::from_name(name).is_none() {
+                log_warn!("[Config] Unknown hotkey action '{}'", name);
+            } else if !key_name.is_empty() && Keycode::from_name(key_name).is_none() {
+                log_warn!("[Config] Unknown key name '{}'", key_name);
+            } else {
+                hotkeys.overrides.insert(name.clone(), key_name.clone());
+            }
+        }
+        hotkeys.rebuild();
+        hotkeys
+    }
+
+    fn rebuild(self: &mut Self) {
+        let overridden: Vec<(Action, Option<Keycode>)> = self.overrides.iter()
+            .filter_map(|(name, key_name)| Some((Action::from_name(name)?, Keycode::from_name(key_name))))
+            .collect();
+        self.bindings = DEFAULT_HOTKEYS.iter()
+            .filter(|(key, action)| overridden.iter().all(|(a, k)| a != action && *k != Some(*key)))
+            .copied()
+            .collect();
+        self.bindings.extend(overridden.iter().filter_map(|(action, key)| Some(((*key)?, *action))));
+    }
+
+    /**
+     * What the config file and the controls screen changed, to save.
+     */
+    pub fn overrides(self: &Self) -> &BTreeMap<String, String> {
+        &self.overrides
+    }
+
+    pub fn action(self: &Self, keycode: Keycode) -> Option<Action> {
+        self.bindings.iter().find(|(key, _)| *key == keycode).map(|(_, action)| *action)
+    }
+
+    pub fn keys_for(self: &Self, action: Action) -> Vec<Keycode> {
+        self.bindings.iter().filter(|(_, a)| *a == action).map(|(key, _)| *key).collect()
+    }
+
+    /**
+     * Binds `keycode` to `action` alone, None leaves the action without a key.
+     */
+    pub fn bind(self: &mut Self, action: Action, keycode: Option<Keycode>) {
+        if let Some(keycode) = keycode {
+            self.unbind_key(keycode);
+        }
+        let key_name = keycode.map(|key| key.name()).unwrap_or_default();
+        self.overrides.insert(action.name().to_string(), key_name);
+        self.rebuild();
+    }
+
+    /**
+     * Takes `keycode` away from the action it runs, which keeps its other keys.
+     */
+    pub fn unbind_key(self: &mut Self, keycode: Keycode) {
+        let action = match self.action(keycode) {
+            Some(action) => action,
+            None => return,
+        };
+        let remaining = self.keys_for(action).into_iter()
+            .find(|key| *key != keycode)
+            .map(|key| key.name())
+            .unwrap_or_default();
+        self.overrides.insert(action.name().to_string(), remaining);
+        self.rebuild();
+    }
+
+    /**
+     * `<key>  <description>` lines for the help overlay.
+     */
+    pub fn help_lines(self: &Self) -> Vec<String> {
+        let mut lines: Vec<String> = HOTKEY_ACTIONS.iter()
+            .filter_map(|(action, _, description)| {
+                let keys = self.keys_for(*action);
+                let key = keys.first()?;
+                Some(format!("{:8} {}", key.name(), description))
+            })
+            .collect();
+        lines.push(format!("{:8} {}", "Alt+Enter", Action::ToggleFullscreen.description()));
+        lines
+    }
+}
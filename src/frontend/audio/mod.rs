@@ -0,0 +1,58 @@
+use sdl2::{
+    Sdl,
+    audio::{ AudioCallback, AudioDevice, AudioSpecDesired },
+};
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(self: &mut Self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { self.volume } else { -self.volume };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/**
+ * A square wave tone that is switched on while `EmuTrait::is_sound_playing`.
+ */
+pub struct Beeper {
+    device: AudioDevice<SquareWave>,
+    playing: bool,
+}
+
+impl Beeper {
+    pub fn new(sdl: &Sdl, tone_hz: u32, volume: f32) -> Result<Beeper, String> {
+        let audio_subsystem = sdl.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired, |spec| SquareWave {
+            phase_inc: tone_hz as f32 / spec.freq as f32,
+            phase: 0.0,
+            volume: volume.clamp(0.0, 1.0),
+        })?;
+        Ok(Beeper { device, playing: false })
+    }
+
+    pub fn set_playing(self: &mut Self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = playing;
+    }
+}
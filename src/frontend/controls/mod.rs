@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use sdl2::{ keyboard::Keycode, rect::Rect, render::WindowCanvas };
+
+use crate::emulators::SystemInfo;
+use super::actions::{ Action, Hotkeys, HOTKEY_ACTIONS };
+use super::menu::MenuInput;
+use super::osd::{ self, OverlayColors };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ControlsRow {
+    // Left and Right pick one of `SystemInfo::key_presets`
+    Preset,
+    // Left and Right switch between saving the keys for this ROM or all of them
+    Scope,
+    Key(u8),
+    Hotkey(Action),
+}
+
+pub enum ControlsAction {
+    None,
+    // Back to the settings menu
+    Close,
+    // `keys`, `key_preset`, `rom_only` or `hotkeys` changed
+    Changed,
+}
+
+/**
+ * Lists the core keys and the hotkeys with the keyboard keys bound to them.
+ * Enter waits for the next key press and binds it to the selected row,
+ * taking it away from whatever had it.
+ */
+pub struct ControlsScreen {
+    system: &'static SystemInfo,
+    rows: Vec<ControlsRow>,
+    selected: usize,
+    // First row on screen
+    scroll: usize,
+    // Waiting for the key to bind to the selected row
+    waiting: bool,
+    pub key_preset: Option<String>,
+    // SDL key name to core key
+    pub keys: BTreeMap<String, u8>,
+    // Whether `keys` are kept for the running ROM only, None without a ROM
+    pub rom_only: Option<bool>,
+    pub hotkeys: Hotkeys,
+}
+
+impl ControlsScreen {
+    pub fn new(system: &'static SystemInfo, key_preset: Option<String>, keys: BTreeMap<String, u8>, rom_only: Option<bool>,
+        hotkeys: Hotkeys) -> ControlsScreen {
+        let mut core_keys: Vec<u8> = system.key_presets.iter()
+            .flat_map(|(_, keys)| keys.iter().map(|(_, key)| *key))
+            .collect();
+        core_keys.sort_unstable();
+        core_keys.dedup();
+
+        let mut rows = Vec::new();
+        if !system.key_presets.is_empty() {
+            rows.push(ControlsRow::Preset);
+        }
+        if rom_only.is_some() && !core_keys.is_empty() {
+            rows.push(ControlsRow::Scope);
+        }
+        rows.extend(core_keys.into_iter().map(ControlsRow::Key));
+        rows.extend(HOTKEY_ACTIONS.iter().map(|(action, _, _)| ControlsRow::Hotkey(*action)));
+
+        ControlsScreen { system, rows, selected: 0, scroll: 0, waiting: false, key_preset, keys, rom_only, hotkeys }
+    }
+
+    pub fn on_key(self: &mut Self, keycode: Keycode) -> ControlsAction {
+        if self.waiting {
+            self.waiting = false;
+            return match keycode {
+                Keycode::Escape => ControlsAction::None,
+                Keycode::Delete => self.bind(None),
+                _ => self.bind(Some(keycode)),
+            };
+        }
+        match keycode {
+            Keycode::Delete => self.bind(None),
+            _ => MenuInput::from_keycode(keycode)
+                .map(|input| self.on_input(input))
+                .unwrap_or(ControlsAction::None),
+        }
+    }
+
+    pub fn on_input(self: &mut Self, input: MenuInput) -> ControlsAction {
+        // Only keyboard keys can be bound, anything else cancels
+        if self.waiting {
+            self.waiting = false;
+            return ControlsAction::None;
+        }
+        let row = self.rows[self.selected];
+        match input {
+            MenuInput::Up => self.selected = (self.selected + self.rows.len() - 1) % self.rows.len(),
+            MenuInput::Down => self.selected = (self.selected + 1) % self.rows.len(),
+            MenuInput::Left | MenuInput::Right | MenuInput::Select if row == ControlsRow::Preset => {
+                return self.next_preset(if input == MenuInput::Left { -1 } else { 1 });
+            },
+            MenuInput::Left | MenuInput::Right | MenuInput::Select if row == ControlsRow::Scope => {
+                self.rom_only = self.rom_only.map(|rom_only| !rom_only);
+                return ControlsAction::Changed;
+            },
+            MenuInput::Select => self.waiting = true,
+            MenuInput::Back => return ControlsAction::Close,
+            _ => {},
+        }
+        ControlsAction::None
+    }
+
+    fn next_preset(self: &mut Self, step: isize) -> ControlsAction {
+        let presets = self.system.key_presets;
+        let current = self.key_preset.as_deref()
+            .and_then(|name| presets.iter().position(|(preset, _)| preset.eq_ignore_ascii_case(name)))
+            .unwrap_or(0);
+        let (name, keys) = presets[(current as isize + step).rem_euclid(presets.len() as isize) as usize];
+        self.key_preset = Some(name.to_string());
+        self.keys = keys.iter().map(|(key_name, key)| (key_name.to_string(), *key)).collect();
+        ControlsAction::Changed
+    }
+
+    /**
+     * Binds `keycode` to the selected row alone, None clears the row.
+     */
+    fn bind(self: &mut Self, keycode: Option<Keycode>) -> ControlsAction {
+        let key_name = keycode.map(|key| key.name());
+        match self.rows[self.selected] {
+            ControlsRow::Preset | ControlsRow::Scope => ControlsAction::None,
+            ControlsRow::Key(key) => {
+                self.keys.retain(|name, k| *k != key && Some(name) != key_name.as_ref());
+                if let Some(keycode) = keycode {
+                    self.hotkeys.unbind_key(keycode);
+                    self.keys.insert(keycode.name(), key);
+                }
+                ControlsAction::Changed
+            },
+            ControlsRow::Hotkey(action) => {
+                if let Some(name) = &key_name {
+                    self.keys.remove(name);
+                }
+                self.hotkeys.bind(action, keycode);
+                ControlsAction::Changed
+            },
+        }
+    }
+
+    fn row_text(self: &Self, row: ControlsRow) -> (String, String) {
+        match row {
+            ControlsRow::Preset => (
+                String::from("Layout"),
+                format!("< {} >", self.key_preset.as_deref().unwrap_or(self.system.key_presets[0].0)),
+            ),
+            ControlsRow::Scope => (
+                String::from("Keys for"),
+                String::from(if self.rom_only == Some(true) { "< this ROM >" } else { "< all ROMs >" }),
+            ),
+            ControlsRow::Key(key) => {
+                let names: Vec<&str> = self.keys.iter()
+                    .filter(|(_, k)| **k == key)
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                (format!("Key {:X}", key), names.join(", "))
+            },
+            ControlsRow::Hotkey(action) => {
+                let names: Vec<String> = self.hotkeys.keys_for(action).iter().map(|key| key.name()).collect();
+                (action.description().to_string(), names.join(", "))
+            },
+        }
+    }
+
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, area: Rect, colors: &OverlayColors) {
+        let visible_rows = osd::panel_rows(area);
+        // Keep the selection on screen
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible_rows {
+            self.scroll = self.selected + 1 - visible_rows;
+        }
+
+        let lines: Vec<String> = self.rows.iter().enumerate().skip(self.scroll).take(visible_rows)
+            .map(|(i, row)| {
+                let cursor = if i == self.selected { '>' } else { ' ' };
+                let (label, keys) = self.row_text(*row);
+                let keys = if self.waiting && i == self.selected { String::from("?") } else { keys };
+                format!("{} {:24} {}", cursor, label, if keys.is_empty() { "-" } else { &keys })
+            })
+            .collect();
+        let title = if self.waiting {
+            "Press a key, Delete clears, Esc cancels"
+        } else {
+            "Controls: Enter rebinds, Delete clears"
+        };
+        osd::draw_panel(canvas, area, title, &lines, Some(self.selected - self.scroll), colors);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{ collections::BTreeSet, ops::Range };
+
+use sdl2::{ keyboard::Keycode, pixels::Color, rect::Rect, render::WindowCanvas };
+
+use crate::debugger::Debugger;
+use crate::emulators::Emulator;
+use crate::p_bitmap_font::{ self, CHAR_HEIGHT };
+use super::menu::MenuInput;
+use super::osd::OverlayColors;
+
+// Bytes before and after the listing searched for jumps and calls to label,
+// all of the CHIP-8 memory
+const LABEL_SCAN: u64 = 0x1000;
+// Pixels between glyphs, between lines and around the text, as in `osd`
+const CHAR_SPACING: i32 = 2;
+const LINE_SPACING: i32 = 2;
+const PADDING: i32 = 4;
+
+pub enum DisassemblyAction {
+    None,
+    Close,
+}
+
+enum Line {
+    // `L_0x2A4:` above an instruction something jumps to
+    Label(u64),
+    Instruction(u64),
+}
+
+/**
+ * Listing of the instructions around the PC over the screen, with their
+ * bytes, a `>` at the PC and a `*` at breakpoints. Jump and call targets get
+ * `L_0x<addr>` labels. Moving the cursor stops following the PC, the
+ * breakpoint and run to hotkeys then use the cursor.
+ */
+pub struct DisassemblyView {
+    range: Range<u64>,
+    // Selected instruction, None follows the PC
+    cursor: Option<u64>,
+    // First instruction listed
+    top: u64,
+    // Instruction length the listing steps by, both cores have fixed lengths
+    step: u64,
+    // Set by `draw` from the area it was given
+    rows: usize,
+}
+
+impl DisassemblyView {
+    /**
+     * None for cores that can't disassemble at an address.
+     */
+    pub fn new(emu: &dyn Emulator) -> Option<DisassemblyView> {
+        let range = emu.get_memory_range()?;
+        let pc = emu.get_program_counter();
+        let (_, step) = emu.disassemble(pc).or_else(|| emu.disassemble(range.start))?;
+        Some(DisassemblyView { range, cursor: None, top: pc, step: step.max(1), rows: 1 })
+    }
+
+    pub fn cursor(self: &Self) -> Option<u64> {
+        self.cursor
+    }
+
+    /**
+     * Moves the cursor `lines` instructions, starting from the PC when it
+     * was following it.
+     */
+    fn move_cursor(self: &mut Self, emu: &dyn Emulator, lines: i64) {
+        let from = self.cursor.unwrap_or_else(|| emu.get_program_counter());
+        // First and last addresses on the grid of `from` that are in memory
+        let first = self.range.start + (from.saturating_sub(self.range.start)) % self.step;
+        let last = first + (self.range.end.saturating_sub(first + self.step)) / self.step * self.step;
+        self.cursor = Some(from.saturating_add_signed(lines * self.step as i64).clamp(first, last));
+    }
+
+    /**
+     * None for keys the view has no use for, which the caller can treat as
+     * hotkeys.
+     */
+    pub fn on_key(self: &mut Self, keycode: Keycode, emu: &dyn Emulator) -> Option<DisassemblyAction> {
+        let page = self.rows as i64;
+        match keycode {
+            Keycode::Escape => return Some(DisassemblyAction::Close),
+            Keycode::Up => self.move_cursor(emu, -1),
+            Keycode::Down => self.move_cursor(emu, 1),
+            Keycode::Left => self.move_cursor(emu, -page),
+            Keycode::Right => self.move_cursor(emu, page),
+            Keycode::Home => self.cursor = None,
+            _ => return None,
+        }
+        Some(DisassemblyAction::None)
+    }
+
+    pub fn on_input(self: &mut Self, input: MenuInput, emu: &dyn Emulator) -> DisassemblyAction {
+        match input {
+            MenuInput::Up => self.move_cursor(emu, -1),
+            MenuInput::Down => self.move_cursor(emu, 1),
+            MenuInput::Left => self.move_cursor(emu, -(self.rows as i64)),
+            MenuInput::Right => self.move_cursor(emu, self.rows as i64),
+            MenuInput::Select => self.cursor = None,
+            MenuInput::Back => return DisassemblyAction::Close,
+        }
+        DisassemblyAction::None
+    }
+
+    /**
+     * `around` moved back by up to `lines` instructions, staying in memory.
+     */
+    fn back(self: &Self, around: u64, lines: u64) -> u64 {
+        around - ((around.saturating_sub(self.range.start)) / self.step).min(lines) * self.step
+    }
+
+    /**
+     * Targets of the jumps and calls near `around` that are on its grid.
+     */
+    fn labels(self: &Self, emu: &dyn Emulator, around: Range<u64>) -> BTreeSet<u64> {
+        let start = self.back(around.start, LABEL_SCAN / self.step);
+        let end = around.end.saturating_add(LABEL_SCAN).min(self.range.end);
+        (start..end).step_by(self.step as usize)
+            .filter_map(|addr| emu.get_branch_target(addr))
+            .filter(|target| self.range.contains(target))
+            .collect()
+    }
+
+    fn lines(self: &Self, labels: &BTreeSet<u64>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut addr = self.top;
+        while lines.len() < self.rows && addr + self.step <= self.range.end {
+            if labels.contains(&addr) {
+                lines.push(Line::Label(addr));
+                if lines.len() == self.rows {
+                    break;
+                }
+            }
+            lines.push(Line::Instruction(addr));
+            addr += self.step;
+        }
+        lines
+    }
+
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, area: Rect, emu: &dyn Emulator, debugger: &Debugger,
+        colors: &OverlayColors, pc_color: &Color) {
+        let line_h = CHAR_HEIGHT + LINE_SPACING;
+        // Title and a blank line
+        self.rows = ((area.height() as i32 - 2 * PADDING) / line_h - 2).max(1) as usize;
+        let pc = emu.get_program_counter();
+
+        // The PC a third of the way down, or the cursor where it was
+        let mut labels = match self.cursor {
+            None if self.range.contains(&pc) => {
+                self.top = self.back(pc, self.rows as u64 / 3);
+                self.labels(emu, self.top..self.top + self.rows as u64 * self.step)
+            },
+            None => BTreeSet::new(),
+            Some(cursor) => {
+                if cursor < self.top || !(cursor - self.top).is_multiple_of(self.step) {
+                    self.top = self.back(cursor, self.rows as u64 / 3);
+                }
+                self.labels(emu, self.top..self.top + self.rows as u64 * self.step)
+            },
+        };
+        let mut lines = self.lines(&labels);
+        if let Some(cursor) = self.cursor {
+            // Scrolled off the bottom, labels make the listing shorter than `rows`
+            if !lines.iter().any(|line| matches!(line, Line::Instruction(addr) if *addr == cursor)) {
+                self.top = self.back(cursor, self.rows as u64 * 2 / 3);
+                labels = self.labels(emu, self.top..self.top + self.rows as u64 * self.step);
+                lines = self.lines(&labels);
+            }
+        }
+
+        canvas.set_draw_color(colors.background);
+        let _ = canvas.fill_rect(area);
+        canvas.set_clip_rect(area);
+
+        let x = area.x() + PADDING;
+        let mut y = area.y() + PADDING;
+        let title = match self.cursor {
+            Some(cursor) => format!("Disassembly 0x{:X}  F9 breakpoint, End runs to it, Home follows PC", cursor),
+            None => String::from("Disassembly  Up / Down select, Esc closes"),
+        };
+        p_bitmap_font::draw_text(canvas, x, y, &title, CHAR_SPACING, &colors.selected, &colors.background);
+        y += 2 * line_h;
+
+        let addr_digits = format!("{:X}", self.range.end - 1).len().max(4);
+        let bytes_width = self.step as usize * 3 - 1;
+        for line in lines {
+            let addr = match line {
+                Line::Label(addr) => {
+                    p_bitmap_font::draw_text(canvas, x, y, &format!("L_0x{:X}:", addr), CHAR_SPACING, &colors.selected, &colors.background);
+                    y += line_h;
+                    continue;
+                },
+                Line::Instruction(addr) => addr,
+            };
+            let bytes: Vec<String> = (addr..addr + self.step)
+                .map(|byte| emu.peek(byte).map_or(String::from("??"), |value| format!("{:02X}", value)))
+                .collect();
+            let mnemonic = emu.disassemble(addr).map_or(String::from("??"), |(text, _)| text);
+            let target = emu.get_branch_target(addr)
+                .filter(|target| labels.contains(target))
+                .map_or(String::new(), |target| format!("  -> L_0x{:X}", target));
+            let text = format!("{}{} {:0digits$X}  {:bytes$}  {}{}",
+                if addr == pc { '>' } else { ' ' },
+                if debugger.has_breakpoint(addr) { '*' } else { ' ' },
+                addr, bytes.join(" "), mnemonic, target,
+                digits = addr_digits, bytes = bytes_width);
+            let color = if addr == pc { pc_color } else { &colors.text };
+            if self.cursor == Some(addr) {
+                // The cursor line is drawn inverted
+                canvas.set_draw_color(*color);
+                let _ = canvas.fill_rect(Rect::new(area.x(), y - LINE_SPACING / 2, area.width(), line_h as u32));
+                p_bitmap_font::draw_text(canvas, x, y, &text, CHAR_SPACING, &colors.background, color);
+            } else {
+                p_bitmap_font::draw_text(canvas, x, y, &text, CHAR_SPACING, color, &colors.background);
+            }
+            y += line_h;
+        }
+        canvas.set_clip_rect(None);
+    }
+}
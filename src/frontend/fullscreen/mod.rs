@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use sdl2::{ VideoSubsystem, pixels::PixelFormatEnum, video::{ DisplayMode, FullscreenType, Window } };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FullscreenMode {
+    // A window covering the display at the desktop resolution
+    Borderless,
+    // The display switched to `Fullscreen::exclusive_mode`
+    Exclusive,
+}
+
+impl FromStr for FullscreenMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "borderless" | "desktop" => Ok(FullscreenMode::Borderless),
+            "exclusive" => Ok(FullscreenMode::Exclusive),
+            _ => Err(format!("Unknown fullscreen mode '{}', expected borderless or exclusive", s)),
+        }
+    }
+}
+
+/**
+ * `<w>x<h>` or `<w>x<h>@<hz>`, as a mode to look for with `closest_display_mode`.
+ */
+pub fn parse_display_mode(s: &str) -> Result<DisplayMode, String> {
+    let err = || format!("Invalid display mode '{}', expected <width>x<height> or <width>x<height>@<hz>", s);
+    let (size, hz) = match s.split_once('@') {
+        Some((size, hz)) => (size, hz.trim().parse::<i32>().map_err(|_| err())?),
+        // 0 takes any refresh rate
+        None => (s, 0),
+    };
+    let (w, h) = size.split_once('x').ok_or_else(err)?;
+    let w = w.trim().parse::<i32>().map_err(|_| err())?;
+    let h = h.trim().parse::<i32>().map_err(|_| err())?;
+    if w <= 0 || h <= 0 {
+        return Err(err());
+    }
+    Ok(DisplayMode::new(PixelFormatEnum::Unknown, w, h, hz))
+}
+
+/**
+ * Which display the window goes on and how it goes fullscreen there. The
+ * window keeps its size when switched back.
+ */
+pub struct Fullscreen {
+    pub display: i32,
+    pub mode: FullscreenMode,
+    // The mode exclusive fullscreen switches the display to
+    pub exclusive_mode: DisplayMode,
+}
+
+impl Fullscreen {
+    /**
+     * Checks `display` exists and finds the supported mode closest to
+     * `display_mode`, the desktop mode if it is empty.
+     */
+    pub fn new(video: &VideoSubsystem, display: u32, mode: FullscreenMode, display_mode: &str) -> Result<Fullscreen, String> {
+        let displays = video.num_video_displays()?;
+        if display as i32 >= displays {
+            return Err(format!("No display {}, there are {} (see --list-displays)", display, displays));
+        }
+        let display = display as i32;
+        let exclusive_mode = if display_mode.is_empty() {
+            video.desktop_display_mode(display)?
+        } else {
+            let wanted = parse_display_mode(display_mode)?;
+            video.closest_display_mode(display, &wanted)
+                .map_err(|_| format!("Display {} has no mode close to {}", display, display_mode))?
+        };
+        Ok(Fullscreen { display, mode, exclusive_mode })
+    }
+
+    /**
+     * Top left corner that centres a `width` x `height` window on the display.
+     */
+    pub fn window_position(self: &Self, video: &VideoSubsystem, width: u32, height: u32) -> Result<(i32, i32), String> {
+        let bounds = video.display_bounds(self.display)?;
+        Ok((bounds.x() + (bounds.width() as i32 - width as i32) / 2,
+            bounds.y() + (bounds.height() as i32 - height as i32) / 2))
+    }
+
+    pub fn is_on(window: &Window) -> bool {
+        window.fullscreen_state() != FullscreenType::Off
+    }
+
+    pub fn set(self: &Self, window: &mut Window, on: bool) -> Result<(), String> {
+        let fullscreen_type = match (on, self.mode) {
+            (false, _) => FullscreenType::Off,
+            (true, FullscreenMode::Borderless) => FullscreenType::Desktop,
+            (true, FullscreenMode::Exclusive) => {
+                window.set_display_mode(Some(self.exclusive_mode))?;
+                FullscreenType::True
+            },
+        };
+        window.set_fullscreen(fullscreen_type)
+    }
+
+    /**
+     * Switches fullscreen on or off, returns whether it is now on.
+     */
+    pub fn toggle(self: &Self, window: &mut Window) -> Result<bool, String> {
+        let on = !Fullscreen::is_on(window);
+        self.set(window, on)?;
+        Ok(on)
+    }
+}
+
+/**
+ * Every display with its position and desktop mode, then the modes it
+ * supports, numbered as `--display` takes them.
+ */
+pub fn print_displays(video: &VideoSubsystem) -> Result<(), String> {
+    for display in 0..video.num_video_displays()? {
+        let name = video.display_name(display).unwrap_or_else(|_| String::from("?"));
+        let bounds = video.display_bounds(display)?;
+        let desktop = video.desktop_display_mode(display)?;
+        println!("Display {}: {} at {},{}, desktop {}", display, name, bounds.x(), bounds.y(), mode_name(&desktop));
+        for i in 0..video.num_display_modes(display)? {
+            match video.display_mode(display, i) {
+                Ok(mode) => println!("    {}", mode_name(&mode)),
+                Err(err) => println!("    Mode {}: {}", i, err),
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * `<w>x<h>@<hz>`, the form `--display-mode` takes.
+ */
+pub fn mode_name(mode: &DisplayMode) -> String {
+    format!("{}x{}@{}", mode.w, mode.h, mode.refresh_rate)
+}
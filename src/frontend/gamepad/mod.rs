@@ -0,0 +1,161 @@
+use std::collections::{ BTreeMap, HashMap };
+
+use sdl2::{ GameControllerSubsystem, Sdl, controller::{ Axis, Button, GameController } };
+
+use crate::log_warn;
+use super::actions::Action;
+use super::menu::MenuInput;
+
+// How far (of 32767) the left stick has to be pushed to count as the D-pad
+const STICK_THRESHOLD: i16 = 16000;
+
+/**
+ * Connected SDL game controllers, each one a player with its own button
+ * mapping. Controllers are opened and dropped as SDL reports them, a
+ * controller plugged back in takes the first free player slot.
+ */
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // Index is the player, None for a slot whose controller was unplugged
+    pads: Vec<Option<GameController>>,
+    // Per player, button to core key
+    mappings: Vec<HashMap<Button, u8>>,
+    // D-pad buttons the left stick is holding down, by instance id
+    stick_held: HashMap<i32, Vec<Button>>,
+}
+
+impl Gamepads {
+    /**
+     * SDL sends `ControllerDeviceAdded` for controllers that are already
+     * plugged in, so nothing is opened here.
+     */
+    pub fn new(sdl: &Sdl) -> Result<Gamepads, String> {
+        Ok(Gamepads {
+            subsystem: sdl.game_controller()?,
+            pads: Vec::new(),
+            mappings: Vec::new(),
+            stick_held: HashMap::new(),
+        })
+    }
+
+    /**
+     * Per player, SDL button names ("a", "dpup", ...) to core keys. Players
+     * past the end of `mappings` have no buttons mapped.
+     */
+    pub fn set_mappings(self: &mut Self, mappings: &[BTreeMap<String, u8>]) {
+        self.mappings = mappings.iter()
+            .map(|buttons| buttons.iter()
+                .filter_map(|(name, key)| match Button::from_string(name) {
+                    Some(button) => Some((button, *key)),
+                    None => {
+                        log_warn!("[Pad] Unknown button name '{}'", name);
+                        None
+                    },
+                })
+                .collect())
+            .collect();
+    }
+
+    /**
+     * Opens the controller at `joystick_index`, returns its player number
+     * (from 1) and name. None for joysticks SDL has no controller mapping
+     * for and for ones that are already open.
+     */
+    pub fn open(self: &mut Self, joystick_index: u32) -> Option<(usize, String)> {
+        if !self.subsystem.is_game_controller(joystick_index) {
+            return None;
+        }
+        let pad = match self.subsystem.open(joystick_index) {
+            Ok(pad) => pad,
+            Err(err) => {
+                log_warn!("[Pad] Could not open controller {}: {}", joystick_index, err);
+                return None;
+            },
+        };
+        if self.player(pad.instance_id()).is_some() {
+            return None;
+        }
+        let name = pad.name();
+        let slot = match self.pads.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.pads.push(None);
+                self.pads.len() - 1
+            },
+        };
+        self.pads[slot] = Some(pad);
+        Some((slot + 1, name))
+    }
+
+    /**
+     * Drops the controller with `instance_id`, returns the player number it had.
+     */
+    pub fn close(self: &mut Self, instance_id: i32) -> Option<usize> {
+        let slot = self.player(instance_id)?;
+        self.pads[slot] = None;
+        self.stick_held.remove(&instance_id);
+        Some(slot + 1)
+    }
+
+    fn player(self: &Self, instance_id: i32) -> Option<usize> {
+        self.pads.iter().position(|pad| pad.as_ref().map(|pad| pad.instance_id()) == Some(instance_id))
+    }
+
+    /**
+     * The core key `button` on controller `instance_id` is mapped to.
+     */
+    pub fn key_for(self: &Self, instance_id: i32, button: Button) -> Option<u8> {
+        self.mappings.get(self.player(instance_id)?)?.get(&button).copied()
+    }
+
+    /**
+     * The left stick as a D-pad: the D-pad buttons that went down (true) or
+     * up (false) with this axis motion.
+     */
+    pub fn stick_buttons(self: &mut Self, instance_id: i32, axis: Axis, value: i16) -> Vec<(Button, bool)> {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (Button::DPadLeft, Button::DPadRight),
+            Axis::LeftY => (Button::DPadUp, Button::DPadDown),
+            _ => return Vec::new(),
+        };
+        let held = self.stick_held.entry(instance_id).or_default();
+        let mut changes = Vec::new();
+        for (button, pushed) in [(negative, value <= -STICK_THRESHOLD), (positive, value >= STICK_THRESHOLD)] {
+            let was_pushed = held.contains(&button);
+            if pushed && !was_pushed {
+                held.push(button);
+                changes.push((button, true));
+            } else if !pushed && was_pushed {
+                held.retain(|b| *b != button);
+                changes.push((button, false));
+            }
+        }
+        changes
+    }
+}
+
+/**
+ * Menu and ROM browser navigation: D-pad, A to pick, B or Start to go back.
+ */
+pub fn menu_input(button: Button) -> Option<MenuInput> {
+    match button {
+        Button::DPadUp => Some(MenuInput::Up),
+        Button::DPadDown => Some(MenuInput::Down),
+        Button::DPadLeft => Some(MenuInput::Left),
+        Button::DPadRight => Some(MenuInput::Right),
+        Button::A => Some(MenuInput::Select),
+        Button::B | Button::Start => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+/**
+ * Frontend actions for buttons that are not mapped to a core key.
+ */
+pub fn button_action(button: Button) -> Option<Action> {
+    match button {
+        Button::Start => Some(Action::OpenMenu),
+        Button::Back => Some(Action::ShowHelp),
+        _ => None,
+    }
+}
@@ -0,0 +1,351 @@
+use std::{ ffi::CString, fs, path::{ Path, PathBuf }, ptr };
+
+use gl::types::{ GLchar, GLenum, GLint, GLuint };
+use sdl2::rect::Rect;
+
+use crate::emulators::{ Emulator, ScreenResolution };
+
+/**
+ * Post-processing chain for the emulator screen.
+ *
+ * The screen is uploaded as a texture at its native resolution and then run
+ * through the fragment shaders in order, each one rendering into its own
+ * texture. A built-in pass copies the result into the window.
+ *
+ * Every shader gets the shared vertex stage below and these inputs:
+ *
+ *   in vec2 v_uv;                   0,0 is the bottom left
+ *   uniform sampler2D u_source;     output of the previous pass (or the emulator screen)
+ *   uniform sampler2D u_prev;       this pass' own output from the last frame
+ *   uniform vec2 u_source_size;     size of u_source in pixels
+ *   uniform vec2 u_output_size;     size of this pass' output in pixels
+ *   uniform vec2 u_native_size;     emulator screen resolution
+ *   uniform int u_frame;
+ *
+ * A shader can add `#pragma scale source` to render at the size of its input
+ * instead of the window size, and `#pragma filter linear` to sample its input
+ * bilinearly instead of with nearest-neighbour.
+ */
+const VERTEX_SHADER: &str = "#version 330 core
+layout(location = 0) in vec2 a_pos;
+out vec2 v_uv;
+void main() {
+    v_uv = a_pos * 0.5 + 0.5;
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+}
+";
+
+const BLIT_SHADER: &str = "#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+uniform sampler2D u_source;
+void main() {
+    frag_color = vec4(texture(u_source, v_uv).rgb, 1.0);
+}
+";
+
+struct RenderTarget {
+    fbo: GLuint,
+    texture: GLuint,
+    width: u32,
+    height: u32,
+}
+
+struct ShaderPass {
+    name: String,
+    program: GLuint,
+    scale_to_source: bool,
+    linear_filter: bool,
+    // Ping-pong pair, the one not written this frame is last frame's output for u_prev
+    targets: Vec<RenderTarget>,
+    current: usize,
+}
+
+pub struct GlRenderer {
+    vao: GLuint,
+    vbo: GLuint,
+    source_texture: GLuint,
+    source_res: ScreenResolution,
+    pixel_buf: Vec<u8>,
+    upload_buf: Vec<u8>,
+    passes: Vec<ShaderPass>,
+    blit_program: GLuint,
+    frame_count: i32,
+}
+
+impl GlRenderer {
+    /**
+     * Needs a current GL 3.3 core context with the `gl` function pointers loaded.
+     */
+    pub fn new(shader_paths: &[PathBuf]) -> Result<GlRenderer, String> {
+        let mut renderer = GlRenderer {
+            vao: 0,
+            vbo: 0,
+            source_texture: 0,
+            source_res: ScreenResolution { width: 0, height: 0 },
+            pixel_buf: Vec::new(),
+            upload_buf: Vec::new(),
+            passes: Vec::new(),
+            blit_program: link_program(BLIT_SHADER)?,
+            frame_count: 0,
+        };
+
+        let quad: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::BindVertexArray(renderer.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(&quad) as isize,
+                quad.as_ptr() as *const _, gl::STATIC_DRAW);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+            gl::BindVertexArray(0);
+
+            gl::GenTextures(1, &mut renderer.source_texture);
+        }
+
+        for path in shader_paths {
+            let source = fs::read_to_string(path)
+                .map_err(|e| format!("Could not read shader {}: {}", path.display(), e))?;
+            let program = link_program(&source)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            renderer.passes.push(ShaderPass {
+                name: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+                program,
+                scale_to_source: has_pragma(&source, "scale source"),
+                linear_filter: has_pragma(&source, "filter linear"),
+                targets: Vec::new(),
+                current: 0,
+            });
+        }
+        Ok(renderer)
+    }
+
+    pub fn pass_names(self: &Self) -> Vec<String> {
+        self.passes.iter().map(|p| p.name.clone()).collect()
+    }
+
+    /**
+     * Draws the emulator screen into `dst` (window coordinates, top left origin)
+     * of a `drawable_height` tall window.
+     */
+    pub fn draw(self: &mut Self, emu: &dyn Emulator, dst: Rect, drawable_height: u32) -> Result<(), String> {
+        let res = emu.get_screen_resolution();
+        self.upload_source(emu, &res)?;
+
+        let mut input = self.source_texture;
+        let mut input_size = (res.width, res.height);
+        for pass in self.passes.iter_mut() {
+            let output_size = if pass.scale_to_source { input_size } else { (dst.width(), dst.height()) };
+            pass.resize_targets(output_size);
+            let prev = pass.targets[1 - pass.current].texture;
+            let target = &pass.targets[pass.current];
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+                gl::Viewport(0, 0, target.width as i32, target.height as i32);
+                gl::UseProgram(pass.program);
+                bind_texture(0, input, if pass.linear_filter { gl::LINEAR } else { gl::NEAREST });
+                bind_texture(1, prev, gl::NEAREST);
+                set_uniform_i(pass.program, "u_source", 0);
+                set_uniform_i(pass.program, "u_prev", 1);
+                set_uniform_2f(pass.program, "u_source_size", input_size);
+                set_uniform_2f(pass.program, "u_output_size", output_size);
+                set_uniform_2f(pass.program, "u_native_size", (res.width, res.height));
+                set_uniform_i(pass.program, "u_frame", self.frame_count);
+                draw_quad(self.vao);
+            }
+            input = target.texture;
+            input_size = output_size;
+            pass.current = 1 - pass.current;
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(dst.x(), drawable_height as i32 - dst.y() - dst.height() as i32,
+                dst.width() as i32, dst.height() as i32);
+            gl::UseProgram(self.blit_program);
+            bind_texture(0, input, gl::NEAREST);
+            set_uniform_i(self.blit_program, "u_source", 0);
+            draw_quad(self.vao);
+            gl::UseProgram(0);
+        }
+        self.frame_count = self.frame_count.wrapping_add(1);
+        Ok(())
+    }
+
+    fn upload_source(self: &mut Self, emu: &dyn Emulator, res: &ScreenResolution) -> Result<(), String> {
+        let row_len = (res.width * 4) as usize;
+        if res.width != self.source_res.width || res.height != self.source_res.height {
+            self.pixel_buf = [0, 0, 0, 0xff].repeat((res.width * res.height) as usize);
+            self.upload_buf = vec![0; self.pixel_buf.len()];
+            self.source_res = ScreenResolution { width: res.width, height: res.height };
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D, self.source_texture);
+                gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, res.width as i32, res.height as i32,
+                    0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+            }
+        }
+        if emu.draw_to_buffer_rgba(&mut self.pixel_buf, res).is_err() {
+            return Err(String::from("Emulator could not draw its screen"));
+        }
+        // Emulator rows go top to bottom, GL textures bottom to top
+        for (dst_row, src_row) in self.upload_buf.chunks_mut(row_len).zip(self.pixel_buf.chunks(row_len).rev()) {
+            dst_row.copy_from_slice(src_row);
+        }
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.source_texture);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, res.width as i32, res.height as i32,
+                gl::RGBA, gl::UNSIGNED_BYTE, self.upload_buf.as_ptr() as *const _);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GlRenderer {
+    fn drop(self: &mut Self) {
+        unsafe {
+            for pass in self.passes.iter_mut() {
+                pass.delete_targets();
+                gl::DeleteProgram(pass.program);
+            }
+            gl::DeleteProgram(self.blit_program);
+            gl::DeleteTextures(1, &self.source_texture);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl ShaderPass {
+    fn resize_targets(self: &mut Self, size: (u32, u32)) {
+        if self.targets.len() == 2 && self.targets[0].width == size.0 && self.targets[0].height == size.1 {
+            return;
+        }
+        self.delete_targets();
+        for _ in 0..2 {
+            let mut target = RenderTarget { fbo: 0, texture: 0, width: size.0, height: size.1 };
+            unsafe {
+                gl::GenTextures(1, &mut target.texture);
+                gl::BindTexture(gl::TEXTURE_2D, target.texture);
+                // Cleared to black so u_prev starts out empty
+                let black = vec![0u8; (size.0 * size.1 * 4) as usize];
+                gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, size.0 as i32, size.1 as i32,
+                    0, gl::RGBA, gl::UNSIGNED_BYTE, black.as_ptr() as *const _);
+                gl::GenFramebuffers(1, &mut target.fbo);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_2D, target.texture, 0);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            }
+            self.targets.push(target);
+        }
+        self.current = 0;
+    }
+
+    fn delete_targets(self: &mut Self) {
+        for target in self.targets.drain(..) {
+            unsafe {
+                gl::DeleteFramebuffers(1, &target.fbo);
+                gl::DeleteTextures(1, &target.texture);
+            }
+        }
+    }
+}
+
+/**
+ * `name` is looked up as `<shader_dir>/<name>.frag` unless it is a path to a file already.
+ */
+pub fn resolve_shader_path(shader_dir: &Path, name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.extension().is_some() || path.components().count() > 1 {
+        path
+    } else {
+        shader_dir.join(format!("{}.frag", name))
+    }
+}
+
+fn has_pragma(source: &str, pragma: &str) -> bool {
+    source.lines().any(|line| {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        words.first() == Some(&"#pragma") && words[1..].join(" ") == pragma
+    })
+}
+
+unsafe fn draw_quad(vao: GLuint) {
+    gl::BindVertexArray(vao);
+    gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+    gl::BindVertexArray(0);
+}
+
+unsafe fn bind_texture(unit: u32, texture: GLuint, filter: GLenum) {
+    gl::ActiveTexture(gl::TEXTURE0 + unit);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+}
+
+unsafe fn uniform_location(program: GLuint, name: &str) -> GLint {
+    let name = CString::new(name).unwrap();
+    gl::GetUniformLocation(program, name.as_ptr())
+}
+
+unsafe fn set_uniform_i(program: GLuint, name: &str, val: i32) {
+    gl::Uniform1i(uniform_location(program, name), val);
+}
+
+unsafe fn set_uniform_2f(program: GLuint, name: &str, val: (u32, u32)) {
+    gl::Uniform2f(uniform_location(program, name), val.0 as f32, val.1 as f32);
+}
+
+fn compile_shader(source: &str, kind: GLenum) -> Result<GLuint, String> {
+    let source = CString::new(source).map_err(|e| e.to_string())?;
+    unsafe {
+        let shader = gl::CreateShader(kind);
+        gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+        let mut status = gl::FALSE as GLint;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+        if status != gl::TRUE as GLint {
+            let mut len = 0;
+            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+            let mut log = vec![0u8; len.max(1) as usize];
+            gl::GetShaderInfoLog(shader, len, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            gl::DeleteShader(shader);
+            return Err(format!("Shader compile error: {}", String::from_utf8_lossy(&log).trim_end_matches('\0')));
+        }
+        Ok(shader)
+    }
+}
+
+fn link_program(fragment_source: &str) -> Result<GLuint, String> {
+    let vertex = compile_shader(VERTEX_SHADER, gl::VERTEX_SHADER)?;
+    let fragment = match compile_shader(fragment_source, gl::FRAGMENT_SHADER) {
+        Ok(fragment) => fragment,
+        Err(err) => {
+            unsafe { gl::DeleteShader(vertex) };
+            return Err(err);
+        },
+    };
+    unsafe {
+        let program = gl::CreateProgram();
+        gl::AttachShader(program, vertex);
+        gl::AttachShader(program, fragment);
+        gl::LinkProgram(program);
+        gl::DeleteShader(vertex);
+        gl::DeleteShader(fragment);
+        let mut status = gl::FALSE as GLint;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status != gl::TRUE as GLint {
+            let mut len = 0;
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+            let mut log = vec![0u8; len.max(1) as usize];
+            gl::GetProgramInfoLog(program, len, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            gl::DeleteProgram(program);
+            return Err(format!("Shader link error: {}", String::from_utf8_lossy(&log).trim_end_matches('\0')));
+        }
+        Ok(program)
+    }
+}
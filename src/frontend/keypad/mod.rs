@@ -0,0 +1,130 @@
+use std::{ collections::HashMap, time::{ Duration, Instant } };
+
+use sdl2::{ rect::Rect, render::WindowCanvas };
+
+use crate::p_bitmap_font::{ self, CHAR_HEIGHT, CHAR_WIDTH };
+use super::osd::OverlayColors;
+
+// How long a key stays highlighted after the program last tested it
+const POLL_HIGHLIGHT: Duration = Duration::from_millis(250);
+// Pixels between the keys
+const KEY_GAP: i32 = 4;
+// Pointer id of the mouse, fingers use the id SDL gives them
+pub const MOUSE_POINTER: i64 = -1;
+// `which` of the mouse events SDL makes up from touches, the finger events
+// already cover those
+pub const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+/**
+ * Clickable keypad drawn next to the screen, for mouse and touch input.
+ * Each pointer (the mouse or a finger) holds down the key under it, keys
+ * the program polls are outlined.
+ */
+pub struct Keypad {
+    // Core keys, a row at a time, see `SystemInfo::keypad`
+    rows: &'static [&'static [u8]],
+    // Key each pointer is holding down
+    pointers: HashMap<i64, u8>,
+    // When the program last tested each key
+    polled: HashMap<u8, Instant>,
+}
+
+impl Keypad {
+    pub fn new(rows: &'static [&'static [u8]]) -> Keypad {
+        Keypad { rows, pointers: HashMap::new(), polled: HashMap::new() }
+    }
+
+    fn columns(self: &Self) -> usize {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    fn key_rect(self: &Self, area: Rect, row: usize, column: usize) -> Rect {
+        let key_w = (area.width() as i32 - KEY_GAP) / self.columns().max(1) as i32 - KEY_GAP;
+        let key_h = (area.height() as i32 - KEY_GAP) / self.rows.len().max(1) as i32 - KEY_GAP;
+        Rect::new(area.x() + KEY_GAP + column as i32 * (key_w + KEY_GAP),
+            area.y() + KEY_GAP + row as i32 * (key_h + KEY_GAP), key_w.max(1) as u32, key_h.max(1) as u32)
+    }
+
+    fn key_at(self: &Self, area: Rect, x: i32, y: i32) -> Option<u8> {
+        self.rows.iter().enumerate()
+            .flat_map(|(row, keys)| keys.iter().enumerate().map(move |(column, key)| (row, column, *key)))
+            .find(|(row, column, _)| self.key_rect(area, *row, *column).contains_point((x, y)))
+            .map(|(_, _, key)| key)
+    }
+
+    fn held_keys(self: &Self) -> Vec<u8> {
+        let mut keys: Vec<u8> = self.pointers.values().copied().collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /**
+     * Moves `pointer` to `pos`, None lifts it. Returns the keys that went
+     * down (true) or up (false), a key stays down while any pointer holds it.
+     */
+    pub fn on_pointer(self: &mut Self, area: Rect, pointer: i64, pos: Option<(i32, i32)>) -> Vec<(u8, bool)> {
+        let before = self.held_keys();
+        match pos.and_then(|(x, y)| self.key_at(area, x, y)) {
+            Some(key) => self.pointers.insert(pointer, key),
+            None => self.pointers.remove(&pointer),
+        };
+        let after = self.held_keys();
+        let pressed = after.iter().filter(|key| !before.contains(key)).map(|key| (*key, true));
+        let released = before.iter().filter(|key| !after.contains(key)).map(|key| (*key, false));
+        released.chain(pressed).collect()
+    }
+
+    /**
+     * Lifts every pointer, returns the keys that were held.
+     */
+    pub fn release_all(self: &mut Self) -> Vec<u8> {
+        let keys = self.held_keys();
+        self.pointers.clear();
+        keys
+    }
+
+    /**
+     * Takes `EmuTrait::take_polled_keys`, one bit per key.
+     */
+    pub fn update_polled(self: &mut Self, keys: u16) {
+        let now = Instant::now();
+        for key in (0..16).filter(|key| keys & (1 << key) != 0) {
+            self.polled.insert(key, now);
+        }
+    }
+
+    pub fn draw(self: &Self, canvas: &mut WindowCanvas, area: Rect, colors: &OverlayColors) {
+        canvas.set_draw_color(colors.background);
+        let _ = canvas.fill_rect(area);
+
+        let held = self.held_keys();
+        let now = Instant::now();
+        for (row, keys) in self.rows.iter().enumerate() {
+            for (column, key) in keys.iter().enumerate() {
+                let rect = self.key_rect(area, row, column);
+                let polled = self.polled.get(key).is_some_and(|at| now - *at < POLL_HIGHLIGHT);
+                let (fill, label) = if held.contains(key) {
+                    (colors.text, colors.background)
+                } else {
+                    (colors.background, if polled { colors.selected } else { colors.text })
+                };
+                canvas.set_draw_color(fill);
+                let _ = canvas.fill_rect(rect);
+                canvas.set_draw_color(if polled { colors.selected } else { colors.text });
+                let _ = canvas.draw_rect(rect);
+                if polled {
+                    let _ = canvas.draw_rect(Rect::new(rect.x() + 1, rect.y() + 1,
+                        rect.width().saturating_sub(2).max(1), rect.height().saturating_sub(2).max(1)));
+                }
+
+                // Label at half the key height, whole font pixels only
+                let scale = ((rect.height() as i32 / 2) / CHAR_HEIGHT).max(1);
+                let label_x = rect.x() + (rect.width() as i32 - CHAR_WIDTH * scale) / 2;
+                let label_y = rect.y() + (rect.height() as i32 - CHAR_HEIGHT * scale) / 2;
+                let c = char::from_digit(*key as u32, 16).unwrap_or('?').to_ascii_uppercase();
+                p_bitmap_font::draw_letter_scaled(canvas, label_x, label_y, c, scale as u32, &label);
+            }
+        }
+    }
+}
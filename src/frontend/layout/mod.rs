@@ -0,0 +1,83 @@
+use sdl2::rect::Rect;
+
+use crate::emulators::{ Emulator, ScreenResolution };
+
+// Space around the debug pane contents, in pixels
+pub const PANE_MARGIN: u32 = 4;
+// Initial window size: the screen at up to 8x, but no wider than 1024 pixels
+pub const MAX_INITIAL_SCALE: u32 = 8;
+pub const MAX_INITIAL_SCREEN_WIDTH: u32 = 1024;
+// Share of the window height the on-screen keypad takes, in percent
+pub const KEYPAD_HEIGHT_PERCENT: u32 = 40;
+
+/**
+ * Where things go in the window. Recomputed when the window is resized or
+ * the debugger pane is toggled.
+ */
+pub struct Layout {
+    // Area the emulator screen is fitted into
+    pub screen: Rect,
+    // Right hand column for the CPU info, if shown
+    pub debug_pane: Option<Rect>,
+    // Square below the screen for the on-screen keypad, if shown
+    pub keypad: Option<Rect>,
+}
+
+impl Layout {
+    /**
+     * Splits an `output_w` x `output_h` window between the screen, the
+     * debugger pane, which is sized from `get_cpu_screen_resolution` but never
+     * takes more than half the width, and the keypad under the screen.
+     */
+    pub fn new(emu: &dyn Emulator, output_w: u32, output_h: u32, show_debug: bool, show_keypad: bool) -> Layout {
+        let output_w = output_w.max(1);
+        let output_h = output_h.max(1);
+        let (screen_w, debug_pane) = if show_debug {
+            let cpu_res = emu.get_cpu_screen_resolution();
+            let pane_w = (cpu_res.width + 2 * PANE_MARGIN).min(output_w / 2).max(1);
+            let screen_w = (output_w - pane_w).max(1);
+            (screen_w, Some(Rect::new(screen_w as i32, 0, pane_w, output_h)))
+        } else {
+            (output_w, None)
+        };
+        if !show_keypad {
+            return Layout {
+                screen: Rect::new(0, 0, screen_w, output_h),
+                debug_pane,
+                keypad: None,
+            };
+        }
+
+        let keypad_h = (output_h * KEYPAD_HEIGHT_PERCENT / 100).max(1);
+        let keypad_w = keypad_h.min(screen_w);
+        let screen_h = (output_h - keypad_h).max(1);
+        Layout {
+            screen: Rect::new(0, 0, screen_w, screen_h),
+            debug_pane,
+            keypad: Some(Rect::new((screen_w - keypad_w) as i32 / 2, screen_h as i32, keypad_w, keypad_h)),
+        }
+    }
+
+    /**
+     * Window size to start with, the screen at a whole scale plus the debugger
+     * pane and the keypad. Without a `scale` the largest one up to `MAX_INITIAL_SCALE` that
+     * stays within `MAX_INITIAL_SCREEN_WIDTH` is used.
+     */
+    pub fn initial_window_size(emu: &dyn Emulator, show_debug: bool, show_keypad: bool, scale: Option<u32>) -> ScreenResolution {
+        let res = emu.get_screen_resolution();
+        let scale = scale.unwrap_or_else(|| (MAX_INITIAL_SCREEN_WIDTH / res.width.max(1)).clamp(1, MAX_INITIAL_SCALE));
+        let mut size = ScreenResolution {
+            width: res.width * scale,
+            height: res.height * scale,
+        };
+        if show_keypad {
+            size.height = size.height * 100 / (100 - KEYPAD_HEIGHT_PERCENT);
+        }
+        if show_debug {
+            let cpu_res = emu.get_cpu_screen_resolution();
+            size.width += cpu_res.width + 2 * PANE_MARGIN;
+            size.height = size.height.max(cpu_res.height + 2 * PANE_MARGIN);
+        }
+        size
+    }
+}
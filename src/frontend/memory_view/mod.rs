@@ -0,0 +1,304 @@
+use std::{ collections::HashMap, ops::Range, time::{ Duration, Instant } };
+
+use sdl2::{ keyboard::Keycode, pixels::Color, rect::Rect, render::WindowCanvas };
+
+use crate::emulators::{ Emulator, MemoryMark };
+use crate::p_bitmap_font::{ self, CHAR_HEIGHT, CHAR_WIDTH };
+use super::menu::MenuInput;
+use super::osd::OverlayColors;
+
+// How long a byte stays highlighted after it changed
+const WRITE_HIGHLIGHT: Duration = Duration::from_millis(1000);
+// Pixels between glyphs, between lines and around the text, as in `osd`
+const CHAR_SPACING: i32 = 2;
+const LINE_SPACING: i32 = 2;
+const PADDING: i32 = 4;
+
+pub struct MemoryColors {
+    pub pc: Color,
+    // Bytes a register points at, see `MemoryMark::is_pointer`
+    pub pointer: Color,
+    // Fixed areas such as the font
+    pub region: Color,
+    pub written: Color,
+}
+
+pub enum MemoryViewAction {
+    None,
+    Close,
+    // To show on screen, e.g. why an edit didn't happen
+    Message(String),
+}
+
+/**
+ * Hex and ASCII dump of the core's memory over the screen. The cursor byte
+ * can be overwritten by typing two hex digits while the debugger is paused,
+ * G jumps to a typed address and Home to the PC.
+ */
+pub struct MemoryView {
+    range: Range<u64>,
+    cursor: u64,
+    // Address of the first row on screen
+    scroll: u64,
+    // Set by `draw` from the area it was given
+    bytes_per_row: u64,
+    rows: u64,
+    // First digit typed over the cursor byte
+    pending_nibble: Option<u8>,
+    // Hex digits typed after G, None when not entering an address
+    goto: Option<String>,
+    // Bytes on screen as last drawn, to spot the ones that changed
+    seen: HashMap<u64, u8>,
+    written: HashMap<u64, Instant>,
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    let name = keycode.name();
+    let name = name.strip_prefix("Keypad ").unwrap_or(&name);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+impl MemoryView {
+    /**
+     * None for cores without a memory range to show.
+     */
+    pub fn new(emu: &dyn Emulator) -> Option<MemoryView> {
+        let range = emu.get_memory_range()?;
+        let pc = emu.get_program_counter();
+        let cursor = if range.contains(&pc) { pc } else { range.start };
+        Some(MemoryView {
+            range,
+            cursor,
+            scroll: cursor,
+            bytes_per_row: 8,
+            rows: 1,
+            pending_nibble: None,
+            goto: None,
+            seen: HashMap::new(),
+            written: HashMap::new(),
+        })
+    }
+
+    fn move_cursor(self: &mut Self, offset: i64) {
+        let last = self.range.end - 1;
+        self.cursor = self.cursor.saturating_add_signed(offset).clamp(self.range.start, last);
+        self.pending_nibble = None;
+    }
+
+    /**
+     * None for keys the view has no use for, which the caller can treat as
+     * hotkeys. `paused` allows editing.
+     */
+    pub fn on_key(self: &mut Self, keycode: Keycode, emu: &mut dyn Emulator, paused: bool) -> Option<MemoryViewAction> {
+        if let Some(typed) = &mut self.goto {
+            match keycode {
+                Keycode::Escape => self.goto = None,
+                Keycode::Backspace => {
+                    typed.pop();
+                },
+                Keycode::Return | Keycode::KpEnter => {
+                    let addr = u64::from_str_radix(typed, 16).ok().filter(|addr| self.range.contains(addr));
+                    self.goto = None;
+                    match addr {
+                        Some(addr) => self.jump_to(addr),
+                        None => return Some(MemoryViewAction::Message(String::from("Address out of range"))),
+                    }
+                },
+                _ => {
+                    if let Some(digit) = hex_digit(keycode) {
+                        if typed.len() < 16 {
+                            typed.push(char::from_digit(digit as u32, 16).unwrap().to_ascii_uppercase());
+                        }
+                    }
+                },
+            }
+            return Some(MemoryViewAction::None);
+        }
+
+        let row = self.bytes_per_row as i64;
+        let page = row * self.rows as i64;
+        match keycode {
+            Keycode::Escape => return Some(MemoryViewAction::Close),
+            Keycode::Up => self.move_cursor(-row),
+            Keycode::Down => self.move_cursor(row),
+            Keycode::Left => self.move_cursor(-1),
+            Keycode::Right => self.move_cursor(1),
+            Keycode::PageUp => self.move_cursor(-page),
+            Keycode::PageDown => self.move_cursor(page),
+            Keycode::Home => {
+                let pc = emu.get_program_counter();
+                if self.range.contains(&pc) {
+                    self.jump_to(pc);
+                }
+            },
+            Keycode::G => {
+                self.pending_nibble = None;
+                self.goto = Some(String::new());
+            },
+            Keycode::Backspace => self.pending_nibble = None,
+            _ => {
+                let digit = hex_digit(keycode)?;
+                if !paused {
+                    return Some(MemoryViewAction::Message(String::from("Pause to edit memory")));
+                }
+                match self.pending_nibble.take() {
+                    None => self.pending_nibble = Some(digit),
+                    Some(high) => {
+                        if let Err(err) = emu.poke(self.cursor, (high << 4) | digit) {
+                            return Some(MemoryViewAction::Message(err));
+                        }
+                        self.move_cursor(1);
+                    },
+                }
+            },
+        }
+        Some(MemoryViewAction::None)
+    }
+
+    pub fn on_input(self: &mut Self, input: MenuInput) -> MemoryViewAction {
+        match input {
+            MenuInput::Up => self.move_cursor(-(self.bytes_per_row as i64)),
+            MenuInput::Down => self.move_cursor(self.bytes_per_row as i64),
+            MenuInput::Left => self.move_cursor(-1),
+            MenuInput::Right => self.move_cursor(1),
+            MenuInput::Back => return MemoryViewAction::Close,
+            MenuInput::Select => {},
+        }
+        MemoryViewAction::None
+    }
+
+    fn jump_to(self: &mut Self, addr: u64) {
+        self.cursor = addr;
+        self.pending_nibble = None;
+        // Put it on the top row
+        self.scroll = self.row_start(addr);
+    }
+
+    fn row_start(self: &Self, addr: u64) -> u64 {
+        addr - (addr - self.range.start) % self.bytes_per_row
+    }
+
+    /**
+     * Notes which of the bytes on screen changed since the last frame.
+     */
+    fn update_written(self: &mut Self, emu: &dyn Emulator, visible: Range<u64>) {
+        let now = Instant::now();
+        self.seen.retain(|addr, _| visible.contains(addr));
+        self.written.retain(|_, at| now - *at < WRITE_HIGHLIGHT);
+        for addr in visible {
+            if let Some(value) = emu.peek(addr) {
+                if self.seen.insert(addr, value).is_some_and(|old| old != value) {
+                    self.written.insert(addr, now);
+                }
+            }
+        }
+    }
+
+    fn byte_color<'a>(self: &Self, addr: u64, pc: &Range<u64>, memory_marks: &[MemoryMark],
+        colors: &'a OverlayColors, marks: &'a MemoryColors) -> &'a Color {
+        let marked = |pointer: bool| memory_marks.iter()
+            .any(|mark| mark.is_pointer == pointer && (mark.start..mark.start + mark.len).contains(&addr));
+        if self.written.contains_key(&addr) {
+            &marks.written
+        } else if pc.contains(&addr) {
+            &marks.pc
+        } else if marked(true) {
+            &marks.pointer
+        } else if marked(false) {
+            &marks.region
+        } else {
+            &colors.text
+        }
+    }
+
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, area: Rect, emu: &dyn Emulator, paused: bool, colors: &OverlayColors, marks: &MemoryColors) {
+        let char_w = CHAR_WIDTH + CHAR_SPACING;
+        let line_h = CHAR_HEIGHT + LINE_SPACING;
+        let addr_digits = format!("{:X}", self.range.end - 1).len().max(4);
+        let columns = ((area.width() as i32 - 2 * PADDING) / char_w).max(1) as usize;
+        // Address, then 3 columns for the hex and 1 for the character of each byte
+        self.bytes_per_row = [16, 8, 4].into_iter()
+            .find(|bytes| addr_digits + 2 + bytes * 4 <= columns)
+            .unwrap_or(4) as u64;
+        // Title and legend lines with a blank line after and before
+        self.rows = (((area.height() as i32 - 2 * PADDING) / line_h - 4).max(1)) as u64;
+
+        // Keep the cursor row on screen
+        let cursor_row = self.row_start(self.cursor);
+        let page = self.bytes_per_row * self.rows;
+        self.scroll = self.row_start(self.scroll.clamp(self.range.start, self.range.end - 1));
+        if cursor_row < self.scroll {
+            self.scroll = cursor_row;
+        } else if cursor_row >= self.scroll + page {
+            self.scroll = cursor_row + self.bytes_per_row - page;
+        }
+        let visible = self.scroll..(self.scroll + page).min(self.range.end);
+        self.update_written(emu, visible.clone());
+        let pc = emu.get_program_counter();
+        let pc = pc..pc + emu.disassemble(pc).map_or(1, |(_, len)| len);
+        let memory_marks = emu.get_memory_marks();
+
+        canvas.set_draw_color(colors.background);
+        let _ = canvas.fill_rect(area);
+        canvas.set_clip_rect(area);
+
+        let x = area.x() + PADDING;
+        let mut y = area.y() + PADDING;
+        let title = match &self.goto {
+            Some(typed) => format!("Go to: {}_  Enter jumps, Esc cancels", typed),
+            None if paused => format!("Memory 0x{:X}  G go to, Home PC, hex digits edit", self.cursor),
+            None => format!("Memory 0x{:X}  G go to, Home PC, pause to edit", self.cursor),
+        };
+        p_bitmap_font::draw_text(canvas, x, y, &title, CHAR_SPACING, &colors.selected, &colors.background);
+        y += 2 * line_h;
+
+        for row in visible.clone().step_by(self.bytes_per_row as usize) {
+            let row_end = (row + self.bytes_per_row).min(self.range.end);
+            p_bitmap_font::draw_text(canvas, x, y, &format!("{:0width$X}", row, width = addr_digits), CHAR_SPACING, &colors.text, &colors.background);
+            let hex_x = x + (addr_digits as i32 + 1) * char_w;
+            let ascii_x = hex_x + (self.bytes_per_row as i32 * 3 + 1) * char_w;
+            for addr in row..row_end {
+                let i = (addr - row) as i32;
+                let value = emu.peek(addr);
+                let text = match (value, self.pending_nibble) {
+                    (_, Some(high)) if addr == self.cursor => format!("{:X}_", high),
+                    (Some(value), _) => format!("{:02X}", value),
+                    (None, _) => String::from("??"),
+                };
+                let c = match value {
+                    Some(value) if value.is_ascii_graphic() => value as char,
+                    _ => '.',
+                };
+                let color = self.byte_color(addr, &pc, &memory_marks, colors, marks);
+                if addr == self.cursor {
+                    // The cursor byte is drawn inverted
+                    canvas.set_draw_color(*color);
+                    let _ = canvas.fill_rect(Rect::new(hex_x + i * 3 * char_w - CHAR_SPACING / 2, y - LINE_SPACING / 2,
+                        (2 * char_w) as u32, line_h as u32));
+                    p_bitmap_font::draw_text(canvas, hex_x + i * 3 * char_w, y, &text, CHAR_SPACING, &colors.background, color);
+                } else {
+                    p_bitmap_font::draw_text(canvas, hex_x + i * 3 * char_w, y, &text, CHAR_SPACING, color, &colors.background);
+                }
+                p_bitmap_font::draw_letter(canvas, ascii_x + i * char_w, y, c as i32, color, &colors.background);
+            }
+            y += line_h;
+        }
+
+        // Legend in the colours it explains
+        let mut legend_x = x;
+        let legend_y = area.y() + PADDING + (self.rows as i32 + 3) * line_h;
+        let mut names = vec![(String::from("PC"), &marks.pc)];
+        for mark in memory_marks {
+            names.push((mark.name.to_string(), if mark.is_pointer { &marks.pointer } else { &marks.region }));
+        }
+        names.push((String::from("written"), &marks.written));
+        for (name, color) in names {
+            legend_x = p_bitmap_font::draw_text(canvas, legend_x, legend_y, &name, CHAR_SPACING, color, &colors.background) + char_w;
+        }
+        canvas.set_clip_rect(None);
+    }
+}
@@ -0,0 +1,108 @@
+use sdl2::{ keyboard::Keycode, rect::Rect, render::WindowCanvas };
+
+use super::actions::Action;
+use super::osd::{ self, OverlayColors };
+
+/**
+ * Menu navigation, so the keyboard and gamepads can drive the same menus.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+impl MenuInput {
+    pub fn from_keycode(keycode: Keycode) -> Option<MenuInput> {
+        match keycode {
+            Keycode::Up => Some(MenuInput::Up),
+            Keycode::Down => Some(MenuInput::Down),
+            Keycode::Left => Some(MenuInput::Left),
+            Keycode::Right => Some(MenuInput::Right),
+            Keycode::Return | Keycode::KpEnter | Keycode::Space => Some(MenuInput::Select),
+            Keycode::Escape | Keycode::Backspace => Some(MenuInput::Back),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuKind {
+    Pause,
+    Settings,
+}
+
+pub struct MenuItem {
+    pub label: String,
+    // Run by Select and Right
+    pub action: Action,
+    // Run by Left, for entries that step a value back and forth
+    pub back_action: Option<Action>,
+}
+
+impl MenuItem {
+    pub fn new(label: &str, action: Action) -> MenuItem {
+        MenuItem { label: label.to_string(), action, back_action: None }
+    }
+
+    pub fn value(label: &str, value: &str, back_action: Action, action: Action) -> MenuItem {
+        MenuItem { label: format!("{:10} < {} >", label, value), action, back_action: Some(back_action) }
+    }
+}
+
+/**
+ * A list of actions, one of them selected. The frontend rebuilds it when the
+ * values shown change.
+ */
+pub struct Menu {
+    pub kind: MenuKind,
+    pub title: String,
+    items: Vec<MenuItem>,
+    selected: usize,
+    // Run by Back
+    back: Action,
+}
+
+impl Menu {
+    pub fn new(kind: MenuKind, title: &str, items: Vec<MenuItem>, back: Action) -> Menu {
+        Menu { kind, title: title.to_string(), items, selected: 0, back }
+    }
+
+    pub fn selected(self: &Self) -> usize {
+        self.selected
+    }
+
+    pub fn with_selected(mut self: Self, selected: usize) -> Menu {
+        self.selected = selected.min(self.items.len().saturating_sub(1));
+        self
+    }
+
+    pub fn on_input(self: &mut Self, input: MenuInput) -> Option<Action> {
+        let item = self.items.get(self.selected)?;
+        match input {
+            MenuInput::Up => {
+                self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+                None
+            },
+            MenuInput::Down => {
+                self.selected = (self.selected + 1) % self.items.len();
+                None
+            },
+            MenuInput::Left => item.back_action,
+            MenuInput::Right => item.back_action.map(|_| item.action),
+            MenuInput::Select => Some(item.action),
+            MenuInput::Back => Some(self.back),
+        }
+    }
+
+    pub fn draw(self: &Self, canvas: &mut WindowCanvas, area: Rect, colors: &OverlayColors) {
+        let lines: Vec<String> = self.items.iter().enumerate()
+            .map(|(i, item)| format!("{} {}", if i == self.selected { '>' } else { ' ' }, item.label))
+            .collect();
+        osd::draw_panel(canvas, area, &self.title, &lines, Some(self.selected), colors);
+    }
+}
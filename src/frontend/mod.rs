@@ -0,0 +1,69 @@
+//! SDL2 frontend building blocks shared by the windowed binary.
+
+use std::str::FromStr;
+
+use sdl2::rect::Rect;
+
+use crate::emulators::ScreenResolution;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScaleMode {
+    // Stretch to fill the whole area, nearest-neighbour sampled
+    Nearest, 
+    // Largest whole multiple of the native resolution that fits, letterboxed
+    IntegerScale, 
+    // Largest size that fits while keeping the aspect ratio, letterboxed
+    AspectFit, 
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" | "stretch" => Ok(ScaleMode::Nearest), 
+            "integer" => Ok(ScaleMode::IntegerScale), 
+            "aspect" | "fit" => Ok(ScaleMode::AspectFit), 
+            _ => Err(format!("Unknown scale mode '{}', expected nearest, integer or aspect", s)), 
+        }
+    }
+}
+
+/**
+ * Where a `res` sized screen goes inside `area` for the given mode, centred.
+ */
+pub fn fit_rect(res: &ScreenResolution, area: Rect, mode: ScaleMode) -> Rect {
+    if res.width == 0 || res.height == 0 {
+        return area;
+    }
+    let (w, h) = match mode {
+        ScaleMode::Nearest => (area.width(), area.height()), 
+        ScaleMode::IntegerScale => {
+            let scale = (area.width() / res.width).min(area.height() / res.height).max(1);
+            (res.width * scale, res.height * scale)
+        }, 
+        ScaleMode::AspectFit => {
+            let scale = (area.width() as f32 / res.width as f32)
+                .min(area.height() as f32 / res.height as f32);
+            (((res.width as f32 * scale) as u32).max(1), ((res.height as f32 * scale) as u32).max(1))
+        }, 
+    };
+    Rect::new(area.x() + (area.width() as i32 - w as i32) / 2, 
+        area.y() + (area.height() as i32 - h as i32) / 2, w, h)
+}
+
+pub mod actions;
+pub mod audio;
+pub mod controls;
+pub mod disassembly;
+pub mod fullscreen;
+pub mod gamepad;
+pub mod keypad;
+pub mod layout;
+pub mod memory_view;
+pub mod menu;
+pub mod osd;
+pub mod pacing;
+pub mod rom_browser;
+pub mod texture_renderer;
+pub mod gl_renderer;
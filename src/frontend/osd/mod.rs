@@ -0,0 +1,113 @@
+use std::{ collections::VecDeque, time::{ Duration, Instant } };
+
+use sdl2::{ pixels::Color, rect::Rect, render::WindowCanvas };
+
+use crate::p_bitmap_font::{ self, CHAR_HEIGHT, CHAR_WIDTH };
+
+// How long a message stays up
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+// Older messages are dropped past this many
+const MAX_MESSAGES: usize = 4;
+// Pixels between glyphs, between lines and around the text
+const CHAR_SPACING: i32 = 2;
+const LINE_SPACING: i32 = 2;
+const PADDING: i32 = 4;
+
+pub struct OverlayColors {
+    pub background: Color,
+    pub text: Color,
+    pub selected: Color,
+}
+
+/**
+ * Short lived messages in the bottom left corner of the screen.
+ */
+pub struct Osd {
+    // Oldest first, with the time each one goes away
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd { messages: VecDeque::new() }
+    }
+
+    /**
+     * Shows `text` for a couple of seconds, and logs it. Repeating the newest
+     * message only keeps it up longer.
+     */
+    pub fn show(self: &mut Self, text: String) {
+        let until = Instant::now() + MESSAGE_TIME;
+        if let Some((last, last_until)) = self.messages.back_mut() {
+            if *last == text {
+                *last_until = until;
+                return;
+            }
+        }
+        crate::log_info!("[OSD] {}", text);
+        self.messages.push_back((text, until));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, area: Rect, colors: &OverlayColors) {
+        let now = Instant::now();
+        self.messages.retain(|(_, until)| *until > now);
+
+        let line_height = CHAR_HEIGHT + LINE_SPACING;
+        let mut y = area.bottom() - PADDING - line_height * self.messages.len() as i32;
+        for (text, _) in &self.messages {
+            let width = text.chars().count() as i32 * (CHAR_WIDTH + CHAR_SPACING);
+            canvas.set_draw_color(colors.background);
+            let _ = canvas.fill_rect(Rect::new(area.x(), y - LINE_SPACING / 2, (width + 2 * PADDING) as u32, line_height as u32));
+            p_bitmap_font::draw_text(canvas, area.x() + PADDING, y, text, CHAR_SPACING, &colors.text, &colors.background);
+            y += line_height;
+        }
+    }
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Osd::new()
+    }
+}
+
+/**
+ * How many lines `draw_panel` fits in `area` below the title.
+ */
+pub fn panel_rows(area: Rect) -> usize {
+    let rows = (area.height() as i32 - 2 * PADDING) / (CHAR_HEIGHT + LINE_SPACING) - 2;
+    rows.max(1) as usize
+}
+
+/**
+ * A box in the middle of `area` with a title and lines of text, `selected`
+ * drawn in the highlight colour. Used by the menus and the help overlay.
+ */
+pub fn draw_panel(canvas: &mut WindowCanvas, area: Rect, title: &str, lines: &[String], selected: Option<usize>, colors: &OverlayColors) {
+    let line_height = CHAR_HEIGHT + LINE_SPACING;
+    let char_width = CHAR_WIDTH + CHAR_SPACING;
+    let columns = lines.iter().map(|l| l.chars().count()).chain([title.chars().count()]).max().unwrap_or(0) as i32;
+    // Title, a blank line, then the lines
+    let rows = lines.len() as i32 + 2;
+    let width = (columns * char_width + 2 * PADDING).min(area.width() as i32);
+    let height = (rows * line_height + 2 * PADDING).min(area.height() as i32);
+    let panel = Rect::new(area.x() + (area.width() as i32 - width) / 2,
+        area.y() + (area.height() as i32 - height) / 2, width.max(1) as u32, height.max(1) as u32);
+
+    canvas.set_draw_color(colors.background);
+    let _ = canvas.fill_rect(panel);
+    canvas.set_draw_color(colors.text);
+    let _ = canvas.draw_rect(panel);
+    canvas.set_clip_rect(panel);
+
+    let x = panel.x() + PADDING;
+    let y = panel.y() + PADDING;
+    p_bitmap_font::draw_text(canvas, x, y, title, CHAR_SPACING, &colors.selected, &colors.background);
+    for (i, line) in lines.iter().enumerate() {
+        let color = if selected == Some(i) { &colors.selected } else { &colors.text };
+        p_bitmap_font::draw_text(canvas, x, y + (i as i32 + 2) * line_height, line, CHAR_SPACING, color, &colors.background);
+    }
+    canvas.set_clip_rect(None);
+}
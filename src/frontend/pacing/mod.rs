@@ -0,0 +1,104 @@
+use std::{ thread, time::{ Duration, Instant } };
+
+// Emulated frames per second, independent of the display refresh rate
+pub const FRAME_RATE: u32 = 60;
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+// Speed while the fast-forward key is held
+pub const FAST_FORWARD_SPEED: f64 = 8.0;
+// Upper bound on emulated frames per displayed frame, past that time is dropped
+const MAX_FRAMES_PER_UPDATE: u32 = 16;
+
+/**
+ * Keeps emulated time running at `FRAME_RATE` times the speed factor.
+ *
+ * Call `frames_due` once per loop to get how many emulated frames to run,
+ * draw only the last one (the rest are skipped) and then `wait_for_next_frame`.
+ */
+pub struct FramePacer {
+    speed_index: usize,
+    pub fast_forward: bool,
+    // With vsync the present call blocks, so there's no sleeping here
+    pub vsync: bool,
+    last_update: Instant,
+    next_frame: Instant,
+    // Emulated seconds not yet run, can dip below zero by half a frame
+    owed: f64,
+    pub skipped_frames: u64,
+}
+
+impl FramePacer {
+    pub fn new(vsync: bool) -> FramePacer {
+        let now = Instant::now();
+        FramePacer {
+            speed_index: SPEEDS.iter().position(|s| *s == 1.0).unwrap(),
+            fast_forward: false,
+            vsync,
+            last_update: now,
+            next_frame: now,
+            owed: 0.0,
+            skipped_frames: 0,
+        }
+    }
+
+    pub fn frame_time() -> Duration {
+        Duration::from_secs(1) / FRAME_RATE
+    }
+
+    pub fn speed(self: &Self) -> f64 {
+        if self.fast_forward {
+            FAST_FORWARD_SPEED.max(SPEEDS[self.speed_index])
+        } else {
+            SPEEDS[self.speed_index]
+        }
+    }
+
+    pub fn speed_up(self: &mut Self) -> f64 {
+        self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+        SPEEDS[self.speed_index]
+    }
+
+    pub fn slow_down(self: &mut Self) -> f64 {
+        self.speed_index = self.speed_index.saturating_sub(1);
+        SPEEDS[self.speed_index]
+    }
+
+    /**
+     * Emulated frames to run since the last call. More than one means the
+     * display fell behind and the frames in between are not drawn.
+     */
+    pub fn frames_due(self: &mut Self) -> u32 {
+        let now = Instant::now();
+        self.owed += (now - self.last_update).as_secs_f64() * self.speed();
+        self.last_update = now;
+
+        // Rounded to the nearest frame so timer jitter doesn't alternate 0 and 2
+        let frame_time = FramePacer::frame_time().as_secs_f64();
+        let mut frames = 0;
+        while self.owed >= frame_time / 2.0 && frames < MAX_FRAMES_PER_UPDATE {
+            self.owed -= frame_time;
+            frames += 1;
+        }
+        if frames == MAX_FRAMES_PER_UPDATE {
+            // Too far behind (e.g. the window was being dragged), don't try to catch up
+            self.owed = 0.0;
+        }
+        self.skipped_frames += frames.saturating_sub(1) as u64;
+        frames
+    }
+
+    /**
+     * Sleeps until the next display frame is due, unless vsync does it.
+     */
+    pub fn wait_for_next_frame(self: &mut Self) {
+        if self.vsync {
+            return;
+        }
+        let now = Instant::now();
+        self.next_frame += FramePacer::frame_time();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{ fs, path::{ Path, PathBuf } };
+
+use sdl2::{ keyboard::Keycode, pixels::Color, rect::Rect, render::WindowCanvas };
+
+use crate::emulators;
+use crate::p_bitmap_font::{ self, CHAR_HEIGHT, CHAR_WIDTH };
+use super::layout::PANE_MARGIN;
+use super::menu::MenuInput;
+use super::osd::OverlayColors;
+
+// Pixels between glyphs and between lines
+const CHAR_SPACING: i32 = 2;
+const LINE_SPACING: i32 = 2;
+// Lines above the list: the title and a blank one
+const HEADER_LINES: usize = 2;
+
+pub struct RomEntry {
+    pub path: PathBuf,
+    // File name without the extension
+    pub title: String,
+    // `SystemInfo::name` for the extension, None if no core claims it
+    pub system: Option<&'static str>,
+    pub recent: bool,
+}
+
+impl RomEntry {
+    fn new(path: PathBuf, recent: bool) -> RomEntry {
+        let title = path.file_stem()
+            .map(|s| s.to_string_lossy().replace('_', " "))
+            .unwrap_or_default();
+        let system = emulators::find_system_for_file(&path.to_string_lossy()).map(|s| s.name);
+        RomEntry { path, title, system, recent }
+    }
+}
+
+pub enum BrowserAction {
+    None,
+    Load(PathBuf),
+    // Back to the running ROM
+    Close,
+}
+
+/**
+ * Full screen list of the recently played files followed by the ROMs in the
+ * ROM directory, picked with the arrow keys and Enter.
+ */
+pub struct RomBrowser {
+    rom_dir: PathBuf,
+    entries: Vec<RomEntry>,
+    selected: usize,
+    // First entry on screen
+    scroll: usize,
+    // Entries that fit, from the last draw
+    visible_rows: usize,
+}
+
+impl RomBrowser {
+    /**
+     * Lists `recent` (missing files are left out) and the files in `rom_dir`
+     * with an extension one of the cores claims, sorted by name.
+     */
+    pub fn new(rom_dir: &Path, recent: &[String]) -> RomBrowser {
+        let mut entries: Vec<RomEntry> = recent.iter()
+            .map(PathBuf::from)
+            .filter(|path| path.is_file())
+            .map(|path| RomEntry::new(path, true))
+            .collect();
+        let recent_paths: Vec<PathBuf> = entries.iter().map(|e| e.path.clone()).collect();
+
+        let mut dir_entries: Vec<RomEntry> = fs::read_dir(rom_dir).into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| !recent_paths.contains(&fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+            .map(|path| RomEntry::new(path, false))
+            .filter(|entry| entry.system.is_some())
+            .collect();
+        dir_entries.sort_by_key(|entry| entry.title.to_lowercase());
+        entries.extend(dir_entries);
+
+        RomBrowser {
+            rom_dir: rom_dir.to_path_buf(),
+            entries,
+            selected: 0,
+            scroll: 0,
+            visible_rows: 1,
+        }
+    }
+
+    pub fn entries(self: &Self) -> &[RomEntry] {
+        &self.entries
+    }
+
+    pub fn on_key(self: &mut Self, keycode: Keycode) -> BrowserAction {
+        let last = self.entries.len().saturating_sub(1);
+        match keycode {
+            Keycode::PageUp => self.selected = self.selected.saturating_sub(self.visible_rows),
+            Keycode::PageDown => self.selected = (self.selected + self.visible_rows).min(last),
+            Keycode::Home => self.selected = 0,
+            Keycode::End => self.selected = last,
+            _ => return MenuInput::from_keycode(keycode)
+                .map(|input| self.on_input(input))
+                .unwrap_or(BrowserAction::None),
+        }
+        BrowserAction::None
+    }
+
+    /**
+     * Up and Down move the selection, Left and Right a page.
+     */
+    pub fn on_input(self: &mut Self, input: MenuInput) -> BrowserAction {
+        let last = self.entries.len().saturating_sub(1);
+        match input {
+            MenuInput::Up => self.selected = self.selected.saturating_sub(1),
+            MenuInput::Down => self.selected = (self.selected + 1).min(last),
+            MenuInput::Left => self.selected = self.selected.saturating_sub(self.visible_rows),
+            MenuInput::Right => self.selected = (self.selected + self.visible_rows).min(last),
+            MenuInput::Select => {
+                if let Some(entry) = self.entries.get(self.selected) {
+                    return BrowserAction::Load(entry.path.clone());
+                }
+            },
+            MenuInput::Back => return BrowserAction::Close,
+        }
+        BrowserAction::None
+    }
+
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, area: Rect, colors: &OverlayColors) {
+        canvas.set_draw_color(colors.background);
+        let _ = canvas.fill_rect(area);
+        canvas.set_clip_rect(area);
+
+        let line_height = CHAR_HEIGHT + LINE_SPACING;
+        let x = area.x() + PANE_MARGIN as i32;
+        let y = area.y() + PANE_MARGIN as i32;
+        let columns = ((area.width() as i32 - 2 * PANE_MARGIN as i32) / (CHAR_WIDTH + CHAR_SPACING)).max(1) as usize;
+        let rows = ((area.height() as i32 - 2 * PANE_MARGIN as i32) / line_height).max(0) as usize;
+        self.visible_rows = rows.saturating_sub(HEADER_LINES).max(1);
+
+        // Keep the selection on screen
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + self.visible_rows {
+            self.scroll = self.selected + 1 - self.visible_rows;
+        }
+
+        let title = if self.entries.is_empty() {
+            format!("No ROMs in {}, drop a file on the window", self.rom_dir.display())
+        } else {
+            String::from("Load ROM: Enter loads, Esc goes back, * recent")
+        };
+        draw_line(canvas, x, y, &title, columns, &colors.text, &colors.background);
+
+        let visible = self.entries.iter().enumerate().skip(self.scroll).take(self.visible_rows);
+        for (row, (i, entry)) in visible.enumerate() {
+            let cursor = if i == self.selected { '>' } else { ' ' };
+            let recent = if entry.recent { '*' } else { ' ' };
+            let text = format!("{}{}{} [{}]", cursor, recent, entry.title, entry.system.unwrap_or("?"));
+            let color = if i == self.selected { &colors.selected } else { &colors.text };
+            let line_y = y + (row + HEADER_LINES) as i32 * line_height;
+            draw_line(canvas, x, line_y, &text, columns, color, &colors.background);
+        }
+        canvas.set_clip_rect(None);
+    }
+}
+
+/**
+ * `text` cut to `columns` characters.
+ */
+fn draw_line(canvas: &mut WindowCanvas, x: i32, y: i32, text: &str, columns: usize, fore_color: &Color, back_color: &Color) {
+    let text: String = text.chars().take(columns).collect();
+    p_bitmap_font::draw_text(canvas, x, y, &text, CHAR_SPACING, fore_color, back_color);
+}
@@ -0,0 +1,84 @@
+use sdl2::{ 
+    pixels::PixelFormatEnum, 
+    rect::Rect, 
+    render::{ BlendMode, Texture, TextureCreator, WindowCanvas }, 
+    video::WindowContext, 
+};
+
+use crate::emulators::{ Emulator, ScreenResolution };
+use crate::filters::persistence::{ PersistenceFilter, PersistenceMode };
+use crate::filters::upscale::Upscaler;
+use super::{ fit_rect, ScaleMode };
+
+/**
+ * Uploads the emulator screen at its native resolution (or run through
+ * `upscaler`) into a streaming texture and lets the SDL renderer do the
+ * rest of the scaling.
+ */
+pub struct TextureRenderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>, 
+    texture: Option<Texture<'a>>, 
+    texture_res: ScreenResolution, 
+    pixel_buf: Vec<u8>, 
+    upscaled_buf: Vec<u8>, 
+    pub scale_mode: ScaleMode, 
+    pub persistence: PersistenceFilter, 
+    pub upscaler: Upscaler, 
+}
+
+impl<'a> TextureRenderer<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>, scale_mode: ScaleMode) -> TextureRenderer<'a> {
+        // Nearest-neighbour sampling, has to be set before the texture is created
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+        TextureRenderer {
+            texture_creator, 
+            texture: None, 
+            texture_res: ScreenResolution { width: 0, height: 0 }, 
+            pixel_buf: Vec::new(), 
+            upscaled_buf: Vec::new(), 
+            scale_mode, 
+            persistence: PersistenceFilter::new(PersistenceMode::Off), 
+            upscaler: Upscaler::None, 
+        }
+    }
+
+    /**
+     * Draws the emulator screen into `area` and returns the rect it ended up in.
+     */
+    pub fn draw(self: &mut Self, canvas: &mut WindowCanvas, emu: &dyn Emulator, area: Rect) 
+        -> Result<Rect, String> {
+
+        let res = emu.get_screen_resolution();
+        if self.pixel_buf.len() != (res.width * res.height * 4) as usize {
+            self.pixel_buf = [0, 0, 0, 0xff].repeat((res.width * res.height) as usize);
+        }
+        if emu.draw_to_buffer_rgba(&mut self.pixel_buf, &res).is_err() {
+            return Err(String::from("Emulator could not draw its screen"));
+        }
+        self.persistence.apply(&mut self.pixel_buf);
+
+        let (pixels, tex_res) = if self.upscaler == Upscaler::None {
+            (&self.pixel_buf, ScreenResolution { width: res.width, height: res.height })
+        } else {
+            let up_res = self.upscaler.upscale(&self.pixel_buf, &res, &mut self.upscaled_buf);
+            (&self.upscaled_buf, up_res)
+        };
+        if self.texture.is_none() || tex_res.width != self.texture_res.width || tex_res.height != self.texture_res.height {
+            let mut texture = self.texture_creator
+                // byte order R, G, B, A on little endian
+                .create_texture_streaming(PixelFormatEnum::ABGR8888, tex_res.width, tex_res.height)
+                .map_err(|e| e.to_string())?;
+            texture.set_blend_mode(BlendMode::None);
+            self.texture = Some(texture);
+            self.texture_res = ScreenResolution { width: tex_res.width, height: tex_res.height };
+        }
+
+        // Placed by the native resolution so switching filters keeps the layout
+        let dst = fit_rect(&res, area, self.scale_mode);
+        let texture = self.texture.as_mut().unwrap();
+        texture.update(None, pixels, (tex_res.width * 4) as usize)
+            .map_err(|e| e.to_string())?;
+        canvas.copy(texture, None, dst)?;
+        Ok(dst)
+    }
+}
@@ -0,0 +1,377 @@
+//! Runs a core without a window, for CI and scripted checks.
+//!
+//! Nothing in here touches SDL, so it works with `default-features = false`.
+
+use std::fs::{ self, File };
+use std::io::{ self, BufWriter, Write };
+
+use crate::config::{ Config, SystemConfig };
+use crate::emulators::{ self, palette, Emulator, RegisterSize, ScreenResolution };
+use crate::filters::persistence::{ PersistenceFilter, PersistenceMode };
+use crate::filters::upscale::{ UpscaleChain, Upscaler };
+
+pub const EXIT_OK: i32 = 0;
+// The emulated program exited with a non-zero code
+pub const EXIT_PROGRAM_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+// `until` was given but never became true within the frame limit
+pub const EXIT_TIMEOUT: i32 = 4;
+
+pub enum StopCondition {
+    // Stop once the program counter reaches this address
+    Pc(u64),
+    // Stop once the program reports an exit code
+    Exit,
+}
+
+pub struct HeadlessOptions {
+    pub rom_path: String,
+    pub system: Option<String>,
+    // The clock, quirks and palette of the system come from here. The default
+    // config file isn't read, so runs don't depend on the user's settings
+    pub config_path: Option<String>,
+    pub frames: u32,
+    // None uses `SystemInfo::ticks_per_frame`
+    pub ticks_per_frame: Option<u32>,
+    pub seed: Option<u64>,
+    pub input_script: Option<String>,
+    pub until: Option<StopCondition>,
+    pub screenshot_path: Option<String>,
+    pub screenshot_scale: u32,
+    pub persistence: PersistenceMode,
+    // Applied before the nearest-neighbour scaling to `screenshot_scale`
+    pub upscaler: Upscaler,
+    // Empty keeps the core's colours
+    pub palette: Vec<[u8; 4]>,
+    // "-" writes to stdout
+    pub state_path: Option<String>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        HeadlessOptions {
+            rom_path: String::new(),
+            system: None,
+            config_path: None,
+            frames: 60,
+            ticks_per_frame: None,
+            seed: None,
+            input_script: None,
+            until: None,
+            screenshot_path: None,
+            screenshot_scale: 1,
+            persistence: PersistenceMode::Off,
+            upscaler: Upscaler::None,
+            palette: Vec::new(),
+            state_path: None,
+        }
+    }
+}
+
+pub struct HeadlessResult {
+    pub frames_run: u32,
+    pub condition_met: bool,
+    pub exit_code: Option<u32>,
+}
+
+impl HeadlessResult {
+    pub fn status_code(self: &Self) -> i32 {
+        if !self.condition_met {
+            return EXIT_TIMEOUT;
+        }
+        match self.exit_code {
+            Some(0) | None => EXIT_OK,
+            Some(_) => EXIT_PROGRAM_FAILED,
+        }
+    }
+}
+
+pub enum HeadlessError {
+    Usage(String),
+    Io(String),
+}
+
+impl HeadlessError {
+    pub fn status_code(self: &Self) -> i32 {
+        match self {
+            HeadlessError::Usage(_) => EXIT_USAGE,
+            HeadlessError::Io(_) => EXIT_IO,
+        }
+    }
+
+    pub fn message(self: &Self) -> &str {
+        match self {
+            HeadlessError::Usage(msg) | HeadlessError::Io(msg) => msg,
+        }
+    }
+}
+
+pub struct InputEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/**
+ * One event per line: `<frame> <press|release> <key>`, the key in hex.
+ * Blank lines and anything after `#` are ignored.
+ *
+ * ```text
+ * # hold 5 for half a second
+ * 120 press 5
+ * 150 release 5
+ * ```
+ */
+pub fn parse_input_script(text: &str) -> Result<Vec<InputEvent>, String> {
+    let mut events = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let err = || format!("Line {}: expected '<frame> <press|release> <key>', got '{}'", line_no + 1, line);
+        if parts.len() != 3 {
+            return Err(err());
+        }
+        let frame = parts[0].parse::<u32>().map_err(|_| err())?;
+        let pressed = match parts[1] {
+            "press" | "down" => true,
+            "release" | "up" => false,
+            _ => return Err(err()),
+        };
+        let key = u8::from_str_radix(parts[2].trim_start_matches("0x"), 16).map_err(|_| err())?;
+        events.push(InputEvent { frame, key, pressed });
+    }
+    events.sort_by_key(|e| e.frame);
+    Ok(events)
+}
+
+pub fn run(options: &HeadlessOptions) -> Result<HeadlessResult, HeadlessError> {
+    let system = match &options.system {
+        Some(name) => emulators::find_system_by_name(name)
+            .ok_or(HeadlessError::Usage(format!("Unknown system '{}'", name)))?,
+        None => emulators::find_system_for_file(&options.rom_path)
+            .unwrap_or(&emulators::SYSTEMS[0]),
+    };
+    let settings = match &options.config_path {
+        Some(path) => Config::load(Some(path)).map_err(HeadlessError::Usage)?.system(system),
+        None => SystemConfig::default(),
+    };
+    // Flags win over the config file
+    let palette = if options.palette.is_empty() {
+        palette::parse_palette(&settings.palette).map_err(HeadlessError::Usage)?
+    } else {
+        options.palette.clone()
+    };
+    let events = match &options.input_script {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| HeadlessError::Io(format!("Could not read input script {}: {}", path, e)))?;
+            parse_input_script(&text).map_err(HeadlessError::Usage)?
+        },
+        None => Vec::new(),
+    };
+    let rom = fs::read(&options.rom_path)
+        .map_err(|e| HeadlessError::Io(format!("Could not read {}: {}", options.rom_path, e)))?;
+
+    let mut emu = (system.create)();
+    emu.load_data(&rom)
+        .map_err(|e| HeadlessError::Io(format!("Could not load {}: {}", options.rom_path, e)))?;
+    if let Some(seed) = options.seed {
+        emu.set_seed(seed);
+    }
+    if let Some(preset) = &settings.quirks {
+        emu.set_quirks(preset).map_err(HeadlessError::Usage)?;
+    }
+    if !palette.is_empty() {
+        emu.set_palette(&palette);
+    }
+    emu.start();
+    let ticks_per_frame = options.ticks_per_frame.or(settings.ticks_per_frame).unwrap_or(system.ticks_per_frame);
+
+    let mut result = HeadlessResult {
+        frames_run: 0,
+        condition_met: options.until.is_none(),
+        exit_code: None,
+    };
+    let res = emu.get_screen_resolution();
+    // Opaque black, for cores that leave alpha alone
+    let mut buf = [0, 0, 0, 0xff].repeat((res.width * res.height) as usize);
+    let mut persistence = PersistenceFilter::new(options.persistence);
+    if let Some([r, g, b, _]) = palette.first() {
+        persistence.background = [*r, *g, *b];
+    }
+    // Persistence needs to see every frame, otherwise only the last one is drawn
+    let draw_every_frame = options.screenshot_path.is_some() && options.persistence != PersistenceMode::Off;
+
+    let mut next_event = 0;
+    'frames: while result.frames_run < options.frames {
+        while next_event < events.len() && events[next_event].frame <= result.frames_run {
+            let event = &events[next_event];
+            if event.pressed {
+                emu.on_key_press(event.key);
+            } else {
+                emu.on_key_release(event.key);
+            }
+            next_event += 1;
+        }
+        for _ in 0..ticks_per_frame {
+            emu.tick();
+            result.exit_code = emu.get_exit_code();
+            let condition_met = match options.until {
+                Some(StopCondition::Pc(pc)) => emu.get_program_counter() == pc,
+                Some(StopCondition::Exit) => result.exit_code.is_some(),
+                None => false,
+            };
+            if condition_met || result.exit_code.is_some() {
+                result.condition_met |= condition_met;
+                result.frames_run += 1;
+                break 'frames;
+            }
+        }
+        emu.on_frame();
+        if draw_every_frame {
+            let _ = emu.draw_to_buffer_rgba(&mut buf, &res);
+            persistence.apply(&mut buf);
+        }
+        result.frames_run += 1;
+    }
+
+    if let Some(path) = &options.screenshot_path {
+        let _ = emu.draw_to_buffer_rgba(&mut buf, &res);
+        persistence.apply(&mut buf);
+        let out_res = ScreenResolution {
+            width: res.width * options.screenshot_scale.max(1),
+            height: res.height * options.screenshot_scale.max(1),
+        };
+        let mut out = vec![0; (out_res.width * out_res.height * 4) as usize];
+        UpscaleChain::new(options.upscaler).apply(&buf, &res, &mut out, &out_res);
+        write_png(path, &out, &out_res)
+            .map_err(|e| HeadlessError::Io(format!("Could not write {}: {}", path, e)))?;
+    }
+    if let Some(path) = &options.state_path {
+        let json = state_to_json(emu.as_ref(), system.name, &result);
+        let written = if path == "-" {
+            io::stdout().write_all(json.as_bytes())
+        } else {
+            fs::write(path, json)
+        };
+        written.map_err(|e| HeadlessError::Io(format!("Could not write {}: {}", path, e)))?;
+    }
+
+    Ok(result)
+}
+
+pub fn write_png(path: &str, rgba: &[u8], res: &ScreenResolution) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), res.width, res.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgba).map_err(|e| e.to_string())
+}
+
+pub fn state_to_json(emu: &dyn Emulator, system_name: &str, result: &HeadlessResult) -> String {
+    let registers: Vec<String> = emu.get_data_registers().iter().enumerate()
+        .map(|(i, r)| {
+            let bits = match r.reg_size_bits {
+                RegisterSize::RegSize8 => 8,
+                RegisterSize::RegSize16 => 16,
+                RegisterSize::RegSize32 => 32,
+                RegisterSize::RegSize64 => 64,
+            };
+            format!("    {{ \"index\": {}, \"bits\": {}, \"value\": {} }}", i, bits, r.reg_value)
+        })
+        .collect();
+    let exit_code = match result.exit_code {
+        Some(code) => code.to_string(),
+        None => String::from("null"),
+    };
+
+    format!("{{\n  \"system\": \"{}\",\n  \"frames\": {},\n  \"condition_met\": {},\n  \"exit_code\": {},\n  \
+        \"pc\": {},\n  \"current_instr\": \"{}\",\n  \"next_instr\": \"{}\",\n  \"registers\": [\n{}\n  ]\n}}\n",
+        json_escape(system_name), result.frames_run, result.condition_met, exit_code,
+        emu.get_program_counter(), json_escape(&emu.get_current_instr()), json_escape(&emu.get_next_instr()),
+        registers.join(",\n"))
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8Emu;
+
+    fn result(condition_met: bool, exit_code: Option<u32>) -> HeadlessResult {
+        HeadlessResult { frames_run: 3, condition_met, exit_code }
+    }
+
+    #[test]
+    fn input_script() {
+        let script = "# hold 5\n\n120 press 5\n  150 release 0x5  # let go\n10 down a\n11 up F\n";
+        let events = parse_input_script(script).unwrap();
+        let events: Vec<(u32, u8, bool)> = events.iter().map(|e| (e.frame, e.key, e.pressed)).collect();
+        // Sorted by frame
+        assert_eq!(events, vec![(10, 0xa, true), (11, 0xf, false), (120, 5, true), (150, 5, false)]);
+        assert!(parse_input_script("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn input_script_errors() {
+        let err = parse_input_script("1 press 1\n2 hold 1").err().unwrap();
+        assert!(err.starts_with("Line 2:"), "{}", err);
+        assert!(parse_input_script("x press 1").is_err());
+        assert!(parse_input_script("1 press").is_err());
+        assert!(parse_input_script("1 press 1 2").is_err());
+        assert!(parse_input_script("1 press 100").is_err());
+        assert!(parse_input_script("-1 press 1").is_err());
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(result(true, None).status_code(), EXIT_OK);
+        assert_eq!(result(true, Some(0)).status_code(), EXIT_OK);
+        assert_eq!(result(true, Some(3)).status_code(), EXIT_PROGRAM_FAILED);
+        assert_eq!(result(false, None).status_code(), EXIT_TIMEOUT);
+        // Exiting before --until-pc was reached doesn't count as a pass
+        assert_eq!(result(false, Some(0)).status_code(), EXIT_TIMEOUT);
+        assert_eq!(result(false, Some(3)).status_code(), EXIT_TIMEOUT);
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(json_escape("plain"), "plain");
+        assert_eq!(json_escape("a \"b\" \\c"), "a \\\"b\\\" \\\\c");
+        assert_eq!(json_escape("x\ny\t"), "x\\u000ay\\u0009");
+    }
+
+    #[test]
+    fn state_json() {
+        // SET V3 0x2A
+        let emu = Chip8Emu::builder().rom_data(&[0x63, 0x2a]).start_running(true).build();
+        let json = state_to_json(&emu, "chip\"8", &result(false, Some(1)));
+        assert!(json.contains("\"system\": \"chip\\\"8\","), "{}", json);
+        assert!(json.contains("\"frames\": 3,"));
+        assert!(json.contains("\"condition_met\": false,"));
+        assert!(json.contains("\"exit_code\": 1,"));
+        assert!(json.contains("\"pc\": 512,"));
+        assert!(json.contains("{ \"index\": 0, \"bits\": 8, \"value\": 0 },"));
+        assert!(json.ends_with("]\n}\n"));
+
+        let json = state_to_json(&emu, "chip8", &result(true, None));
+        assert!(json.contains("\"exit_code\": null,"));
+    }
+}
@@ -1 +1,31 @@
-pub mod emulators;
\ No newline at end of file
+//! Emulator cores and the pieces needed to drive them from a frontend.
+//!
+//! Every core implements [`EmuTrait`] (loading, ticking, drawing) and [`CpuInfo`]
+//! (register and instruction views), which together make up [`Emulator`].
+//! Cores are found by name or file extension through [`emulators::SYSTEMS`].
+//! User settings are read through [`config::Config`], and [`cli`] parses the
+//! command lines of both binaries. [`debugger`] adds breakpoints and run control
+//! on top of any core.
+//!
+//! The `sdl` feature (on by default) adds the SDL2 specific helpers such as
+//! [`p_bitmap_font`] and the [`frontend`] renderers. Build with `default-features = false` to use the cores
+//! without SDL2 installed.
+
+pub mod cli;
+pub mod config;
+pub mod debugger;
+pub mod emulators;
+pub mod filters;
+pub mod headless;
+pub mod logging;
+#[cfg(feature = "sdl")]
+pub mod p_bitmap_font;
+#[cfg(feature = "sdl")]
+pub mod frontend;
+
+pub use emulators::{
+    EmuTrait, CpuInfo, KeyboardDriver, Emulator,
+    ScreenResolution, RegisterInfo, RegisterSize, MemoryAccess, MemoryMark, SystemInfo,
+};
+pub use emulators::chip8_emu::{ Chip8Emu, Chip8EmuBuilder, quirks::Chip8Quirks };
+pub use emulators::riscv::RiscvEmu;
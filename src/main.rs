@@ -3,154 +3,863 @@ extern crate sdl2;
 
 use sdl2:: { 
     Sdl, VideoSubsystem, 
-    rect::{ Point }, 
+    rect::Rect, 
     pixels::Color, 
-    render::WindowCanvas, 
-    event::Event, 
-    keyboard::Keycode, 
+    render::{ TextureCreator, WindowCanvas }, 
+    video::{ GLContext, GLProfile, SwapInterval, Window, WindowContext }, 
+    event::{ Event, WindowEvent }, 
+    keyboard::{ self, Keycode }, 
+    mouse::MouseButton, 
+    controller::Button, 
 };
 
 //use std::thread;
-use std::{ time::{ SystemTime }, env, process};
+use std::{ collections::{ BTreeMap, HashMap }, env, fs, process, path::PathBuf };
 
-use ru_emu_lib::emulators::{ chip8_emu, EmuTrait, ScreenResolution, CpuInfo, RegisterSize, RegisterInfo };
-
-mod p_bitmap_font;
+use ru_emu_lib::{ log_debug, log_error, log_info, log_warn };
+use ru_emu_lib::cli::{ self, Cli, CliError, Command, RunOptions };
+use ru_emu_lib::config::{ 
+    self, Config, SystemConfig, 
+    bindings::{ Bindings, SystemBindings }, 
+    recent::RecentFiles, 
+    rom_settings::{ self, RomSettings, RomStore }, 
+    save_states, 
+};
+use ru_emu_lib::debugger::{ Breakpoint, Debugger, PauseReason, Watchpoint };
+use ru_emu_lib::emulators::{ self, palette, Emulator, RegisterSize, RegisterInfo, SystemInfo };
+use ru_emu_lib::frontend::{ 
+    fit_rect, ScaleMode, 
+    audio::Beeper, 
+    gamepad::{ self, Gamepads }, 
+    keypad::{ self, Keypad }, 
+    layout::{ self, Layout }, 
+    pacing::FramePacer, 
+    actions::{ Action, Hotkeys }, 
+    controls::{ ControlsAction, ControlsScreen }, 
+    disassembly::{ DisassemblyAction, DisassemblyView }, 
+    fullscreen::{ self, Fullscreen, FullscreenMode }, 
+    memory_view::{ MemoryColors, MemoryView, MemoryViewAction }, 
+    menu::{ Menu, MenuInput, MenuItem, MenuKind }, 
+    osd::{ self, Osd, OverlayColors }, 
+    rom_browser::{ BrowserAction, RomBrowser }, 
+    texture_renderer::TextureRenderer, 
+    gl_renderer::{ self, GlRenderer }, 
+};
+use ru_emu_lib::filters::{ persistence::PersistenceMode, upscale::Upscaler };
+use ru_emu_lib::headless;
+use ru_emu_lib::logging;
+use ru_emu_lib::p_bitmap_font;
 
 fn main() {
-    let sdl = sdl2::init().unwrap();
-    
-    let video_subsystem = sdl.video().unwrap();
+    let cli = match cli::parse_args(env::args().skip(1).collect()) {
+        Ok(cli) => cli, 
+        Err(err) => {
+            eprintln!("{}\n\n{}", err.message, err.usage);
+            process::exit(cli::EXIT_USAGE);
+        }, 
+    };
+    logging::set_level(cli.log_level);
+
+    let status = match &cli.command {
+        Command::Help(usage) => {
+            println!("{}", usage);
+            Ok(())
+        }, 
+        Command::Version => {
+            println!("ru_emu_lib {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }, 
+        Command::Headless(options) => process::exit(cli::run_headless(options)), 
+        Command::Disasm(options) => cli::select_system(cli.system.as_deref(), &options.rom_path)
+            .and_then(|system| cli::disassemble_rom(options, system))
+            .map(|text| print!("{}", text)), 
+        Command::Info(options) => cli::select_system(cli.system.as_deref(), &options.rom_path)
+            .and_then(|system| cli::rom_info(options, system, &load_config(&cli), &load_rom_store()))
+            .map(|text| print!("{}", text)), 
+        Command::ListDisplays => {
+            let sdl = sdl2::init().unwrap();
+            let video_subsystem = sdl.video().unwrap();
+            fullscreen::print_displays(&video_subsystem).map_err(|err| CliError::Io(format!("[SDL] {}", err)))
+        }, 
+        Command::Run(options) => {
+            let sdl = sdl2::init().unwrap();
+            let video_subsystem = sdl.video().unwrap();
+            try_sdl_canvas(&sdl, &video_subsystem, &cli, options)
+        }, 
+    };
+    if let Err(err) = status {
+        log_error!("{}", err.message());
+        process::exit(err.status_code());
+    }
+}
 
-    try_sdl_canvas(&sdl, &video_subsystem);
-    // print_sdl_debug_info(&sdl, &video_subsystem);
-    // try_gl_loop(&sdl, &video_subsystem);
+enum Screen<'a> {
+    Canvas(WindowCanvas, TextureRenderer<'a>), 
+    Gl(Window, GLContext, GlRenderer), 
 }
 
 fn _print_type_of<T>(_: &T) {
     println!("{}", std::any::type_name::<T>());
 }
 
-#[allow(unused_parens)]
-fn try_sdl_canvas(sdl: &Sdl, video_subsystem: &VideoSubsystem) {
+fn try_sdl_canvas(sdl: &Sdl, video_subsystem: &VideoSubsystem, cli: &Cli, options: &RunOptions) -> Result<(), CliError> {
 
-    let args: Vec<String> = env::args().collect();
-    let is_debug_mode = args.contains(&String::from("debug"));
-    let mut is_debug_paused = args.contains(&String::from("debug"));
-    println!("[Debug] {}", is_debug_mode);
+    let mut debugger = Debugger::new(options.paused);
+    log_debug!("[Debug] {}", options.paused);
 
-    let mut found_file_path = false;
-    let mut file_path: String = String::from("");
-    for arg in args {
-        if arg == "--f" {
-            found_file_path = true;
-            continue;
-        }
-        if found_file_path {
-            file_path = String::from(arg);
-            break;
-        }
+    // Command-line options override the config file
+    let mut config = load_config(cli);
+    let mut bindings = load_bindings();
+    bindings.apply_to(&mut config);
+    // Without a ROM an empty core of the asked for (or first) system sits behind the browser
+    let start_system = match &cli.system {
+        None if options.rom_path.is_empty() => &emulators::SYSTEMS[0], 
+        name => cli::select_system(name.as_deref(), &options.rom_path)?, 
+    };
+    // Its [systems.<name>.video] and [.audio], the flags below go on top
+    config.resolve_system(start_system);
+    if let Some(renderer) = &options.renderer {
+        config.video.renderer = renderer.clone();
     }
-    if !found_file_path {
-        println!("Please provide the file path using --f <file path>");
-        process::exit(1);
+    if let Some(shaders) = &options.shaders {
+        // Post-processing only exists in the GL renderer
+        config.video.renderer = String::from("gl");
+        config.video.shaders = shaders.clone();
     }
+    if let Some(shader_dir) = &options.shader_dir {
+        config.video.shader_dir = shader_dir.clone();
+    }
+    if let Some(persistence) = &options.persistence {
+        config.video.persistence = persistence.clone();
+    }
+    if let Some(filter) = &options.filter {
+        config.video.filter = filter.clone();
+    }
+    if let Some(scale_mode) = &options.scale_mode {
+        config.video.scale_mode = scale_mode.clone();
+    }
+    if let Some(rom_dir) = &options.rom_dir {
+        config.browser.rom_dir = rom_dir.clone();
+    }
+    config.video.vsync |= options.vsync;
+    config.video.show_keypad |= options.keypad;
+    config.video.fullscreen |= options.fullscreen;
+    if let Some(mode) = &options.fullscreen_mode {
+        config.video.fullscreen_mode = mode.clone();
+    }
+    if let Some(display) = options.display {
+        config.video.display = display;
+    }
+    if let Some(display_mode) = &options.display_mode {
+        config.video.display_mode = display_mode.clone();
+    }
+    config.audio.enabled &= !options.no_audio;
+    config.debugger.breakpoints.extend(options.breakpoints.iter().cloned());
+    config.debugger.watchpoints.extend(options.watchpoints.iter().cloned());
+    for breakpoint in &config.debugger.breakpoints {
+        debugger.add_breakpoint(breakpoint.parse::<Breakpoint>().map_err(CliError::Usage)?);
+    }
+    for watchpoint in &config.debugger.watchpoints {
+        debugger.add_watchpoint(watchpoint.parse::<Watchpoint>().map_err(CliError::Usage)?);
+    }
+    // Per-ROM flags, remembered for the ROM once given
+    let rom_flags = RomSettings {
+        ticks_per_frame: options.ticks_per_frame, 
+        quirks: options.quirks.clone(), 
+        palette: options.palette.clone(), 
+        ..RomSettings::default()
+    };
 
-    let window = video_subsystem
-        .window("RUST SDL OpenGL 00", 800, 600)
-        .opengl()
-        .build()
-        .unwrap();
-    println!("[Wnd]: {:?}", window.window_pixel_format());
+    let mut rom_store = load_rom_store();
+    let mut recent = match RecentFiles::load(RecentFiles::default_path(), config.browser.max_recent) {
+        Ok(recent) => recent, 
+        Err(err) => {
+            log_warn!("[Config] Ignoring the recent files: {}", err);
+            RecentFiles::load(None, config.browser.max_recent).unwrap()
+        }, 
+    };
+    let (mut system, mut emu, mut rom) = if options.rom_path.is_empty() {
+        (start_system, (start_system.create)(), LoadedRom::empty(config.system(start_system)))
+    } else {
+        let loaded = open_rom(&options.rom_path, Some(start_system.name), &config, &rom_store, &rom_flags)?;
+        add_recent(&mut recent, &options.rom_path);
+        loaded
+    };
+    if let Some(seed) = options.seed {
+        emu.set_seed(seed);
+    }
+    if options.print_config {
+        // The settings for this ROM go into the system's section
+        config.systems.insert(system.name.to_string(), rom.settings.clone());
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+    if options.rom_path.is_empty() && config.video.renderer == "gl" {
+        return Err(CliError::Usage(String::from("The ROM browser needs the canvas renderer, give a ROM file to use --renderer gl")));
+    }
+    if rom_flags.quirks.is_some() || rom_flags.ticks_per_frame.is_some() || !rom_flags.palette.is_empty() {
+        rom.remember(&mut rom_store, |saved| {
+            if rom_flags.quirks.is_some() {
+                saved.quirks = rom_flags.quirks.clone();
+            }
+            if rom_flags.ticks_per_frame.is_some() {
+                saved.ticks_per_frame = rom_flags.ticks_per_frame;
+            }
+            if !rom_flags.palette.is_empty() {
+                saved.palette = rom_flags.palette.clone();
+            }
+        });
+    }
 
-    let mut canvas = window
-        .into_canvas()
+    let use_gl = config.video.renderer == "gl";
+    let use_vsync = config.video.vsync;
+    let scale_mode: ScaleMode = config.video.scale_mode.parse().map_err(CliError::Usage)?;
+    let persistence: PersistenceMode = config.video.persistence.parse().map_err(CliError::Usage)?;
+    let upscaler: Upscaler = config.video.filter.parse().map_err(CliError::Usage)?;
+    let debug_colors = DebugColors {
+        window_background: parse_sdl_color(&config.debugger.window_background)?, 
+        pane_background: parse_sdl_color(&config.debugger.pane_background)?, 
+        register: parse_sdl_color(&config.debugger.register_color)?, 
+        current_instr: parse_sdl_color(&config.debugger.current_instr_color)?, 
+        next_instr: parse_sdl_color(&config.debugger.next_instr_color)?, 
+    };
+    let memory_colors = MemoryColors {
+        pc: parse_sdl_color(&config.debugger.memory_pc_color)?, 
+        pointer: parse_sdl_color(&config.debugger.memory_pointer_color)?, 
+        region: parse_sdl_color(&config.debugger.memory_region_color)?, 
+        written: parse_sdl_color(&config.debugger.memory_written_color)?, 
+    };
+    let overlay_colors = OverlayColors {
+        background: debug_colors.pane_background, 
+        text: debug_colors.register, 
+        selected: debug_colors.next_instr, 
+    };
+    let mut keymap = build_keymap(&rom.settings.keys);
+    let mut hotkeys = Hotkeys::new(&config.hotkeys);
+    let mut beeper = if config.audio.enabled {
+        Beeper::new(sdl, config.audio.tone_hz, config.audio.volume)
+            .map_err(|err| log_warn!("[Audio] Disabled: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    let mut gamepads = Gamepads::new(sdl)
+        .map_err(|err| log_warn!("[Pad] Disabled: {}", err))
+        .ok();
+    if let Some(pads) = &mut gamepads {
+        pads.set_mappings(&rom.settings.controllers);
+    }
+    // The GL renderer has no debugger pane
+    let mut show_debug_pane = !use_gl && config.debugger.show_pane;
+    // Same for the keypad, which also needs a core that has one
+    let mut show_keypad = !use_gl && config.video.show_keypad;
+    let mut keypad = new_keypad(system, show_keypad);
+
+    if use_gl {
+        let gl_attr = video_subsystem.gl_attr();
+        gl_attr.set_context_profile(GLProfile::Core);
+        gl_attr.set_context_version(3, 3);
+    }
+    let window_size = Layout::initial_window_size(emu.as_ref(), show_debug_pane, keypad.is_some(), options.scale);
+    let fullscreen_mode: FullscreenMode = config.video.fullscreen_mode.parse().map_err(CliError::Usage)?;
+    let fullscreen = Fullscreen::new(video_subsystem, config.video.display, fullscreen_mode, &config.video.display_mode)
+        .map_err(CliError::Usage)?;
+    let (window_x, window_y) = fullscreen.window_position(video_subsystem, window_size.width, window_size.height)
+        .map_err(|err| CliError::Usage(format!("[SDL] {}", err)))?;
+    let mut window_builder = video_subsystem.window("RUST SDL OpenGL 00", window_size.width, window_size.height);
+    window_builder.opengl().resizable().position(window_x, window_y);
+    let mut window = window_builder
         .build()
         .unwrap();
+    if config.video.fullscreen {
+        if let Err(err) = fullscreen.set(&mut window, true) {
+            log_warn!("[Wnd] Could not go fullscreen: {}", err);
+        }
+    }
+    log_debug!("[Wnd]: {:?}", window.window_pixel_format());
 
-    let mut c8emu = chip8_emu::Chip8Emu::new();
-    c8emu.load_data_file(&file_path);
-    let mut event_pump = sdl.event_pump().unwrap();
+    let texture_creator: TextureCreator<WindowContext>;
+    let mut screen = if use_gl {
+        let shader_dir = PathBuf::from(&config.video.shader_dir);
+        let shader_paths: Vec<PathBuf> = config.video.shaders.iter()
+            .map(|name| gl_renderer::resolve_shader_path(&shader_dir, name))
+            .collect();
+        create_gl_screen(video_subsystem, window, &shader_paths, use_vsync)
+            .map_err(|err| CliError::Usage(format!("[GL Error]: {}", err)))?
+    } else {
+        let mut canvas_builder = window.into_canvas();
+        if use_vsync {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let canvas = canvas_builder
+            .build()
+            .unwrap();
+        texture_creator = canvas.texture_creator();
+        let mut screen_renderer = TextureRenderer::new(&texture_creator, scale_mode);
+        screen_renderer.persistence.mode = persistence;
+        screen_renderer.persistence.background = rom.background();
+        screen_renderer.upscaler = upscaler;
+        Screen::Canvas(canvas, screen_renderer)
+    };
 
-    let mov_x = 100.0;
-    let mov_y = 100.0;
-    let mut prev_delta_time = 0.0;
-    let mut pos_x = 0.0;
-    let mut pos_y = 0.0;
+    let (out_w, out_h) = output_size(&screen);
+    let mut layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+    let mut event_pump = sdl.event_pump().unwrap();
+    let mut pacer = FramePacer::new(use_vsync);
+    let rom_dir = PathBuf::from(&config.browser.rom_dir);
+    let mut browser = if options.rom_path.is_empty() {
+        Some(RomBrowser::new(&rom_dir, recent.files()))
+    } else {
+        None
+    };
+    let mut osd = Osd::new();
+    let mut menu: Option<Menu> = None;
+    let mut show_help = false;
+    let mut controls: Option<ControlsScreen> = None;
+    // Emulation keeps running under it
+    let mut memory_view: Option<MemoryView> = None;
+    // Same, only one of the two is open at a time
+    let mut disassembly: Option<DisassemblyView> = None;
+    let mut audio_muted = false;
 
     'main: loop {
-        let frame_start_time = SystemTime::now();
+        // Set by the browser or a dropped file, loaded after the events
+        let mut rom_to_load: Option<String> = None;
+        // From hotkeys and menus, run after the events
+        let mut actions: Vec<Action> = Vec::new();
+        // Controller instance id, button and whether it went down, the stick counts as the D-pad
+        let mut pad_buttons: Vec<(i32, Button, bool)> = Vec::new();
+        let mut controls_actions: Vec<ControlsAction> = Vec::new();
+        // Mouse or finger id and where it is on the window, None when lifted
+        let mut pointer_moves: Vec<(i64, Option<(i32, i32)>)> = Vec::new();
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} => break 'main, 
-                Event::KeyDown { keycode: Some(keycode), .. } => {
-                    match keycode {
-                        Keycode::Num1 => println!("1"), 
-                        Keycode::Num2 => println!("2"), 
-                        Keycode::Num3 => println!("3"), 
-                        Keycode::Q => println!("Q"), 
-                        Keycode::W => println!("W"), 
-                        Keycode::E => println!("E"), 
-                        Keycode::A => println!("A"), 
-                        Keycode::S => println!("S"), 
-                        Keycode::D => println!("D"), 
-                        Keycode::Z => println!("Z"), 
-                        Keycode::X => println!("X"), 
-                        Keycode::C => println!("C"), 
-                        Keycode::F10 => is_debug_paused = false, 
-                        _ => {}
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    let (out_w, out_h) = output_size(&screen);
+                    layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+                }, 
+                Event::DropFile { filename, .. } => rom_to_load = Some(filename), 
+                Event::KeyDown { keycode: Some(Keycode::Return), keymod, .. } if keymod.intersects(keyboard::LALTMOD | keyboard::RALTMOD) => {
+                    actions.push(Action::ToggleFullscreen);
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } if controls.is_some() => {
+                    controls_actions.push(controls.as_mut().unwrap().on_key(keycode));
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } if browser.is_some() => {
+                    match browser.as_mut().unwrap().on_key(keycode) {
+                        BrowserAction::Load(path) => rom_to_load = Some(path.to_string_lossy().into_owned()), 
+                        // Nothing to go back to before the first ROM
+                        BrowserAction::Close if !rom.hash.is_empty() => browser = None, 
+                        _ => {}, 
                     }
-                }
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } if menu.is_some() => {
+                    let input = MenuInput::from_keycode(keycode);
+                    if let Some(action) = input.and_then(|input| menu.as_mut().unwrap().on_input(input)) {
+                        actions.push(action);
+                    }
+                }, 
+                Event::KeyDown { .. } if show_help => show_help = false, 
+                Event::KeyDown { keycode: Some(keycode), .. } if memory_view.is_some() => {
+                    match memory_view.as_mut().unwrap().on_key(keycode, emu.as_mut(), debugger.is_paused()) {
+                        Some(MemoryViewAction::None) => {}, 
+                        Some(MemoryViewAction::Close) => memory_view = None, 
+                        Some(MemoryViewAction::Message(text)) => osd.show(text), 
+                        // Pause, step and the other hotkeys still work
+                        None => actions.extend(hotkeys.action(keycode)), 
+                    }
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } if disassembly.is_some() => {
+                    match disassembly.as_mut().unwrap().on_key(keycode, emu.as_ref()) {
+                        Some(DisassemblyAction::None) => {}, 
+                        Some(DisassemblyAction::Close) => disassembly = None, 
+                        // Breakpoint, run to cursor and stepping act on the listing
+                        None => actions.extend(hotkeys.action(keycode)), 
+                    }
+                }, 
+                Event::TextInput { text, .. } if system.text_input && !is_modal(&controls, &browser, &menu, show_help, &memory_view, &disassembly) => {
+                    for byte in text.bytes() {
+                        emu.on_key_press(byte);
+                    }
+                }, 
+                Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } if system.text_input => emu.on_key_press(b'\r'), 
+                // Already sent as text
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if system.text_input && types_text(keycode, keymod) => {}, 
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } if keymap.contains_key(&keycode) => {
+                    emu.on_key_press(keymap[&keycode]);
+                }, 
+                Event::KeyUp { keycode: Some(keycode), .. } if keymap.contains_key(&keycode) => {
+                    emu.on_key_release(keymap[&keycode]);
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } if hotkeys.action(keycode) == Some(Action::FastForward) => {
+                    pacer.fast_forward = true;
+                }, 
+                Event::KeyUp { keycode: Some(keycode), .. } if hotkeys.action(keycode) == Some(Action::FastForward) => {
+                    pacer.fast_forward = false;
+                }, 
+                Event::KeyDown { keycode: Some(keycode), .. } => actions.extend(hotkeys.action(keycode)), 
+                Event::MouseButtonDown { which, mouse_btn: MouseButton::Left, x, y, .. } if which != keypad::TOUCH_MOUSE_ID => {
+                    pointer_moves.push((keypad::MOUSE_POINTER, Some((x, y))));
+                }, 
+                Event::MouseMotion { which, mousestate, x, y, .. } if which != keypad::TOUCH_MOUSE_ID && mousestate.left() => {
+                    pointer_moves.push((keypad::MOUSE_POINTER, Some((x, y))));
+                }, 
+                Event::MouseButtonUp { which, mouse_btn: MouseButton::Left, .. } if which != keypad::TOUCH_MOUSE_ID => {
+                    pointer_moves.push((keypad::MOUSE_POINTER, None));
+                }, 
+                // Finger positions are from 0.0 to 1.0 across the window
+                Event::FingerDown { finger_id, x, y, .. } | Event::FingerMotion { finger_id, x, y, .. } => {
+                    let (out_w, out_h) = output_size(&screen);
+                    pointer_moves.push((finger_id, Some(((x * out_w as f32) as i32, (y * out_h as f32) as i32))));
+                }, 
+                Event::FingerUp { finger_id, .. } => pointer_moves.push((finger_id, None)), 
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some((player, name)) = gamepads.as_mut().and_then(|pads| pads.open(which)) {
+                        osd.show(format!("Controller {} connected: {}", player, name));
+                    }
+                }, 
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(player) = gamepads.as_mut().and_then(|pads| pads.close(which)) {
+                        osd.show(format!("Controller {} disconnected", player));
+                    }
+                }, 
+                Event::ControllerButtonDown { which, button, .. } => pad_buttons.push((which, button, true)), 
+                Event::ControllerButtonUp { which, button, .. } => pad_buttons.push((which, button, false)), 
+                Event::ControllerAxisMotion { which, axis, value, .. } => {
+                    if let Some(pads) = &mut gamepads {
+                        pad_buttons.extend(pads.stick_buttons(which, axis, value).into_iter()
+                            .map(|(button, pressed)| (which, button, pressed)));
+                    }
+                }, 
                 _ => {}, 
             }
         }
+        if let (Some(pad), Some(area)) = (&mut keypad, layout.keypad) {
+            for (pointer, pos) in pointer_moves {
+                for (key, pressed) in pad.on_pointer(area, pointer, pos) {
+                    if pressed {
+                        emu.on_key_press(key);
+                    } else {
+                        emu.on_key_release(key);
+                    }
+                }
+            }
+        }
+        for (which, button, pressed) in pad_buttons {
+            let key = gamepads.as_ref().and_then(|pads| pads.key_for(which, button));
+            if !pressed {
+                if let Some(key) = key {
+                    emu.on_key_release(key);
+                }
+            } else if let Some(screen_ui) = &mut controls {
+                controls_actions.extend(gamepad::menu_input(button).map(|input| screen_ui.on_input(input)));
+            } else if let Some(browser_ui) = &mut browser {
+                match gamepad::menu_input(button).map(|input| browser_ui.on_input(input)) {
+                    Some(BrowserAction::Load(path)) => rom_to_load = Some(path.to_string_lossy().into_owned()), 
+                    Some(BrowserAction::Close) if !rom.hash.is_empty() => browser = None, 
+                    _ => {}, 
+                }
+            } else if let Some(current) = &mut menu {
+                actions.extend(gamepad::menu_input(button).and_then(|input| current.on_input(input)));
+            } else if show_help {
+                show_help = false;
+            } else if let Some(view) = &mut memory_view {
+                if let Some(MemoryViewAction::Close) = gamepad::menu_input(button).map(|input| view.on_input(input)) {
+                    memory_view = None;
+                }
+            } else if let Some(view) = &mut disassembly {
+                if let Some(DisassemblyAction::Close) = gamepad::menu_input(button).map(|input| view.on_input(input, emu.as_ref())) {
+                    disassembly = None;
+                }
+            } else if let Some(key) = key {
+                emu.on_key_press(key);
+            } else {
+                actions.extend(gamepad::button_action(button));
+            }
+        }
+
+        for change in controls_actions {
+            let screen_ui = match &controls {
+                Some(screen_ui) => screen_ui, 
+                None => break, 
+            };
+            match change {
+                ControlsAction::None => {}, 
+                ControlsAction::Close => {
+                    controls = None;
+                    menu = Some(settings_menu(&pacer, &screen, &rom, system, show_debug_pane, show_keypad, audio_muted));
+                }, 
+                ControlsAction::Changed => {
+                    hotkeys = screen_ui.hotkeys.clone();
+                    rom.settings.key_preset = screen_ui.key_preset.clone();
+                    rom.settings.keys = screen_ui.keys.clone();
+                    keymap = build_keymap(&rom.settings.keys);
+                    let rom_only = screen_ui.rom_only == Some(true);
+                    save_controls(&mut bindings, &mut config, &mut rom_store, system, &rom, &hotkeys, rom_only);
+                }, 
+            }
+        }
 
-        pos_x += (prev_delta_time * mov_x);
-        pos_y += (prev_delta_time * mov_y);
-        if pos_x > 800.0 {
-            pos_x = 0.0;
+        for action in actions {
+            // The menus and overlays are drawn with the bitmap font, so canvas only
+            let is_canvas = matches!(screen, Screen::Canvas(..));
+            match action {
+                Action::TogglePause => {
+                    if debugger.is_paused() {
+                        debugger.resume(emu.as_ref());
+                        osd.show(String::from("Running"));
+                    } else {
+                        debugger.pause();
+                        osd.show(String::from("Paused"));
+                    }
+                }, 
+                Action::Continue => {
+                    if debugger.is_paused() {
+                        debugger.resume(emu.as_ref());
+                        osd.show(String::from("Running"));
+                    }
+                }, 
+                Action::Step => debugger.step(emu.as_ref(), config.debugger.step_count.max(1)), 
+                Action::StepOver => debugger.step_over(emu.as_ref()), 
+                Action::StepOut => {
+                    if let Err(err) = debugger.step_out(emu.as_ref()) {
+                        osd.show(err);
+                    }
+                }, 
+                Action::RunToCursor => {
+                    let pc = emu.get_program_counter();
+                    match (disassembly.as_ref().and_then(|view| view.cursor()), emu.disassemble(pc)) {
+                        (Some(addr), _) => debugger.run_to(emu.as_ref(), addr), 
+                        (None, Some((_, len))) => debugger.run_to(emu.as_ref(), pc + len), 
+                        (None, None) => osd.show(String::from("No instruction at the PC")), 
+                    }
+                }, 
+                Action::ToggleBreakpoint => {
+                    let addr = disassembly.as_ref().and_then(|view| view.cursor())
+                        .unwrap_or_else(|| emu.get_program_counter());
+                    if debugger.toggle_breakpoint(addr) {
+                        osd.show(format!("Breakpoint set at 0x{:X}", addr));
+                    } else {
+                        osd.show(format!("Breakpoint cleared at 0x{:X}", addr));
+                    }
+                }, 
+                // Only while the key is held, see the key events
+                Action::FastForward => {}, 
+                Action::SpeedUp => osd.show(format!("Speed {}x", pacer.speed_up())), 
+                Action::SlowDown => osd.show(format!("Speed {}x", pacer.slow_down())), 
+                Action::ClockUp | Action::ClockDown => {
+                    let ticks = rom.ticks_per_frame(system);
+                    // Steps of about 10%, at least one tick
+                    let step = (ticks / 10).max(1);
+                    let ticks = if action == Action::ClockDown { ticks.saturating_sub(step).max(1) } else { ticks + step };
+                    rom.settings.ticks_per_frame = Some(ticks);
+                    rom.remember(&mut rom_store, |saved| saved.ticks_per_frame = Some(ticks));
+                    osd.show(format!("Clock {} ticks per frame", ticks));
+                }, 
+                Action::NextQuirks => {
+                    let presets = emu.get_quirk_presets();
+                    if presets.is_empty() {
+                        osd.show(String::from("No quirk presets for this system"));
+                    } else {
+                        let current = rom.settings.quirks.as_deref().unwrap_or(presets[0]);
+                        let i = presets.iter().position(|p| *p == current).map_or(0, |i| (i + 1) % presets.len());
+                        let preset = presets[i].to_string();
+                        if emu.set_quirks(&preset).is_ok() {
+                            osd.show(format!("Quirks {}", preset));
+                            rom.settings.quirks = Some(preset.clone());
+                            rom.remember(&mut rom_store, |saved| saved.quirks = Some(preset));
+                        }
+                    }
+                }, 
+                Action::NextPalette => {
+                    let presets = palette::preset_names();
+                    let i = presets.iter().position(|p| p.eq_ignore_ascii_case(&rom.palette_name())).map_or(0, |i| (i + 1) % presets.len());
+                    let preset = presets[i];
+                    emu.set_palette(palette::from_preset(preset).unwrap_or_default());
+                    rom.settings.palette = vec![preset.to_string()];
+                    rom.remember(&mut rom_store, |saved| saved.palette = vec![preset.to_string()]);
+                    if let Screen::Canvas(_, screen_renderer) = &mut screen {
+                        screen_renderer.persistence.background = rom.background();
+                    }
+                    osd.show(format!("Palette {}", preset));
+                }, 
+                Action::NextFilter => {
+                    if let Screen::Canvas(_, screen_renderer) = &mut screen {
+                        screen_renderer.upscaler = screen_renderer.upscaler.next();
+                        osd.show(format!("Filter {}", screen_renderer.upscaler.name()));
+                    }
+                }, 
+                Action::ToggleDebugPane => {
+                    if is_canvas {
+                        show_debug_pane = !show_debug_pane;
+                        let (out_w, out_h) = output_size(&screen);
+                        layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+                    }
+                }, 
+                Action::ToggleAudio => {
+                    audio_muted = !audio_muted;
+                    osd.show(String::from(if audio_muted { "Audio off" } else { "Audio on" }));
+                }, 
+                Action::ToggleFullscreen => {
+                    let window = match &mut screen {
+                        Screen::Canvas(canvas, _) => canvas.window_mut(), 
+                        Screen::Gl(window, ..) => window, 
+                    };
+                    if let Err(err) = fullscreen.toggle(window) {
+                        osd.show(format!("Fullscreen failed: {}", err));
+                    }
+                    // SDL also sends SizeChanged, but not always for exclusive mode switches
+                    let (out_w, out_h) = output_size(&screen);
+                    layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+                }, 
+                Action::ToggleKeypad => {
+                    if is_canvas {
+                        show_keypad = !show_keypad;
+                        if let Some(pad) = &mut keypad {
+                            for key in pad.release_all() {
+                                emu.on_key_release(key);
+                            }
+                        }
+                        keypad = new_keypad(system, show_keypad);
+                        let (out_w, out_h) = output_size(&screen);
+                        layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+                    }
+                }, 
+                Action::ToggleMemoryView => {
+                    if memory_view.is_some() {
+                        memory_view = None;
+                    } else if is_canvas {
+                        memory_view = MemoryView::new(emu.as_ref());
+                        if memory_view.is_none() {
+                            osd.show(String::from("This system has no memory viewer"));
+                        } else {
+                            disassembly = None;
+                        }
+                    }
+                }, 
+                Action::ToggleDisassembly => {
+                    if disassembly.is_some() {
+                        disassembly = None;
+                    } else if is_canvas {
+                        disassembly = DisassemblyView::new(emu.as_ref());
+                        if disassembly.is_none() {
+                            osd.show(String::from("This system has no disassembly"));
+                        } else {
+                            memory_view = None;
+                        }
+                    }
+                }, 
+                Action::Screenshot => {
+                    match save_screenshot(emu.as_ref(), &config.video.screenshot_dir, &rom) {
+                        Ok(path) => osd.show(format!("Saved {}", path)), 
+                        Err(err) => osd.show(err), 
+                    }
+                }, 
+                Action::OpenBrowser => {
+                    if is_canvas {
+                        menu = None;
+                        browser = Some(RomBrowser::new(&rom_dir, recent.files()));
+                    }
+                }, 
+                Action::SaveState | Action::LoadState if rom.hash.is_empty() => {}, 
+                Action::SaveState => {
+                    let result = match emu.save_state() {
+                        Some(data) => save_states::save(&rom.hash, rom.save_slot, &data), 
+                        None => Err(String::from("This system has no save states")), 
+                    };
+                    match result {
+                        Ok(()) => osd.show(format!("State {} saved", rom.save_slot)), 
+                        Err(err) => osd.show(err), 
+                    }
+                }, 
+                Action::LoadState => {
+                    let result = save_states::load(&rom.hash, rom.save_slot)
+                        .and_then(|data| emu.load_state(&data));
+                    match result {
+                        Ok(()) => osd.show(format!("State {} loaded", rom.save_slot)), 
+                        Err(err) => osd.show(err), 
+                    }
+                }, 
+                Action::NextSlot | Action::PrevSlot => {
+                    let step = if action == Action::NextSlot { 1 } else { save_states::SLOT_COUNT - 1 };
+                    let slot = (rom.save_slot + step) % save_states::SLOT_COUNT;
+                    rom.save_slot = slot;
+                    rom.remember(&mut rom_store, |saved| saved.save_slot = Some(slot));
+                    osd.show(format!("Slot {}", slot));
+                }, 
+                Action::Reset => {
+                    if !rom.hash.is_empty() {
+                        rom_to_load = Some(rom.path.clone());
+                    }
+                }, 
+                Action::OpenMenu => {
+                    if is_canvas {
+                        menu = Some(pause_menu(rom.save_slot));
+                    }
+                }, 
+                Action::OpenSettings => menu = Some(settings_menu(&pacer, &screen, &rom, system, show_debug_pane, show_keypad, audio_muted)), 
+                Action::OpenControls => {
+                    menu = None;
+                    // Keys for this ROM only if it already has its own
+                    let rom_only = (!rom.hash.is_empty())
+                        .then(|| rom_store.get(&rom.hash).is_some_and(|saved| !saved.keys.is_empty()));
+                    controls = Some(ControlsScreen::new(system, rom.settings.key_preset.clone(), rom.settings.keys.clone(), rom_only, hotkeys.clone()));
+                }, 
+                Action::ShowHelp => {
+                    menu = None;
+                    show_help = is_canvas;
+                }, 
+                Action::Resume => {
+                    menu = None;
+                    show_help = false;
+                }, 
+                Action::Quit => break 'main, 
+            }
         }
-        if pos_y > 600.0 {
-            pos_y = 0.0;
+        // Keep the values shown in the menus up to date
+        if let Some(current) = &menu {
+            let selected = current.selected();
+            menu = Some(match current.kind {
+                MenuKind::Pause => pause_menu(rom.save_slot), 
+                MenuKind::Settings => settings_menu(&pacer, &screen, &rom, system, show_debug_pane, show_keypad, audio_muted), 
+            }.with_selected(selected));
+        }
+        if let Some(message) = emu.take_message() {
+            osd.show(message);
         }
 
-        canvas.set_draw_color(Color::RGB(64, 64, 64));
-        canvas.clear();
-        canvas.set_draw_color(Color::RGB(255, 0, 0));
+        if let Some(path) = rom_to_load {
+            // Reloading keeps the core the ROM runs on, the ROM from the command line keeps its flags
+            let system_name = (path == rom.path).then_some(system.name);
+            let flags = if path == options.rom_path { rom_flags.clone() } else { RomSettings::default() };
+            match open_rom(&path, system_name, &config, &rom_store, &flags) {
+                Ok((new_system, new_emu, new_rom)) => {
+                    (system, emu, rom) = (new_system, new_emu, new_rom);
+                    if let Some(seed) = options.seed {
+                        emu.set_seed(seed);
+                    }
+                    add_recent(&mut recent, &path);
+                    keymap = build_keymap(&rom.settings.keys);
+                    keypad = new_keypad(system, show_keypad);
+                    if let Some(pads) = &mut gamepads {
+                        pads.set_mappings(&rom.settings.controllers);
+                    }
+                    if let Screen::Canvas(_, screen_renderer) = &mut screen {
+                        screen_renderer.persistence.clear();
+                        screen_renderer.persistence.background = rom.background();
+                    }
+                    let (out_w, out_h) = output_size(&screen);
+                    layout = Layout::new(emu.as_ref(), out_w, out_h, show_debug_pane, keypad.is_some());
+                    browser = None;
+                    menu = None;
+                    memory_view = None;
+                    disassembly = None;
+                    osd.show(format!("Loaded {}", rom.file_name));
+                }, 
+                Err(err) => osd.show(err.message().to_string()), 
+            }
+        }
 
-        if !is_debug_paused {
-            update_emulator(&mut c8emu);
+        // While paused the debugger only lets the requested steps through
+        let frames_due = pacer.frames_due();
+        let is_ui_open = browser.is_some() || menu.is_some() || show_help || controls.is_some();
+        if !is_ui_open {
+            for _ in 0..frames_due {
+                match debugger.run(emu.as_mut(), rom.ticks_per_frame(system)) {
+                    // A whole frame ran, steps while paused leave the timers alone
+                    None if !debugger.is_paused() => emu.on_frame(), 
+                    None => {}, 
+                    Some(PauseReason::Step) => break, 
+                    Some(reason) => {
+                        osd.show(reason.to_string());
+                        break;
+                    }, 
+                }
+            }
+        }
+        if let Some(pad) = &mut keypad {
+            pad.update_polled(emu.take_polled_keys());
         }
-        is_debug_paused = is_debug_mode;
-        draw_emulator_screen(&mut canvas, &mut c8emu);
-        draw_cpu_info(&mut canvas, &mut c8emu);
-
-        canvas.present();
-        match frame_start_time.elapsed() {
-            Ok(elapsed) => {
-                prev_delta_time = elapsed.as_secs_f32();
+        if let Some(beeper) = &mut beeper {
+            beeper.set_playing(emu.is_sound_playing() && !debugger.is_paused() && !is_ui_open && !audio_muted);
+        }
+
+        match &mut screen {
+            Screen::Canvas(canvas, screen_renderer) => {
+                canvas.set_draw_color(debug_colors.window_background);
+                canvas.clear();
+
+                if let Err(err) = screen_renderer.draw(canvas, emu.as_ref(), layout.screen) {
+                    log_error!("[Render Error]: {}", err);
+                }
+                if let Some(pane) = layout.debug_pane {
+                    draw_cpu_info(canvas, emu.as_mut(), &debugger, pane, &debug_colors);
+                }
+                if let (Some(pad), Some(area)) = (&keypad, layout.keypad) {
+                    pad.draw(canvas, area, &overlay_colors);
+                }
+                if let Some(view) = &mut memory_view {
+                    view.draw(canvas, layout.screen, emu.as_ref(), debugger.is_paused(), &overlay_colors, &memory_colors);
+                }
+                if let Some(view) = &mut disassembly {
+                    view.draw(canvas, layout.screen, emu.as_ref(), &debugger, &overlay_colors, &debug_colors.current_instr);
+                }
+                osd.draw(canvas, layout.screen, &overlay_colors);
+                if let Some(menu) = &menu {
+                    menu.draw(canvas, layout.screen, &overlay_colors);
+                }
+                if show_help {
+                    osd::draw_panel(canvas, layout.screen, "Hotkeys, any key closes", &hotkeys.help_lines(), None, &overlay_colors);
+                }
+                if let Some(controls) = &mut controls {
+                    controls.draw(canvas, layout.screen, &overlay_colors);
+                }
+                if let Some(browser) = &mut browser {
+                    browser.draw(canvas, layout.screen, &overlay_colors);
+                }
+
+                canvas.present();
             }, 
-            Err(_) => {
+            Screen::Gl(window, _gl_context, gl_renderer) => {
+                let (_, out_h) = window.drawable_size();
+                unsafe {
+                    gl::Viewport(0, 0, layout.screen.width() as i32, out_h as i32);
+                    let bg = debug_colors.window_background;
+                    gl::ClearColor(bg.r as f32 / 255.0, bg.g as f32 / 255.0, bg.b as f32 / 255.0, 1.0);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                }
+                let dst = fit_rect(&emu.get_screen_resolution(), layout.screen, scale_mode);
+                if let Err(err) = gl_renderer.draw(emu.as_ref(), dst, out_h) {
+                    log_error!("[Render Error]: {}", err);
+                }
+
+                window.gl_swap_window();
             }, 
         }
+        pacer.wait_for_next_frame();
     }
-}
-
-fn update_emulator(emu: &mut dyn EmuTrait)
-{
-    emu.tick();
+    Ok(())
 }
 
 fn draw_cpu_info(
     canvas: &mut WindowCanvas, 
-    emu: &mut dyn CpuInfo
+    emu: &mut dyn Emulator, 
+    debugger: &Debugger, 
+    pane: Rect, 
+    colors: &DebugColors, 
 ) {
+    canvas.set_draw_color(colors.pane_background);
+    let _ = canvas.fill_rect(pane);
+    // Long lines are cut at the pane edge instead of running over the screen
+    canvas.set_clip_rect(pane);
+
     let reg_data = emu.get_data_registers();
     let mut i = 0;
-    let x_offset = 4;
-    let y_offset = 128;
-    let fore_color = Color::RGB(255, 255, 0);
-    let back_color = Color::RGB(2, 2, 2);
+    let x_offset = pane.x() + layout::PANE_MARGIN as i32;
+    let y_offset = pane.y() + layout::PANE_MARGIN as i32;
+    let fore_color = colors.register;
+    let back_color = colors.pane_background;
     let char_h_spacing = 2;
     let char_v_spacing = 2;
     for r_data in reg_data {
@@ -165,6 +874,7 @@ fn draw_cpu_info(
         }
         i += 1;
     }
+    let fore_color = colors.current_instr;
     let op_str = emu.get_current_instr();
     let mut x_iter = 0;
     for c in op_str.chars() {
@@ -175,8 +885,7 @@ fn draw_cpu_info(
         x_iter += 1;
     }
     i += 1;
-    let fore_color = Color::RGB(0, 255, 0);
-    let back_color = Color::RGB(2, 2, 2);
+    let fore_color = colors.next_instr;
     let op_str = emu.get_next_instr();
     let mut x_iter = 0;
     for c in op_str.chars() {
@@ -186,118 +895,402 @@ fn draw_cpu_info(
             c as i32, &fore_color, &back_color);
         x_iter += 1;
     }
+    i += 1;
+    // Why the debugger stopped, or that it is running, wrapped at the pane edge
+    let fore_color = colors.current_instr;
+    let mut status = match debugger.pause_reason() {
+        Some(reason) => reason.to_string(), 
+        None => String::from("Running"), 
+    };
+    if let Some(depth) = emu.get_call_depth() {
+        status.push_str(&format!(" depth {}", depth));
+    }
+    let line_chars = ((pane.width() as i32 - 2 * layout::PANE_MARGIN as i32) / (8 + char_h_spacing)).max(1) as usize;
+    for line in wrap_words(&status, line_chars) {
+        let mut x_iter = 0;
+        for c in line.chars() {
+            p_bitmap_font::draw_letter(canvas, 
+                x_offset + x_iter * (8 + char_h_spacing), 
+                y_offset + i * (8 + char_v_spacing), 
+                c as i32, &fore_color, &back_color);
+            x_iter += 1;
+        }
+        i += 1;
+    }
+    canvas.set_clip_rect(None);
 }
 
-fn get_reg_value_hex(
-    r_data: &RegisterInfo, 
-) -> String {
-    match r_data.reg_size_bits {
-        RegisterSize::RegSize8 => format!("{:2x}", r_data.reg_value), 
-        RegisterSize::RegSize16 => format!("{:4x}", r_data.reg_value), 
-        RegisterSize::RegSize32 => format!("{:8x}", r_data.reg_value), 
-        RegisterSize::RegSize64 => format!("{:16x}", r_data.reg_value), 
+/**
+ * Splits `text` at spaces into lines of at most `width` characters, longer
+ * words get a line of their own.
+ */
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }, 
+            _ => lines.push(word.to_string()), 
+        }
     }
+    lines
 }
 
-fn draw_emulator_screen(
-    canvas: &mut WindowCanvas, 
-    emu: &mut dyn EmuTrait
-) {
-    let scale: u32 = 8; 
+/**
+ * The loaded ROM and the settings in effect for it.
+ */
+struct LoadedRom {
+    hash: String, 
+    path: String, 
+    file_name: String, 
+    settings: SystemConfig, 
+    save_slot: u8, 
+}
 
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    let res = emu.get_screen_resolution();
-    let w = res.width * scale;
-    let h = res.height * scale;
-    let screen_res = ScreenResolution { width: w, height: h};
-    let mut draw_buf = vec![0; (w * h * 4) as usize];
-    let pos_x = (800 - w) as i32 / 2;
-    let pos_y = (600 - h) as i32 / 8;
-
-    let draw_buf_result = emu.draw_to_buffer_rgba(&mut draw_buf, &screen_res);
-    match draw_buf_result {
-        Ok(_) => {
-            for x in 0..w {
-                for y in 0..h {
-                    let byte_offset = (y * w * 4 + x * 4) as usize;
-                    canvas.set_draw_color(Color::RGB(draw_buf[byte_offset], 
-                        draw_buf[byte_offset + 1], draw_buf[byte_offset + 2]));
-
-                    let _ = canvas.draw_point(Point::new(x as i32 + pos_x as i32, 
-                        y as i32 + pos_y as i32));
-                }
-            }
-        }, 
-        Err(_) => {
+impl LoadedRom {
+    /**
+     * Stands in until a ROM is picked in the browser.
+     */
+    fn empty(settings: SystemConfig) -> LoadedRom {
+        LoadedRom {
+            hash: String::new(), 
+            path: String::new(), 
+            file_name: String::new(), 
+            settings, 
+            save_slot: 0, 
+        }
+    }
+    fn ticks_per_frame(self: &Self, system: &SystemInfo) -> u32 {
+        self.settings.ticks_per_frame.unwrap_or(system.ticks_per_frame)
+    }
 
+    /**
+     * The preset in use, or "custom".
+     */
+    fn palette_name(self: &Self) -> String {
+        match self.settings.palette.as_slice() {
+            [] => String::from(palette::PALETTE_PRESETS[0].0), 
+            [name] if palette::from_preset(name).is_some() => name.to_lowercase(), 
+            _ => String::from("custom"), 
         }
     }
 
-    // let scr_rect = Rect::new((800 - w) as i32 / 2, (600 - h) as i32 / 8, w, h);
-    // let _ = canvas.fill_rect(scr_rect);
+    /**
+     * First palette entry, what the persistence filter fades to.
+     */
+    fn background(self: &Self) -> [u8; 3] {
+        let colors = palette::parse_palette(&self.settings.palette).unwrap_or_default();
+        let [r, g, b, _] = colors.first().copied().unwrap_or(palette::DEFAULT_COLORS[0]);
+        [r, g, b]
+    }
+
+    /**
+     * Saves a change made while the ROM is running to the per-ROM store.
+     */
+    fn remember<F: FnOnce(&mut RomSettings)>(self: &Self, rom_store: &mut RomStore, change: F) {
+        if self.hash.is_empty() {
+            return;
+        }
+        let result = rom_store.update(&self.hash, |saved| {
+            saved.name = self.file_name.clone();
+            change(saved);
+        });
+        if let Err(err) = result {
+            log_warn!("[Config] Could not save the ROM settings: {}", err);
+        }
+    }
 }
 
-fn _print_sdl_debug_info(_sdl: &Sdl, video_subsystem: &VideoSubsystem) {
-    match video_subsystem.num_video_displays() {
-        Ok(n_disp) => { 
-            println!("[Displays]: {}", n_disp);
-            for i in 0..n_disp {
-                match video_subsystem.display_name(i) {
-                    Ok(disp_name) => {
-                        println!("\t[Display]: {}", disp_name);
-                        let n_modes = video_subsystem.num_display_modes(i).unwrap();
-                        for j in 0..n_modes {
-                            match video_subsystem.display_mode(i, j) {
-                                Ok(mode) => {
-                                    println!("\t\t[DisplayMode]: {:?}", mode);
-                                }, 
-                                Err(err) => println!("[DisplayMode Error]: {}", err), 
-                            }
-                        }
-                    }, 
-                    Err(err) => println!("\t[Display Error]: {}", err), 
-                }
-            }
-        }, 
-        Err(_) => println!("WTF Displays!"), 
+/**
+ * Loads a ROM through `load_data` and applies its settings: the config
+ * file's system section, then what was remembered for this ROM, then `rom_flags`.
+ */
+fn load_rom(
+    emu: &mut dyn Emulator, 
+    file_path: &str, 
+    system: &SystemInfo, 
+    config: &Config, 
+    rom_store: &RomStore, 
+    rom_flags: &RomSettings, 
+) -> Result<LoadedRom, CliError> {
+    let data = fs::read(file_path).map_err(|e| CliError::Io(format!("Could not read {}: {}", file_path, e)))?;
+    let hash = rom_settings::sha1_hex(&data);
+
+    let mut settings = config.system(system);
+    let mut save_slot = 0;
+    if let Some(saved) = rom_store.get(&hash) {
+        log_info!("[Config] Using the saved settings for {}", hash);
+        saved.apply_to(&mut settings);
+        save_slot = saved.save_slot.unwrap_or(0) % save_states::SLOT_COUNT;
+    }
+    rom_flags.apply_to(&mut settings);
+
+    log_info!("[Loading] {}...", file_path);
+    emu.load_data(&data).map_err(|e| CliError::Io(format!("Could not load {}: {}", file_path, e)))?;
+    if let Some(preset) = &settings.quirks {
+        emu.set_quirks(preset).map_err(CliError::Usage)?;
+    }
+    if !settings.palette.is_empty() {
+        emu.set_palette(&palette::parse_palette(&settings.palette).map_err(CliError::Usage)?);
+    }
+
+    let file_name = PathBuf::from(file_path).file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(LoadedRom { hash, path: file_path.to_string(), file_name, settings, save_slot })
+}
+
+/**
+ * Creates a core for `path` (picked by `system_name` or the extension) and
+ * loads the ROM into it.
+ */
+fn open_rom(
+    path: &str, 
+    system_name: Option<&str>, 
+    config: &Config, 
+    rom_store: &RomStore, 
+    rom_flags: &RomSettings, 
+) -> Result<(&'static SystemInfo, Box<dyn Emulator>, LoadedRom), CliError> {
+    let system = cli::select_system(system_name, path)?;
+    log_info!("[System] {}", system.description);
+    let mut emu = (system.create)();
+    let rom = load_rom(emu.as_mut(), path, system, config, rom_store, rom_flags)?;
+    Ok((system, emu, rom))
+}
+
+fn add_recent(recent: &mut RecentFiles, path: &str) {
+    if let Err(err) = recent.add(&PathBuf::from(path)) {
+        log_warn!("[Config] Could not save the recent files: {}", err);
+    }
+}
+
+fn pause_menu(save_slot: u8) -> Menu {
+    let items = vec![
+        MenuItem::new("Resume", Action::Resume), 
+        MenuItem::new("Reset", Action::Reset), 
+        MenuItem::new("Load ROM", Action::OpenBrowser), 
+        MenuItem::new("Save state", Action::SaveState), 
+        MenuItem::new("Load state", Action::LoadState), 
+        MenuItem::value("Slot", &save_slot.to_string(), Action::PrevSlot, Action::NextSlot), 
+        MenuItem::new("Settings", Action::OpenSettings), 
+        MenuItem::new("Hotkeys", Action::ShowHelp), 
+        MenuItem::new("Quit", Action::Quit), 
+    ];
+    Menu::new(MenuKind::Pause, "Paused", items, Action::Resume)
+}
+
+fn settings_menu(pacer: &FramePacer, screen: &Screen, rom: &LoadedRom, system: &SystemInfo, show_debug_pane: bool, show_keypad: bool, audio_muted: bool) -> Menu {
+    let filter = match screen {
+        Screen::Canvas(_, screen_renderer) => screen_renderer.upscaler.name(), 
+        Screen::Gl(..) => "shaders", 
     };
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let items = vec![
+        MenuItem::value("Speed", &format!("{}x", pacer.speed()), Action::SlowDown, Action::SpeedUp), 
+        MenuItem::value("Clock", &rom.ticks_per_frame(system).to_string(), Action::ClockDown, Action::ClockUp), 
+        MenuItem::value("Quirks", rom.settings.quirks.as_deref().unwrap_or("default"), Action::NextQuirks, Action::NextQuirks), 
+        MenuItem::value("Palette", &rom.palette_name(), Action::NextPalette, Action::NextPalette), 
+        MenuItem::value("Filter", filter, Action::NextFilter, Action::NextFilter), 
+        MenuItem::value("Debugger", on_off(show_debug_pane), Action::ToggleDebugPane, Action::ToggleDebugPane), 
+        MenuItem::value("Keypad", on_off(show_keypad), Action::ToggleKeypad, Action::ToggleKeypad), 
+        MenuItem::value("Audio", on_off(!audio_muted), Action::ToggleAudio, Action::ToggleAudio), 
+        MenuItem::new("Controls", Action::OpenControls), 
+        MenuItem::new("Back", Action::OpenMenu), 
+    ];
+    Menu::new(MenuKind::Settings, "Settings", items, Action::OpenMenu)
 }
 
-fn _try_gl_loop(sdl: &Sdl, video_subsystem: &VideoSubsystem) {
-    let gl_attr = video_subsystem.gl_attr();
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-    gl_attr.set_context_version(3, 3);
+struct DebugColors {
+    window_background: Color, 
+    pane_background: Color, 
+    register: Color, 
+    current_instr: Color, 
+    next_instr: Color, 
+}
 
-    let window = video_subsystem
-        .window("RUST SDL OpenGL 00", 800, 600)
-        .opengl()
-        .build()
-        .unwrap();
-    println!("[Wnd]: {:?}", window.window_pixel_format());
+/**
+ * The config file named on the command line, or the default one.
+ */
+fn load_config(cli: &Cli) -> Config {
+    match Config::load(cli.config_path.as_deref()) {
+        Ok(config) => config, 
+        Err(err) => {
+            log_error!("[Config] {}", err);
+            process::exit(cli::EXIT_USAGE);
+        }, 
+    }
+}
 
-    let _gl_context = window.gl_create_context().unwrap();
-    let _gl = gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
-    
-    debug_assert_eq!(gl_attr.context_profile(), sdl2::video::GLProfile::Core);
-    debug_assert_eq!(gl_attr.context_version(), (3, 3));
+fn load_rom_store() -> RomStore {
+    match RomStore::load(RomStore::default_path()) {
+        Ok(rom_store) => rom_store, 
+        Err(err) => {
+            log_warn!("[Config] Ignoring the per-ROM settings: {}", err);
+            RomStore::load(None).unwrap()
+        }, 
+    }
+}
+
+/**
+ * The on-screen keypad for `system`, if it is shown and the core has one.
+ */
+fn new_keypad(system: &SystemInfo, show: bool) -> Option<Keypad> {
+    (show && !system.keypad.is_empty()).then(|| Keypad::new(system.keypad))
+}
 
-    unsafe {
-        gl::Viewport(0, 0, 800, 600);
-        gl::ClearColor(0.0, 1.0, 0.0, 0.0);
+fn load_bindings() -> Bindings {
+    match Bindings::load(Bindings::default_path()) {
+        Ok(bindings) => bindings, 
+        Err(err) => {
+            log_warn!("[Config] Ignoring the saved controls: {}", err);
+            Bindings::load(None).unwrap()
+        }, 
     }
-    let mut event_pump = sdl.event_pump().unwrap();
-    'main: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                sdl2::event::Event::Quit {..} => break 'main, 
-                _ => {}, 
-            }
+}
 
-            unsafe {
-                gl::Clear(gl::COLOR_BUFFER_BIT);
-            }
+/**
+ * Saves what the controls screen changed. Hotkeys are for every system, the
+ * keys either for this ROM alone (`rom_only`) or for every ROM of `system`,
+ * which drops the keys this ROM had of its own.
+ */
+fn save_controls(
+    bindings: &mut Bindings, 
+    config: &mut Config, 
+    rom_store: &mut RomStore, 
+    system: &SystemInfo, 
+    rom: &LoadedRom, 
+    hotkeys: &Hotkeys, 
+    rom_only: bool, 
+) {
+    let mut saved = bindings.set_hotkeys(hotkeys.overrides());
+    if rom_only {
+        rom.remember(rom_store, |saved| {
+            saved.key_preset = rom.settings.key_preset.clone();
+            saved.keys = rom.settings.keys.clone();
+        });
+    } else {
+        let system_bindings = SystemBindings {
+            key_preset: rom.settings.key_preset.clone(), 
+            keys: rom.settings.keys.clone(), 
+        };
+        saved = saved.and_then(|_| bindings.set_system(system.name, system_bindings));
+        if rom_store.get(&rom.hash).is_some_and(|saved| !saved.keys.is_empty()) {
+            rom.remember(rom_store, |saved| {
+                saved.key_preset = None;
+                saved.keys.clear();
+            });
+        }
+    }
+    if let Err(err) = saved {
+        log_warn!("[Config] Could not save the controls: {}", err);
+    }
+    bindings.apply_to(config);
+}
 
-            window.gl_swap_window();
+/**
+ * Writes the screen at its native resolution to `<dir>/<ROM name>-<n>.png`
+ * and returns the path.
+ */
+fn save_screenshot(emu: &dyn Emulator, dir: &str, rom: &LoadedRom) -> Result<String, String> {
+    let res = emu.get_screen_resolution();
+    let mut buf = vec![0; (res.width * res.height * 4) as usize];
+    let _ = emu.draw_to_buffer_rgba(&mut buf, &res);
+
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    let stem = PathBuf::from(&rom.file_name).file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("screenshot"));
+    let path = (1..).map(|n| dir.join(format!("{}-{}.png", stem, n)))
+        .find(|path| !path.exists())
+        .unwrap();
+    let path = path.to_string_lossy().into_owned();
+    headless::write_png(&path, &buf, &res).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    Ok(path)
+}
+
+fn parse_sdl_color(value: &str) -> Result<Color, CliError> {
+    let [r, g, b, a] = config::parse_color(value).map_err(CliError::Usage)?;
+    Ok(Color::RGBA(r, g, b, a))
+}
+
+/**
+ * Whether a screen or overlay that takes the keyboard is open.
+ */
+fn is_modal(
+    controls: &Option<ControlsScreen>, 
+    browser: &Option<RomBrowser>, 
+    menu: &Option<Menu>, 
+    show_help: bool, 
+    memory_view: &Option<MemoryView>, 
+    disassembly: &Option<DisassemblyView>, 
+) -> bool {
+    controls.is_some() || browser.is_some() || menu.is_some() || show_help || memory_view.is_some() || disassembly.is_some()
+}
+
+/**
+ * Whether the key types a character, which SDL also sends as `TextInput`.
+ */
+fn types_text(keycode: Keycode, keymod: keyboard::Mod) -> bool {
+    let shortcut = keyboard::LCTRLMOD | keyboard::RCTRLMOD | keyboard::LALTMOD | keyboard::RALTMOD
+        | keyboard::LGUIMOD | keyboard::RGUIMOD;
+    !keymod.intersects(shortcut) && (keycode == Keycode::Space || keycode.name().chars().count() == 1)
+}
+
+/**
+ * SDL key names from the config to core keys, unknown names are skipped.
+ */
+fn build_keymap(keys: &BTreeMap<String, u8>) -> HashMap<Keycode, u8> {
+    let mut keymap = HashMap::new();
+    for (name, key) in keys {
+        match Keycode::from_name(name) {
+            Some(keycode) => {
+                keymap.insert(keycode, *key);
+            }, 
+            None => log_warn!("[Config] Unknown key name '{}'", name), 
         }
     }
-}
\ No newline at end of file
+    keymap
+}
+
+fn get_reg_value_hex(
+    r_data: &RegisterInfo, 
+) -> String {
+    match r_data.reg_size_bits {
+        RegisterSize::RegSize8 => format!("{:2x}", r_data.reg_value), 
+        RegisterSize::RegSize16 => format!("{:4x}", r_data.reg_value), 
+        RegisterSize::RegSize32 => format!("{:8x}", r_data.reg_value), 
+        RegisterSize::RegSize64 => format!("{:16x}", r_data.reg_value), 
+    }
+}
+
+fn output_size(screen: &Screen) -> (u32, u32) {
+    match screen {
+        Screen::Canvas(canvas, _) => canvas.output_size().unwrap_or((1, 1)), 
+        Screen::Gl(window, _, _) => window.drawable_size(), 
+    }
+}
+
+fn create_gl_screen<'a>(
+    video_subsystem: &VideoSubsystem, 
+    window: Window, 
+    shader_paths: &[PathBuf], 
+    use_vsync: bool, 
+) -> Result<Screen<'a>, String> {
+    let gl_context = window.gl_create_context()?;
+    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    let swap_interval = if use_vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+    if !video_subsystem.gl_set_swap_interval(swap_interval) {
+        log_warn!("[GL] Could not set the swap interval: {}", sdl2::get_error());
+    }
+
+    let gl_attr = video_subsystem.gl_attr();
+    debug_assert_eq!(gl_attr.context_profile(), GLProfile::Core);
+    debug_assert_eq!(gl_attr.context_version(), (3, 3));
+
+    let gl_renderer = GlRenderer::new(shader_paths)?;
+    log_info!("[GL] Shader passes: {:?}", gl_renderer.pass_names());
+    Ok(Screen::Gl(window, gl_context, gl_renderer))
+}
@@ -1,12 +1,12 @@
 use sdl2::{ 
     // keyboard::Keycode, 
     render::WindowCanvas, 
-    rect::{ Point }, 
+    rect::{ Point, Rect }, 
     pixels::Color, 
 };
 
 
-const FONT_DATA: [[[u8; 8]; 8]; 37] = [
+const FONT_DATA: [[[u8; 8]; 8]; 54] = [
     // A
     [
         [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
@@ -403,6 +403,193 @@ const FONT_DATA: [[[u8; 8]; 8]; 37] = [
         [0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00], 
         [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
     ], 
+    // Full stop
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+    ], 
+    // Comma
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Minus
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Underscore
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+    ], 
+    // Colon
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Slash
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Greater than
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Less than
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Question mark
+    [
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+    ], 
+    // Exclamation mark
+    [
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+    ], 
+    // Left parenthesis
+    [
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+    ], 
+    // Right parenthesis
+    [
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Left bracket
+    [
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+    ], 
+    // Right bracket
+    [
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+    ], 
+    // Plus
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Equals
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
+    // Asterisk
+    [
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00], 
+        [0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+        [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
+    ], 
     // Space
     [
         [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 
@@ -416,8 +603,14 @@ const FONT_DATA: [[[u8; 8]; 8]; 37] = [
     ], 
 ];
 
+// Glyphs after the digits, in FONT_DATA order
+const PUNCTUATION: &str = ".,-_:/><?!()[]+=*";
 
-pub fn draw_letter(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, keycode: i32, fore_color: &Color, back_color: &Color) {
+// Size of a glyph in pixels
+pub const CHAR_WIDTH: i32 = 8;
+pub const CHAR_HEIGHT: i32 = 8;
+
+fn glyph_index(keycode: i32) -> usize {
     let mut idx: usize = FONT_DATA.len() - 1;
     if keycode > 96 && keycode < 123 {
         idx = (keycode - 97) as usize;
@@ -425,12 +618,19 @@ pub fn draw_letter(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, keycode: i
         idx = (keycode - 65) as usize;
     } else if keycode > 47 && keycode < 58 {
         idx = (keycode - 48 + 26) as usize;
+    } else if let Some(i) = char::from_u32(keycode as u32).and_then(|c| PUNCTUATION.find(c)) {
+        idx = 36 + i;
     }
+    idx
+}
+
+pub fn draw_letter(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, keycode: i32, fore_color: &Color, back_color: &Color) {
+    let idx = glyph_index(keycode);
 
     for x in 0..8 {
         for y in 0..8 {
 
-            if FONT_DATA[idx][y][x] <= 0 {
+            if FONT_DATA[idx][y][x] == 0 {
                 canvas.set_draw_color(*back_color);
             } else {
                 canvas.set_draw_color(*fore_color);
@@ -440,3 +640,32 @@ pub fn draw_letter(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, keycode: i
         }
     }
 }
+
+/**
+ * Draws `text` on one line, `h_spacing` pixels between glyphs. Returns the x
+ * position after the last glyph.
+ */
+pub fn draw_text(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, text: &str, h_spacing: i32, fore_color: &Color, back_color: &Color) -> i32 {
+    let mut x = pos_x;
+    for c in text.chars() {
+        draw_letter(canvas, x, pos_y, c as i32, fore_color, back_color);
+        x += CHAR_WIDTH + h_spacing;
+    }
+    x
+}
+
+/**
+ * `c` with every font pixel drawn as a `scale` x `scale` square, leaving
+ * the background as it is.
+ */
+pub fn draw_letter_scaled(canvas: &mut WindowCanvas, pos_x: i32, pos_y: i32, c: char, scale: u32, fore_color: &Color) {
+    let glyph = &FONT_DATA[glyph_index(c as i32)];
+    canvas.set_draw_color(*fore_color);
+    for (y, row) in glyph.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if *pixel != 0 {
+                let _ = canvas.fill_rect(Rect::new(pos_x + x as i32 * scale as i32, pos_y + y as i32 * scale as i32, scale, scale));
+            }
+        }
+    }
+}
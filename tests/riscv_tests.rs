@@ -0,0 +1,60 @@
+//! Runs the official riscv-tests `rv32ui-p-*` and `rv32um-p-*` ELF files against `RiscvEmu`.
+//!
+//! The binaries are not part of this repository. Build them from
+//! https://github.com/riscv-software-src/riscv-tests and point `RISCV_TESTS_DIR`
+//! at the `isa` output directory (defaults to `tests/riscv-tests/isa`).
+//! The suites are ignored by default, run them with
+//! `cargo test --test riscv_tests -- --ignored`.
+
+use std::{ env, fs, path::PathBuf };
+
+use ru_emu_lib::emulators::{ riscv::RiscvEmu, EmuTrait };
+
+const MAX_STEPS: u32 = 10_000_000;
+
+fn run_suite(prefix: &str) {
+    let dir = env::var("RISCV_TESTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/riscv-tests/isa"));
+    let entries = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("{}: {}, set RISCV_TESTS_DIR to run the {}* tests", dir.display(), e, prefix));
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with(prefix) && p.extension().is_none()
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No {}* tests in {}", prefix, dir.display());
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let mut emu = RiscvEmu::new();
+        emu.load_data(&fs::read(path).unwrap()).unwrap();
+        let mut steps = 0;
+        while emu.get_exit_code().is_none() && steps < MAX_STEPS {
+            emu.tick();
+            steps += 1;
+        }
+        match emu.get_exit_code() {
+            Some(0) => {},
+            Some(code) => failures.push(format!("{}: failed test #{}", path.display(), code)),
+            None => failures.push(format!("{}: timed out after {} steps", path.display(), steps)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the riscv-tests binaries, run with --ignored"]
+fn rv32ui() {
+    run_suite("rv32ui-p-");
+}
+
+#[test]
+#[ignore = "needs the riscv-tests binaries, run with --ignored"]
+fn rv32um() {
+    run_suite("rv32um-p-");
+}